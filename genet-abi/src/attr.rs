@@ -386,7 +386,7 @@ extern "C" fn abi_get(
             _ => ValueType::Nil,
         },
         Err(e) => {
-            unsafe { *err = Error::new(::std::error::Error::description(&*e)) }
+            unsafe { *err = Error::new(&e.to_string()) }
             ValueType::Error
         }
     }
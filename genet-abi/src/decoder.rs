@@ -1,11 +1,14 @@
 use bincode;
 use context::Context;
-use error::Error;
+use error::{Error, Panic};
 use fixed::MutFixed;
 use layer::{Layer, LayerStack, Parent};
 use result::Result;
 use serde::ser::{Serialize, Serializer};
-use std::ptr;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
 use vec::SafeVec;
 
 /// Execution type.
@@ -13,6 +16,11 @@ use vec::SafeVec;
 pub enum ExecType {
     ParallelSync,
     SerialSync,
+    /// Runs after frames are stored, without blocking the frame list.
+    ///
+    /// Like serial decoders, async decoders decode every layer of a frame once, including
+    /// the layers which earlier stages have decoded.
+    Async,
 }
 
 /// Decoding status.
@@ -23,12 +31,19 @@ pub enum Status {
 }
 
 /// Decoder metadata.
+///
+/// The fields after `exec_type` are sent to the kernel as an optional extension,
+/// so that the metadata of plugins which lack them can still be read.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Metadata {
     pub id: String,
     pub name: String,
     pub description: String,
     pub exec_type: ExecType,
+    /// Decoders with higher priority run first.
+    pub priority: i32,
+    /// Ids of the decoders which must run before this decoder.
+    pub runs_after: Vec<String>,
 }
 
 impl Default for Metadata {
@@ -38,6 +53,8 @@ impl Default for Metadata {
             name: String::new(),
             description: String::new(),
             exec_type: ExecType::ParallelSync,
+            priority: 0,
+            runs_after: Vec::new(),
         }
     }
 }
@@ -78,6 +95,7 @@ impl WorkerBox {
         let mut error = Error::new("");
         let result = (self.decode)(self, ctx, stack, layers.len() as u64, layer, &mut error);
         match result {
+            3 => Err(Box::new(Panic::new(&error))),
             2 => Ok(true),
             1 => Ok(false),
             _ => Err(Box::new(error)),
@@ -97,17 +115,24 @@ extern "C" fn abi_decode(
     let ctx = unsafe { &mut (*ctx) };
     let mut layer = unsafe { &mut *layer };
     let stack = unsafe { LayerStack::new(layers, len as usize) };
-    match worker.decode(ctx, &stack, &mut layer) {
-        Ok(stat) => match stat {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| worker.decode(ctx, &stack, &mut layer)));
+    match result {
+        Ok(Ok(stat)) => match stat {
             Status::Done => 2,
             Status::Skip => 1,
         },
-        Err(err) => {
+        Ok(Err(err)) => {
             unsafe {
-                ptr::write(error, Error::new(err.description()));
+                ptr::write(error, Error::new(&err.to_string()));
             }
             0
         }
+        Err(payload) => {
+            unsafe {
+                ptr::write(error, Panic::error(&payload));
+            }
+            3
+        }
     }
 }
 
@@ -161,12 +186,41 @@ impl DecoderBox {
         }
     }
 
+    /// Returns the address of the decoder, which is shared by the copies of self.
+    pub fn as_ptr(&self) -> *const () {
+        self.decoder as *const ()
+    }
+
     pub fn new_worker(&mut self, ctx: &Context) -> WorkerBox {
         (self.new_worker)(self, ctx)
     }
 
+    /// Returns the metadata of the decoder.
+    ///
+    /// Panics if the metadata is malformed, which `try_metadata` reports instead.
+    /// The kernel does not load libraries with such decoders.
     pub fn metadata(&self) -> Metadata {
-        bincode::deserialize(&(self.metadata)(self)).unwrap()
+        self.try_metadata().unwrap()
+    }
+
+    /// Returns the metadata of the decoder, or an error if the metadata is malformed.
+    pub fn try_metadata(&self) -> Result<Metadata> {
+        let data = (self.metadata)(self);
+        let mut reader: &[u8] = &data;
+        let (id, name, description, exec_type) = bincode::deserialize_from(&mut reader)?;
+        let (priority, runs_after) = if reader.is_empty() {
+            (0, Vec::new())
+        } else {
+            bincode::deserialize_from(&mut reader)?
+        };
+        Ok(Metadata {
+            id,
+            name,
+            description,
+            exec_type,
+            priority,
+            runs_after,
+        })
     }
 }
 
@@ -187,11 +241,15 @@ extern "C" fn abi_new_worker(diss: *mut DecoderBox, ctx: *const Context) -> Work
 
 extern "C" fn abi_metadata(diss: *const DecoderBox) -> SafeVec<u8> {
     let diss = unsafe { &*((*diss).decoder) };
-    bincode::serialize(&diss.metadata()).unwrap().into()
+    let m = diss.metadata();
+    let base = (&m.id, &m.name, &m.description, &m.exec_type);
+    let ext = (m.priority, &m.runs_after);
+    bincode::serialize(&(base, ext)).unwrap().into()
 }
 
 #[cfg(test)]
 mod tests {
+    use bincode;
     use context::Context;
     use decoder::{Decoder, DecoderBox, ExecType, Metadata, Status, Worker};
     use fixed::Fixed;
@@ -200,6 +258,7 @@ mod tests {
     use result::Result;
     use slice::ByteSlice;
     use token::Token;
+    use vec::SafeVec;
 
     #[test]
     fn decode() {
@@ -245,4 +304,71 @@ mod tests {
 
         assert_eq!(worker.decode(&mut ctx, &[], &mut layer).unwrap(), true);
     }
+
+    #[test]
+    fn metadata() {
+        struct TestWorker {}
+
+        impl Worker for TestWorker {
+            fn decode(
+                &mut self,
+                _ctx: &mut Context,
+                _stack: &LayerStack,
+                _parent: &mut Parent,
+            ) -> Result<Status> {
+                Ok(Status::Skip)
+            }
+        }
+
+        #[derive(Clone)]
+        struct TestDecoder {}
+
+        impl Decoder for TestDecoder {
+            fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+                Box::new(TestWorker {})
+            }
+
+            fn metadata(&self) -> Metadata {
+                Metadata {
+                    id: "test".into(),
+                    priority: 10,
+                    runs_after: vec!["eth".into()],
+                    ..Metadata::default()
+                }
+            }
+        }
+
+        let diss = DecoderBox::new(TestDecoder {});
+        let metadata = diss.metadata();
+        assert_eq!(metadata.id, "test");
+        assert_eq!(metadata.priority, 10);
+        assert_eq!(metadata.runs_after, vec!["eth".to_string()]);
+
+        // Older plugins do not send the fields after exec_type.
+        extern "C" fn legacy_metadata(_diss: *const DecoderBox) -> SafeVec<u8> {
+            let base = ("legacy", "", "", ExecType::SerialSync);
+            bincode::serialize(&base).unwrap().into()
+        }
+
+        let diss = DecoderBox {
+            metadata: legacy_metadata,
+            ..diss
+        };
+        let metadata = diss.metadata();
+        assert_eq!(metadata.id, "legacy");
+        assert_eq!(metadata.exec_type, ExecType::SerialSync);
+        assert_eq!(metadata.priority, 0);
+        assert!(metadata.runs_after.is_empty());
+
+        extern "C" fn malformed_metadata(_diss: *const DecoderBox) -> SafeVec<u8> {
+            let base = ("malformed", "", "", 100u32);
+            bincode::serialize(&base).unwrap().into()
+        }
+
+        let diss = DecoderBox {
+            metadata: malformed_metadata,
+            ..diss
+        };
+        assert!(diss.try_metadata().is_err());
+    }
 }
@@ -5,12 +5,18 @@ use parking_lot::Mutex;
 use std::{cell::RefCell, collections::hash_map::Entry, slice, str};
 use token::Token;
 
+/// The revision of the ABI within a minor version.
+///
+/// It is incremented whenever the layout of the types shared with plugins changes,
+/// so that plugins built against an older layout are rejected.
+const ABI_REVISION: u64 = 2;
+
 #[cfg(not(feature = "genet-static"))]
 #[no_mangle]
 pub extern "C" fn genet_abi_version() -> u64 {
     let major: u64 = env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0);
     let minor: u64 = env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0);
-    major << 32 | minor
+    major << 32 | minor << 16 | ABI_REVISION
 }
 
 #[cfg(not(feature = "genet-static"))]
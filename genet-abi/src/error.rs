@@ -1,4 +1,4 @@
-use std::{error, fmt, str};
+use std::{any::Any, error, fmt, str};
 use string::SafeString;
 
 /// An error object.
@@ -35,6 +35,44 @@ impl error::Error for Error {
     }
 }
 
+/// An error caused by a panic in a package.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Panic {
+    desc: String,
+}
+
+impl Panic {
+    /// Creates a new Panic from an error which carries the panic message.
+    pub fn new(err: &Error) -> Panic {
+        Self {
+            desc: err.desc.to_string(),
+        }
+    }
+
+    /// Creates a new Error from a panic payload.
+    pub fn error(payload: &Box<Any + Send>) -> Error {
+        if let Some(msg) = payload.downcast_ref::<&str>() {
+            Error::new(msg)
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            Error::new(msg)
+        } else {
+            Error::new("unknown panic")
+        }
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "panicked: {}", self.desc)
+    }
+}
+
+impl error::Error for Panic {
+    fn description(&self) -> &str {
+        &self.desc
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,4 +84,14 @@ mod tests {
         let err = Error::new(msg);
         assert_eq!(error::Error::description(&err), msg);
     }
+
+    #[test]
+    fn panic() {
+        let payload: Box<Any + Send> = Box::new("index out of bounds");
+        let err = Panic::new(&Panic::error(&payload));
+        assert_eq!(err.to_string(), "panicked: index out of bounds");
+        let payload: Box<Any + Send> = Box::new(String::from("overflow"));
+        let err = Panic::new(&Panic::error(&payload));
+        assert_eq!(err.to_string(), "panicked: overflow");
+    }
 }
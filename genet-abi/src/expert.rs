@@ -0,0 +1,100 @@
+use std::{fmt, ops::Range};
+use string::SafeString;
+use token::Token;
+
+/// Severity of an expert item.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note = 1,
+    Warning = 2,
+    Error = 3,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An expert item which points out a notable condition in a layer.
+#[repr(C)]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Expert {
+    severity: Severity,
+    group: Token,
+    message: SafeString,
+    start: u64,
+    end: u64,
+}
+
+impl Expert {
+    /// Creates a new Expert covering the whole layer.
+    pub fn new<T: Into<Token>>(severity: Severity, group: T, message: &str) -> Expert {
+        Expert {
+            severity,
+            group: group.into(),
+            message: SafeString::from(message),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Sets the byte range of self.
+    pub fn with_range(mut self, range: Range<usize>) -> Expert {
+        self.start = range.start as u64;
+        self.end = range.end as u64;
+        self
+    }
+
+    /// Returns the severity of self.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the group of self.
+    pub fn group(&self) -> Token {
+        self.group
+    }
+
+    /// Returns the message of self.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the byte range of self.
+    ///
+    /// An empty range means the whole layer.
+    pub fn range(&self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expert::{Expert, Severity};
+    use token::Token;
+
+    #[test]
+    fn range() {
+        let expert = Expert::new(Severity::Warning, Token::null(), "bad checksum");
+        assert_eq!(expert.range(), 0..0);
+        let expert = expert.with_range(10..12);
+        assert_eq!(expert.range(), 10..12);
+        assert_eq!(expert.severity(), Severity::Warning);
+        assert_eq!(expert.message(), "bad checksum");
+    }
+
+    #[test]
+    fn severity() {
+        assert!(Severity::Note < Severity::Warning);
+        assert!(Severity::Warning < Severity::Error);
+        assert_eq!(Severity::Error.to_string(), "error");
+    }
+}
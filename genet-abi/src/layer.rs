@@ -1,4 +1,5 @@
 use attr::Attr;
+use expert::Expert;
 use fixed::{Fixed, MutFixed};
 use metadata::Metadata;
 use slice::ByteSlice;
@@ -7,6 +8,7 @@ use std::{
     marker::PhantomData,
     ops::{Deref, DerefMut},
     slice,
+    sync::Arc,
 };
 use token::Token;
 
@@ -34,9 +36,16 @@ impl<'a> LayerStack<'a> {
 
     /// Find the attribute in the LayerStack.
     pub fn attr(&self, id: Token) -> Option<&Attr> {
+        self.attr_layer(id).map(|(attr, _)| attr)
+    }
+
+    /// Find the attribute and the layer which has it in the LayerStack.
+    ///
+    /// The value of the attribute must be read from the returned layer.
+    pub fn attr_layer(&self, id: Token) -> Option<(&Attr, &Layer)> {
         for layer in self.layers().rev() {
             if let Some(attr) = layer.attr(id) {
-                return Some(attr);
+                return Some((attr, layer));
             }
         }
         None
@@ -115,6 +124,16 @@ impl<'a> Parent<'a> {
         self.deref_mut().add_payload(payload);
     }
 
+    /// Returns the slice of expert items.
+    pub fn experts(&self) -> &[Expert] {
+        self.deref().experts()
+    }
+
+    /// Adds an expert item to the Layer.
+    pub fn add_expert(&mut self, expert: Expert) {
+        self.deref_mut().add_expert(expert);
+    }
+
     pub fn add_child<T: Into<MutFixed<Layer>>>(&mut self, layer: T) {
         (self.add_child)(self, layer.into().as_mut_ptr());
     }
@@ -154,11 +173,14 @@ extern "C" fn abi_children_data(layer: *const Parent) -> *const *mut Layer {
 
 /// A layer object.
 #[repr(C)]
+#[derive(Clone)]
 pub struct Layer {
     class: Fixed<LayerClass>,
     data: ByteSlice,
     attrs: Vec<Fixed<Attr>>,
     payloads: Vec<Payload>,
+    experts: Vec<Expert>,
+    buffers: Vec<Arc<Vec<u8>>>,
 }
 
 unsafe impl Send for Layer {}
@@ -171,6 +193,8 @@ impl Layer {
             data: data.into(),
             attrs: Vec::new(),
             payloads: Vec::new(),
+            experts: Vec::new(),
+            buffers: Vec::new(),
         }
     }
 
@@ -179,11 +203,21 @@ impl Layer {
         self.class.id()
     }
 
+    /// Returns the class of self.
+    pub fn class(&self) -> Fixed<LayerClass> {
+        self.class.clone()
+    }
+
     /// Returns the type of self.
     pub fn data(&self) -> ByteSlice {
         self.class.data(self)
     }
 
+    /// Replaces the data of self.
+    pub fn set_data<B: Into<ByteSlice>>(&mut self, data: B) {
+        self.data = data.into();
+    }
+
     /// Returns the slice of headers.
     pub fn headers(&self) -> &[Fixed<Attr>] {
         self.class.headers()
@@ -194,15 +228,19 @@ impl Layer {
         self.class.attrs(self)
     }
 
-    /// Find the attribute in the Layer.
-    pub fn attr<T: Into<Token>>(&self, id: T) -> Option<&Attr> {
+    /// Returns the target ID if the given ID is an alias, or the ID itself.
+    pub fn resolve_alias<T: Into<Token>>(&self, id: T) -> Token {
         let id = id.into();
-        let id = self
-            .class
+        self.class
             .aliases()
             .find(|alias| alias.id == id)
             .map(|alias| alias.target)
-            .unwrap_or(id);
+            .unwrap_or(id)
+    }
+
+    /// Find the attribute in the Layer.
+    pub fn attr<T: Into<Token>>(&self, id: T) -> Option<&Attr> {
+        let id = self.resolve_alias(id);
         self.attrs()
             .iter()
             .chain(self.class.headers().iter())
@@ -226,6 +264,32 @@ impl Layer {
         let func = self.class.add_payload;
         (func)(self, payload);
     }
+
+    /// Returns the slice of expert items.
+    pub fn experts(&self) -> &[Expert] {
+        self.class.experts(self)
+    }
+
+    /// Adds an expert item to the Layer.
+    pub fn add_expert(&mut self, expert: Expert) {
+        let func = self.class.add_expert;
+        (func)(self, expert);
+    }
+
+    /// Takes the ownership of the data and returns a slice of it.
+    ///
+    /// The data is freed with the Layer, so that it can be used for payloads
+    /// or the data of the Layer itself.
+    pub fn add_buffer(&mut self, data: Vec<u8>) -> ByteSlice {
+        let slice = unsafe { ByteSlice::from_raw_parts(data.as_ptr(), data.len()) };
+        self.buffers.push(Arc::new(data));
+        slice
+    }
+
+    /// Returns the total size of the buffers owned by the Layer.
+    pub fn buffer_size(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.len()).sum()
+    }
 }
 
 impl fmt::Debug for Layer {
@@ -242,6 +306,7 @@ impl Into<MutFixed<Layer>> for Layer {
 
 /// A payload object.
 #[repr(C)]
+#[derive(Clone)]
 pub struct Payload {
     data: *const u8,
     len: u64,
@@ -337,6 +402,9 @@ impl LayerClassBuilder {
             payloads_len: abi_payloads_len,
             payloads_data: abi_payloads_data,
             add_payload: abi_add_payload,
+            experts_len: abi_experts_len,
+            experts_data: abi_experts_data,
+            add_expert: abi_add_expert,
             id: self.id,
             meta: self.meta,
             aliases: self.aliases,
@@ -366,6 +434,9 @@ pub struct LayerClass {
     payloads_len: extern "C" fn(*const Layer) -> u64,
     payloads_data: extern "C" fn(*const Layer) -> *const Payload,
     add_payload: extern "C" fn(*mut Layer, Payload),
+    experts_len: extern "C" fn(*const Layer) -> u64,
+    experts_data: extern "C" fn(*const Layer) -> *const Expert,
+    add_expert: extern "C" fn(*mut Layer, Expert),
     id: Token,
     meta: Metadata,
     aliases: Vec<Alias>,
@@ -417,6 +488,12 @@ impl LayerClass {
         let len = (self.payloads_len)(layer) as usize;
         unsafe { slice::from_raw_parts(data, len) }
     }
+
+    fn experts(&self, layer: &Layer) -> &[Expert] {
+        let data = (self.experts_data)(layer);
+        let len = (self.experts_len)(layer) as usize;
+        unsafe { slice::from_raw_parts(data, len) }
+    }
 }
 
 impl Into<Fixed<LayerClass>> for &'static LayerClass {
@@ -479,12 +556,26 @@ extern "C" fn abi_add_payload(layer: *mut Layer, payload: Payload) {
     payloads.push(payload);
 }
 
+extern "C" fn abi_experts_len(layer: *const Layer) -> u64 {
+    unsafe { (*layer).experts.len() as u64 }
+}
+
+extern "C" fn abi_experts_data(layer: *const Layer) -> *const Expert {
+    unsafe { (*layer).experts.as_ptr() }
+}
+
+extern "C" fn abi_add_expert(layer: *mut Layer, expert: Expert) {
+    let experts = unsafe { &mut (*layer).experts };
+    experts.push(expert);
+}
+
 #[cfg(test)]
 mod tests {
     use attr::{Attr, AttrClass};
     use cast::Cast;
+    use expert::{Expert, Severity};
     use fixed::Fixed;
-    use layer::{Layer, LayerClass, Payload};
+    use layer::{Layer, LayerClass, LayerStack, Payload};
     use slice::ByteSlice;
     use std::io::Result;
     use token::Token;
@@ -528,6 +619,74 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn buffers() {
+        let class = Fixed::new(LayerClass::builder(Token::null()).build());
+        let mut layer = Layer::new(class, ByteSlice::new());
+        let data = layer.add_buffer(b"hello".to_vec());
+        layer.set_data(data);
+        let payload = layer.add_buffer(vec![1, 2, 3]);
+        layer.add_payload(Payload::new(payload, Token::null()));
+        assert_eq!(layer.buffer_size(), 8);
+
+        let copy = layer.clone();
+        drop(layer);
+        assert_eq!(&copy.data()[..], b"hello");
+        assert_eq!(&copy.payloads()[0].data()[..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn stack_attr_layer() {
+        #[derive(Clone)]
+        struct TestCast {}
+
+        impl Cast for TestCast {
+            fn cast(&self, attr: &Attr, data: &ByteSlice) -> Result<Variant> {
+                Ok(Variant::UInt64(u64::from(data[attr.range().start])))
+            }
+        }
+        let class = Fixed::new(AttrClass::builder("addr").cast(TestCast {}).build());
+        let data = b"hello";
+        let lower = Fixed::new(LayerClass::builder(Token::from(1)).build());
+        let mut lower = Layer::new(lower, ByteSlice::from(&data[..]));
+        lower.add_attr(Attr::builder(class.clone()).range(1..3).build());
+        let upper = Fixed::new(LayerClass::builder(Token::from(2)).build());
+        let upper = Layer::new(upper, ByteSlice::from(&data[2..]));
+
+        let layers = [&lower as *const Layer, &upper as *const Layer];
+        let stack = unsafe { LayerStack::new(layers.as_ptr(), layers.len()) };
+        let (attr, layer) = stack.attr_layer(Token::from("addr")).unwrap();
+        assert_eq!(layer.id(), Token::from(1));
+        assert_eq!(
+            attr.try_get(layer).unwrap(),
+            Variant::UInt64(u64::from(b'e'))
+        );
+        assert!(stack.attr_layer(Token::from("port")).is_none());
+    }
+
+    #[test]
+    fn experts() {
+        let class = Fixed::new(LayerClass::builder(Token::null()).build());
+        let mut layer = Layer::new(class, ByteSlice::new());
+        assert!(layer.experts().is_empty());
+
+        layer.add_expert(Expert::new(
+            Severity::Warning,
+            Token::from(1),
+            "bad checksum",
+        ));
+        layer.add_expert(
+            Expert::new(Severity::Note, Token::from(2), "retransmission").with_range(4..8),
+        );
+
+        let experts = layer.experts();
+        assert_eq!(experts.len(), 2);
+        assert_eq!(experts[0].severity(), Severity::Warning);
+        assert_eq!(experts[0].message(), "bad checksum");
+        assert_eq!(experts[1].group(), Token::from(2));
+        assert_eq!(experts[1].range(), 4..8);
+    }
+
     #[test]
     fn attrs() {
         let class = Fixed::new(LayerClass::builder(Token::null()).build());
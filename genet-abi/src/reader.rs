@@ -1,12 +1,16 @@
 use bincode;
 use context::Context;
-use error::Error;
+use error::{Error, Panic};
 use file::FileType;
 use fixed::MutFixed;
 use layer::Layer;
 use result::Result;
 use serde::ser::{Serialize, Serializer};
-use std::{fmt, mem, ptr, slice, str};
+use std::{
+    fmt, mem,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice, str,
+};
 use vec::SafeVec;
 
 /// Reader metadata.
@@ -109,7 +113,7 @@ extern "C" fn abi_reader_new_worker(
             1
         }
         Err(e) => {
-            unsafe { *err = Error::new(e.description()) };
+            unsafe { *err = Error::new(&e.to_string()) };
             0
         }
     }
@@ -121,6 +125,17 @@ extern "C" fn abi_metadata(reader: *const ReaderBox) -> SafeVec<u8> {
 }
 
 /// Reader worker trait.
+///
+/// `read` returns an empty batch at the end of the input. Errors are reported to the
+/// user as a failure of the input.
+///
+/// # ABI contract
+///
+/// The kernel takes ownership of the returned layers and of their data, and frees them
+/// with `Box::from_raw` once the frames are dropped or their data is moved to a file.
+/// Hence the data must be created with `ByteSlice::from` from a `Vec<u8>` or a
+/// `Box<[u8]>`, never from a borrowed or static slice, and plugins must not replace the
+/// global allocator, so that the kernel and the plugins share the same heap.
 pub trait Worker: Send {
     fn read(&mut self) -> Result<Vec<Layer>>;
 }
@@ -147,10 +162,10 @@ impl WorkerBox {
     pub fn read(&mut self) -> Result<Vec<MutFixed<Layer>>> {
         let mut v = SafeVec::new();
         let mut e = Error::new("");
-        if (self.read)(self.worker, &mut v, &mut e) == 0 {
-            Err(Box::new(e))
-        } else {
-            Ok(v.into_iter().collect())
+        match (self.read)(self.worker, &mut v, &mut e) {
+            0 => Err(Box::new(e)),
+            2 => Err(Box::new(Panic::new(&e))),
+            _ => Ok(v.into_iter().collect()),
         }
     }
 }
@@ -177,8 +192,8 @@ extern "C" fn abi_reader_worker_read(
     err: *mut Error,
 ) -> u8 {
     let worker = unsafe { &mut *worker };
-    match worker.read() {
-        Ok(layers) => {
+    match panic::catch_unwind(AssertUnwindSafe(|| worker.read())) {
+        Ok(Ok(layers)) => {
             let mut safe = SafeVec::with_capacity(layers.len() as u64);
             for layer in layers {
                 safe.push(MutFixed::new(layer));
@@ -186,9 +201,13 @@ extern "C" fn abi_reader_worker_read(
             unsafe { *out = safe };
             1
         }
-        Err(e) => {
-            unsafe { *err = Error::new(e.description()) };
+        Ok(Err(e)) => {
+            unsafe { *err = Error::new(&e.to_string()) };
             0
         }
+        Err(payload) => {
+            unsafe { *err = Panic::error(&payload) };
+            2
+        }
     }
 }
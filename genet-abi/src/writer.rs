@@ -1,12 +1,16 @@
 use bincode;
 use context::Context;
-use error::Error;
+use error::{Error, Panic};
 use file::FileType;
 use fixed::MutFixed;
 use layer::{Layer, LayerStack};
 use result::Result;
 use serde::ser::{Serialize, Serializer};
-use std::{fmt, mem, ptr, slice, str};
+use std::{
+    fmt, mem,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice, str,
+};
 use vec::SafeVec;
 
 /// Writer metadata.
@@ -109,7 +113,7 @@ extern "C" fn abi_writer_new_worker(
             1
         }
         Err(e) => {
-            unsafe { *err = Error::new(e.description()) };
+            unsafe { *err = Error::new(&e.to_string()) };
             0
         }
     }
@@ -154,19 +158,19 @@ impl WorkerBox {
     pub fn write(&mut self, index: u32, layers: &[MutFixed<Layer>]) -> Result<()> {
         let mut e = Error::new("");
         let stack = layers.as_ptr() as *const *const Layer;
-        if (self.write)(self.worker, index, stack, layers.len() as u64, &mut e) == 0 {
-            Err(Box::new(e))
-        } else {
-            Ok(())
+        match (self.write)(self.worker, index, stack, layers.len() as u64, &mut e) {
+            0 => Err(Box::new(e)),
+            2 => Err(Box::new(Panic::new(&e))),
+            _ => Ok(()),
         }
     }
 
     pub fn end(&mut self) -> Result<()> {
         let mut e = Error::new("");
-        if (self.end)(self.worker, &mut e) == 0 {
-            Err(Box::new(e))
-        } else {
-            Ok(())
+        match (self.end)(self.worker, &mut e) {
+            0 => Err(Box::new(e)),
+            2 => Err(Box::new(Panic::new(&e))),
+            _ => Ok(()),
         }
     }
 }
@@ -196,22 +200,30 @@ extern "C" fn abi_writer_worker_write(
 ) -> u8 {
     let worker = unsafe { &mut *worker };
     let stack = unsafe { LayerStack::new(layers, len as usize) };
-    match worker.write(index, &stack) {
-        Ok(()) => 1,
-        Err(e) => {
-            unsafe { *err = Error::new(e.description()) };
+    match panic::catch_unwind(AssertUnwindSafe(|| worker.write(index, &stack))) {
+        Ok(Ok(())) => 1,
+        Ok(Err(e)) => {
+            unsafe { *err = Error::new(&e.to_string()) };
             0
         }
+        Err(payload) => {
+            unsafe { *err = Panic::error(&payload) };
+            2
+        }
     }
 }
 
 extern "C" fn abi_writer_worker_end(worker: *mut Box<Worker>, err: *mut Error) -> u8 {
     let worker = unsafe { &mut *worker };
-    match worker.end() {
-        Ok(()) => 1,
-        Err(e) => {
-            unsafe { *err = Error::new(e.description()) };
+    match panic::catch_unwind(AssertUnwindSafe(|| worker.end())) {
+        Ok(Ok(())) => 1,
+        Ok(Err(e)) => {
+            unsafe { *err = Error::new(&e.to_string()) };
             0
         }
+        Err(payload) => {
+            unsafe { *err = Panic::error(&payload) };
+            2
+        }
     }
 }
@@ -0,0 +1,436 @@
+extern crate clap;
+extern crate genet_abi;
+extern crate genet_filter;
+extern crate genet_kernel;
+#[macro_use]
+extern crate serde_json;
+
+use clap::{App, Arg, ArgMatches};
+use genet_abi::{attr::Attr, layer::Layer, variant::Variant};
+use genet_filter::Filter;
+use genet_kernel::{
+    frame::Frame,
+    profile::Profile,
+    session::{Callback, Event, Session},
+};
+use std::{
+    cmp::Reverse,
+    error, fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+const BLOCK_SIZE: usize = 1024;
+const FILTER_ID: u32 = 0;
+
+type Result<T> = ::std::result::Result<T, Box<dyn error::Error>>;
+
+#[derive(Clone)]
+struct EventSender(Sender<Event>);
+
+impl Callback for EventSender {
+    fn on_event(&self, event: Event) {
+        let _ = self.0.send(event);
+    }
+}
+
+fn main() {
+    let matches = App::new("genet")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Decodes, filters and exports capture files")
+        .arg(
+            Arg::with_name("library-dir")
+                .short("L")
+                .long("library-dir")
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Loads decoder, reader and writer libraries from DIR"),
+        )
+        .arg(
+            Arg::with_name("reader")
+                .short("r")
+                .long("reader")
+                .value_name("ID")
+                .help("Reader id (defaults to the only registered reader)"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .short("f")
+                .long("filter")
+                .value_name("FILTER")
+                .help("Display filter"),
+        )
+        .arg(
+            Arg::with_name("tree")
+                .short("t")
+                .long("tree")
+                .help("Prints the layer and attribute tree of each frame"),
+        )
+        .arg(
+            Arg::with_name("writer")
+                .short("w")
+                .long("writer")
+                .value_name("ID")
+                .requires("output")
+                .help("Exports the frames through the writer instead of printing them"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .requires("writer")
+                .help("Output file for the writer"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .short("j")
+                .long("concurrency")
+                .value_name("N")
+                .help("Number of decoder threads (0 for the number of CPUs)"),
+        )
+        .arg(
+            Arg::with_name("disable")
+                .short("d")
+                .long("disable")
+                .value_name("ID")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Disables the decoder with ID"),
+        )
+        .arg(
+            Arg::with_name("list")
+                .long("list")
+                .help("Lists the registered readers, writers and decoders"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .value_name("FILE")
+                .required_unless("list")
+                .help("Capture file to read"),
+        )
+        .get_matches();
+
+    if let Err(err) = run(&matches) {
+        eprintln!("genet: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let mut profile = Profile::new();
+    if let Some(concurrency) = matches.value_of("concurrency") {
+        let concurrency = concurrency
+            .parse()
+            .map_err(|_| format!("invalid concurrency: {}", concurrency))?;
+        profile.set_concurrency(concurrency);
+    }
+    if let Some(dirs) = matches.values_of("library-dir") {
+        for dir in dirs {
+            load_libraries(&mut profile, Path::new(dir))?;
+        }
+    }
+
+    if let Some(ids) = matches.values_of("disable") {
+        for id in ids {
+            profile.set_decoder_enabled(id, false);
+        }
+    }
+
+    if matches.is_present("list") {
+        print_list(&profile);
+        return Ok(());
+    }
+
+    let filter = match matches.value_of("filter") {
+        Some(filter) => Some(Filter::compile(filter).map_err(|err| err.to_string())?),
+        None => None,
+    };
+    let reader = reader_id(&profile, matches.value_of("reader"))?;
+    let input = matches.value_of("input").unwrap();
+
+    let (sender, events) = mpsc::channel();
+    let mut session = Session::new(profile, EventSender(sender));
+    let id = session.create_reader(&reader, &file_arg(input));
+    if id == 0 {
+        return wait(&events, |_| None);
+    }
+
+    wait(&events, |event| match event {
+        Event::Input(i, err) if i == id => {
+            Some(err.map_or(Ok(()), |err| Err(err.to_string().into())))
+        }
+        _ => None,
+    })?;
+
+    if let (Some(writer), Some(output)) = (matches.value_of("writer"), matches.value_of("output")) {
+        if session
+            .profile()
+            .writers()
+            .all(|w| w.metadata().id != writer)
+        {
+            return Err(format!("unknown writer: {}", writer).into());
+        }
+        let id = session.create_writer(writer, &file_arg(output), filter);
+        return wait(&events, |event| match event {
+            Event::Output(i, err) if i == id => {
+                Some(err.map_or(Ok(()), |err| Err(err.to_string().into())))
+            }
+            _ => None,
+        });
+    }
+
+    let indices = if let Some(filter) = filter {
+        session.set_filter(FILTER_ID, Some(filter));
+        let len = wait(&events, |event| match event {
+            Event::FilterComplete(i, len) if i == FILTER_ID => Some(Ok(len as usize)),
+            _ => None,
+        })?;
+        Some(session.filtered_frames(FILTER_ID, 0..len))
+    } else {
+        None
+    };
+
+    let tree = matches.is_present("tree");
+    match print_frames(&mut session, indices, tree) {
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+        result => result?,
+    }
+
+    let stats = session.stats();
+    if stats.errors > 0 {
+        eprintln!(
+            "genet: {} decoder errors in {} frames",
+            stats.errors, stats.error_frames
+        );
+    }
+    let mut experts = stats.experts.into_iter().collect::<Vec<_>>();
+    experts.sort_by_key(|&((severity, group), _)| (Reverse(severity), group.to_string()));
+    for ((severity, group), count) in experts {
+        eprintln!("genet: {} {} items in {}", count, severity, group);
+    }
+    Ok(())
+}
+
+/// Receives events until `f` returns a result.
+fn wait<T, F>(events: &Receiver<Event>, mut f: F) -> Result<T>
+where
+    F: FnMut(Event) -> Option<Result<T>>,
+{
+    loop {
+        match events.recv()? {
+            Event::Error(err) => return Err(err.to_string().into()),
+            Event::Panic(ref decoder, frame) if decoder.is_empty() => {
+                eprintln!("genet: a decoder panicked on frame {}", frame)
+            }
+            Event::Panic(decoder, frame) => {
+                eprintln!("genet: decoder {} panicked on frame {}", decoder, frame)
+            }
+            event => {
+                if let Some(result) = f(event) {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+fn load_libraries(profile: &mut Profile, dir: &Path) -> Result<()> {
+    let mut paths = fs::read_dir(dir)
+        .map_err(|err| format!("{}: {}", dir.display(), err))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |ext| ext == "so" || ext == "dylib" || ext == "dll")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        profile
+            .load_library(&path.to_string_lossy())
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+fn reader_id(profile: &Profile, id: Option<&str>) -> Result<String> {
+    let readers = profile
+        .readers()
+        .map(|r| r.metadata().id)
+        .collect::<Vec<_>>();
+    match id {
+        Some(id) if readers.iter().any(|r| r == id) => Ok(id.to_string()),
+        Some(id) => Err(format!("unknown reader: {}", id).into()),
+        None if readers.len() == 1 => Ok(readers[0].clone()),
+        None if readers.is_empty() => Err("no readers are registered".into()),
+        None => Err(format!(
+            "multiple readers are registered; specify one of: {}",
+            readers.join(", ")
+        )
+        .into()),
+    }
+}
+
+fn file_arg(path: &str) -> String {
+    json!({ "file": path }).to_string()
+}
+
+fn print_list(profile: &Profile) {
+    println!("readers:");
+    for reader in profile.readers() {
+        println!("  {}", reader.metadata().id);
+    }
+    println!("writers:");
+    for writer in profile.writers() {
+        println!("  {}", writer.metadata().id);
+    }
+    println!("decoders:");
+    for decoder in profile.decoders() {
+        let id = decoder.metadata().id;
+        if profile.is_decoder_enabled(&id) {
+            println!("  {}", id);
+        } else {
+            println!("  {} (disabled)", id);
+        }
+    }
+}
+
+fn print_frames(session: &mut Session, indices: Option<Vec<u32>>, tree: bool) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let ranges = match indices {
+        Some(indices) => indices
+            .chunks(BLOCK_SIZE)
+            .flat_map(contiguous_ranges)
+            .collect::<Vec<_>>(),
+        None => (0..session.len())
+            .step_by(BLOCK_SIZE)
+            .map(|start| (start, (start + BLOCK_SIZE).min(session.len())))
+            .collect(),
+    };
+    for (start, end) in ranges {
+        for frame in session.frames(start..end) {
+            let result = frame.with(|frame| {
+                if tree {
+                    print_tree(&mut out, frame)
+                } else {
+                    print_summary(&mut out, frame)
+                }
+            });
+            if let Some(result) = result {
+                result?;
+            }
+        }
+    }
+    out.flush()
+}
+
+/// Groups sorted frame indices into ranges of consecutive indices.
+fn contiguous_ranges(indices: &[u32]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &index in indices {
+        let index = index as usize;
+        match ranges.last_mut() {
+            Some(range) if range.1 == index => range.1 += 1,
+            _ => ranges.push((index, index + 1)),
+        }
+    }
+    ranges
+}
+
+fn print_summary<W: Write>(out: &mut W, frame: &Frame) -> io::Result<()> {
+    let layers = frame.layers();
+    let root = &layers[0];
+    let timestamp = root
+        .attr("link.timestamp")
+        .and_then(|attr| attr.try_get(root).ok());
+    let length = root
+        .attr("link.length")
+        .and_then(|attr| attr.try_get(root).ok());
+    let primary = &layers[frame.primary()];
+
+    writeln!(
+        out,
+        "{:>8} {:>18} {:>6} {}",
+        frame.index(),
+        timestamp.map_or_else(String::new, |ts| format_timestamp(&ts)),
+        length.map_or_else(String::new, |len| format_value(&len)),
+        primary.id()
+    )
+}
+
+fn print_tree<W: Write>(out: &mut W, frame: &Frame) -> io::Result<()> {
+    print_summary(out, frame)?;
+    print_layer(out, frame, 0, 1)
+}
+
+fn print_layer<W: Write>(out: &mut W, frame: &Frame, index: usize, depth: usize) -> io::Result<()> {
+    let layer = match frame.layers().get(index) {
+        Some(layer) => layer,
+        None => return Ok(()),
+    };
+    let indent = "  ".repeat(depth);
+    writeln!(out, "{}{}", indent, layer.id())?;
+    for attr in layer.headers().iter().chain(layer.attrs().iter()) {
+        writeln!(
+            out,
+            "{}  {}: {}",
+            indent,
+            attr.id(),
+            format_attr(attr, layer)
+        )?;
+    }
+    for expert in layer.experts() {
+        writeln!(
+            out,
+            "{}  [{}] {} ({})",
+            indent,
+            expert.severity(),
+            expert.message(),
+            expert.group()
+        )?;
+    }
+    for child in frame.children(index) {
+        print_layer(out, frame, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn format_attr(attr: &Attr, layer: &Layer) -> String {
+    match attr.try_get(layer) {
+        Ok(value) => format_value(&value),
+        Err(err) => format!("<{}>", err),
+    }
+}
+
+fn format_timestamp(value: &Variant) -> String {
+    match value {
+        Variant::Float64(ts) => format!("{:.6}", ts),
+        value => format_value(value),
+    }
+}
+
+fn format_value(value: &Variant) -> String {
+    match value {
+        Variant::Nil => "nil".to_string(),
+        Variant::Bool(b) => b.to_string(),
+        Variant::Int64(n) => n.to_string(),
+        Variant::UInt64(n) => n.to_string(),
+        Variant::Float64(n) => n.to_string(),
+        Variant::String(s) => format!("{:?}", s),
+        Variant::BigInt(b) | Variant::Buffer(b) => format_bytes(b),
+        Variant::Slice(s) => format_bytes(s),
+    }
+}
+
+fn format_bytes(data: &[u8]) -> String {
+    let hex = data
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .concat();
+    format!("0x{}", hex)
+}
@@ -0,0 +1,123 @@
+extern crate genet_abi;
+extern crate genet_filter;
+
+use genet_abi::{
+    attr::{Attr, AttrClass},
+    cast::Typed,
+    fixed::{Fixed, MutFixed},
+    layer::{Layer, LayerClass},
+    slice::{ByteSlice, TryGet},
+    variant::Variant,
+};
+use genet_filter::{
+    context::Context,
+    parser::parse,
+    program::{Machine, Program},
+    variant::VariantExt,
+};
+use std::{
+    io::Result,
+    time::{Duration, Instant},
+};
+
+const FRAMES: usize = 100_000;
+const ROUNDS: usize = 5;
+
+const FILTERS: &[&str] = &[
+    "ipv4.src == @192.168.0.0/16 && _.proto in {6, 17}",
+    "ipv4.ttl - 1 > 32 * 2 || ipv4.proto == 6",
+    "ipv4 && ipv4.ttl > 10 && ipv4.ttl < 200 && ipv4.ttl != 64",
+];
+
+/// Reads a single byte as an integer, or a longer range as a slice.
+#[derive(Clone)]
+struct Field;
+
+impl Typed for Field {
+    type Output = Variant;
+
+    fn cast(&self, attr: &Attr, data: &ByteSlice) -> Result<Variant> {
+        let range = attr.range();
+        if range.len() == 1 {
+            data.try_get(range.start).map(|b| Variant::UInt64(b.into()))
+        } else {
+            data.try_get(range).map(Variant::Slice)
+        }
+    }
+}
+
+fn attr(id: &'static str, range: std::ops::Range<usize>) -> Fixed<Attr> {
+    let class = Fixed::new(AttrClass::builder(id).cast(Field).build());
+    Fixed::new(Attr::builder(class).range(range).build())
+}
+
+/// Builds a shared layer class whose headers are read from each frame,
+/// as decoders do.
+fn class() -> Fixed<LayerClass> {
+    Fixed::new(
+        LayerClass::builder("ipv4")
+            .alias("_.proto", "ipv4.proto")
+            .header(attr("ipv4.ttl", 8..9))
+            .header(attr("ipv4.proto", 9..10))
+            .header(attr("ipv4.src", 12..16))
+            .build(),
+    )
+}
+
+fn layers(class: &Fixed<LayerClass>, n: usize) -> Vec<MutFixed<Layer>> {
+    let mut data = vec![0; 20];
+    data[8] = (n % 256) as u8;
+    data[9] = 6;
+    data[12..16].copy_from_slice(&[192, 168, (n / 256 % 256) as u8, (n % 256) as u8]);
+    vec![MutFixed::new(Layer::new(class.clone(), data))]
+}
+
+fn measure<F>(frames: &[Vec<MutFixed<Layer>>], mut f: F) -> (Duration, usize)
+where
+    F: FnMut(&Context) -> bool,
+{
+    let start = Instant::now();
+    let matched = frames
+        .iter()
+        .filter(|layers| f(&Context::new(layers)))
+        .count();
+    (start.elapsed(), matched)
+}
+
+fn nanos(d: Duration) -> u64 {
+    d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos())
+}
+
+fn main() {
+    let class = class();
+    let frames = (0..FRAMES).map(|n| layers(&class, n)).collect::<Vec<_>>();
+    for filter in FILTERS {
+        let expr = parse(filter).unwrap();
+        let program = Program::compile(&expr).unwrap();
+        let mut machine = Machine::new();
+
+        // Alternate the two forms and keep the best round of each,
+        // so that neither benefits from running after the other.
+        let mut tree = Duration::from_secs(u64::max_value());
+        let mut compiled = tree;
+        let mut matched = 0;
+        for _ in 0..ROUNDS {
+            let (elapsed, expected) = measure(&frames, |ctx| expr.eval(ctx).is_truthy());
+            tree = tree.min(elapsed);
+            let (elapsed, count) = measure(&frames, |ctx| machine.run(&program, ctx).is_truthy());
+            compiled = compiled.min(elapsed);
+            assert_eq!(expected, count);
+            matched = count;
+        }
+
+        println!("{} ({} matched)", filter, matched);
+        println!(
+            "    Expr::eval   {:>6} ns/frame",
+            nanos(tree) / FRAMES as u64
+        );
+        println!(
+            "    Machine::run {:>6} ns/frame",
+            nanos(compiled) / FRAMES as u64
+        );
+    }
+}
@@ -1,41 +1,221 @@
 use context::Context;
 use genet_abi::{token::Token, variant::Variant};
+use regex::{self, bytes::Regex};
+use std::{fmt, ops::Deref};
 use variant::VariantExt;
 
+/// A regular expression compiled from a `matches` operand.
+#[derive(Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(re: &str) -> Result<Pattern, regex::Error> {
+        Regex::new(re).map(Pattern)
+    }
+}
+
+impl Deref for Pattern {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.as_str())
+    }
+}
+
+/// An address prefix parsed from a CIDR macro such as `@10.0.0.0/8`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Prefix {
+    addr: Box<[u8]>,
+    bits: usize,
+}
+
+impl Prefix {
+    /// Returns a prefix with the host bits of `addr` cleared,
+    /// or `None` if `bits` exceeds the address length.
+    pub fn new(addr: &[u8], bits: usize) -> Option<Prefix> {
+        if bits > addr.len() * 8 {
+            return None;
+        }
+        let mut addr = addr.to_vec();
+        for (i, b) in addr.iter_mut().enumerate() {
+            let remain = bits.saturating_sub(i * 8);
+            if remain < 8 {
+                *b &= !(0xff >> remain);
+            }
+        }
+        Some(Prefix {
+            addr: addr.into_boxed_slice(),
+            bits,
+        })
+    }
+
+    pub fn addr(&self) -> &[u8] {
+        &self.addr
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Returns true if the value is an address of the same family within the prefix.
+    pub fn contains(&self, var: &Variant) -> bool {
+        let addr = match var {
+            Variant::Buffer(b) => b,
+            Variant::Slice(b) => &b[..],
+            _ => return false,
+        };
+        if addr.len() != self.addr.len() {
+            return false;
+        }
+        let bytes = self.bits / 8;
+        if addr[..bytes] != self.addr[..bytes] {
+            return false;
+        }
+        let remain = self.bits % 8;
+        remain == 0 || (addr[bytes] & !(0xff >> remain)) == self.addr[bytes]
+    }
+}
+
+/// An element of an `in` set.
+#[derive(PartialEq, Clone, Debug)]
+pub enum SetItem {
+    Value(Expr),
+    /// An inclusive range.
+    Range(Expr, Expr),
+}
+
+impl SetItem {
+    pub fn contains(&self, ctx: &Context, var: &Variant) -> bool {
+        match self {
+            SetItem::Value(Expr::Prefix(p)) => p.contains(var),
+            SetItem::Value(v) => var.op_eq(&v.eval(ctx)),
+            SetItem::Range(start, end) => {
+                var.op_gte(&start.eval(ctx)) && var.op_lte(&end.eval(ctx))
+            }
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Expr {
     Literal(Variant),
     Token(Token),
     Macro(String),
+    Prefix(Prefix),
     CmpEq(Box<Expr>, Box<Expr>),
     CmpNotEq(Box<Expr>, Box<Expr>),
     CmpLt(Box<Expr>, Box<Expr>),
     CmpGt(Box<Expr>, Box<Expr>),
     CmpLte(Box<Expr>, Box<Expr>),
     CmpGte(Box<Expr>, Box<Expr>),
+    Contains(Box<Expr>, Box<Expr>),
+    Matches(Box<Expr>, Pattern),
+    In(Box<Expr>, Vec<SetItem>),
     LogicalAnd(Box<Expr>, Box<Expr>),
     LogicalOr(Box<Expr>, Box<Expr>),
+    BitAnd(Box<Expr>, Box<Expr>),
+    BitOr(Box<Expr>, Box<Expr>),
+    BitXor(Box<Expr>, Box<Expr>),
+    Shl(Box<Expr>, Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Rem(Box<Expr>, Box<Expr>),
     LogicalNegation(Box<Expr>),
     UnaryPlus(Box<Expr>),
     UnaryNegation(Box<Expr>),
 }
 
 impl Expr {
+    /// Rebuilds the expression bottom-up, replacing each node with `f(node)`.
+    pub fn map<F: Fn(Expr) -> Expr>(self, f: &F) -> Expr {
+        let m = |e: Box<Expr>| Box::new(e.map(f));
+        let expr = match self {
+            Expr::CmpEq(l, r) => Expr::CmpEq(m(l), m(r)),
+            Expr::CmpNotEq(l, r) => Expr::CmpNotEq(m(l), m(r)),
+            Expr::CmpLt(l, r) => Expr::CmpLt(m(l), m(r)),
+            Expr::CmpGt(l, r) => Expr::CmpGt(m(l), m(r)),
+            Expr::CmpLte(l, r) => Expr::CmpLte(m(l), m(r)),
+            Expr::CmpGte(l, r) => Expr::CmpGte(m(l), m(r)),
+            Expr::Contains(l, r) => Expr::Contains(m(l), m(r)),
+            Expr::Matches(l, p) => Expr::Matches(m(l), p),
+            Expr::In(l, set) => Expr::In(
+                m(l),
+                set.into_iter()
+                    .map(|item| match item {
+                        SetItem::Value(v) => SetItem::Value(v.map(f)),
+                        SetItem::Range(start, end) => SetItem::Range(start.map(f), end.map(f)),
+                    })
+                    .collect(),
+            ),
+            Expr::LogicalAnd(l, r) => Expr::LogicalAnd(m(l), m(r)),
+            Expr::LogicalOr(l, r) => Expr::LogicalOr(m(l), m(r)),
+            Expr::BitAnd(l, r) => Expr::BitAnd(m(l), m(r)),
+            Expr::BitOr(l, r) => Expr::BitOr(m(l), m(r)),
+            Expr::BitXor(l, r) => Expr::BitXor(m(l), m(r)),
+            Expr::Shl(l, r) => Expr::Shl(m(l), m(r)),
+            Expr::Shr(l, r) => Expr::Shr(m(l), m(r)),
+            Expr::Add(l, r) => Expr::Add(m(l), m(r)),
+            Expr::Sub(l, r) => Expr::Sub(m(l), m(r)),
+            Expr::Mul(l, r) => Expr::Mul(m(l), m(r)),
+            Expr::Div(l, r) => Expr::Div(m(l), m(r)),
+            Expr::Rem(l, r) => Expr::Rem(m(l), m(r)),
+            Expr::LogicalNegation(v) => Expr::LogicalNegation(m(v)),
+            Expr::UnaryPlus(v) => Expr::UnaryPlus(m(v)),
+            Expr::UnaryNegation(v) => Expr::UnaryNegation(m(v)),
+            expr => expr,
+        };
+        f(expr)
+    }
+
+    /// Evaluates the expression.
+    ///
+    /// Panics on `@` macros, which `Program::compile` expands or rejects beforehand.
     pub fn eval(&self, ctx: &Context) -> Variant {
         match self {
             Expr::Literal(v) => v.clone(),
-            Expr::CmpEq(l, r) => Variant::Bool(l.eval(ctx).op_eq(&r.eval(ctx))),
-            Expr::CmpNotEq(l, r) => Variant::Bool(!l.eval(ctx).op_eq(&r.eval(ctx))),
+            Expr::CmpEq(l, r) => Variant::Bool(l.is_equal(r, ctx)),
+            Expr::CmpNotEq(l, r) => Variant::Bool(!l.is_equal(r, ctx)),
             Expr::CmpLt(l, r) => Variant::Bool(l.eval(ctx).op_lt(&r.eval(ctx))),
             Expr::CmpGt(l, r) => Variant::Bool(l.eval(ctx).op_gt(&r.eval(ctx))),
             Expr::CmpLte(l, r) => Variant::Bool(l.eval(ctx).op_lte(&r.eval(ctx))),
             Expr::CmpGte(l, r) => Variant::Bool(l.eval(ctx).op_gte(&r.eval(ctx))),
+            Expr::Contains(l, r) => Variant::Bool(l.eval(ctx).op_contains(&r.eval(ctx))),
+            Expr::Matches(l, p) => Variant::Bool(l.eval(ctx).op_matches(p)),
+            Expr::In(l, set) => {
+                let var = l.eval(ctx);
+                Variant::Bool(set.iter().any(|item| item.contains(ctx, &var)))
+            }
             Expr::LogicalAnd(l, r) => {
                 Variant::Bool(l.eval(ctx).is_truthy() && r.eval(ctx).is_truthy())
             }
             Expr::LogicalOr(l, r) => {
                 Variant::Bool(l.eval(ctx).is_truthy() || r.eval(ctx).is_truthy())
             }
+            Expr::BitAnd(l, r) => l.eval(ctx).op_bit_and(&r.eval(ctx)),
+            Expr::BitOr(l, r) => l.eval(ctx).op_bit_or(&r.eval(ctx)),
+            Expr::BitXor(l, r) => l.eval(ctx).op_bit_xor(&r.eval(ctx)),
+            Expr::Shl(l, r) => l.eval(ctx).op_shl(&r.eval(ctx)),
+            Expr::Shr(l, r) => l.eval(ctx).op_shr(&r.eval(ctx)),
+            Expr::Add(l, r) => l.eval(ctx).op_add(&r.eval(ctx)),
+            Expr::Sub(l, r) => l.eval(ctx).op_sub(&r.eval(ctx)),
+            Expr::Mul(l, r) => l.eval(ctx).op_mul(&r.eval(ctx)),
+            Expr::Div(l, r) => l.eval(ctx).op_div(&r.eval(ctx)),
+            Expr::Rem(l, r) => l.eval(ctx).op_rem(&r.eval(ctx)),
             Expr::LogicalNegation(v) => Variant::Bool(!v.eval(ctx).is_truthy()),
             Expr::UnaryPlus(v) => v.eval(ctx).op_unary_plus(),
             Expr::UnaryNegation(v) => v.eval(ctx).op_unary_negation(),
@@ -44,20 +224,32 @@ impl Expr {
                     if layer.id() == *t {
                         return Variant::Bool(true);
                     }
+                    let id = layer.resolve_alias(*t);
                     if let Some(attr) = layer
                         .headers()
                         .iter()
                         .chain(layer.attrs().iter())
-                        .find(|a| a.id() == *t)
+                        .find(|a| a.id() == id)
                     {
                         if let Ok(val) = attr.try_get(layer) {
                             return val;
                         }
                     }
                 }
-                Variant::Nil
+                ctx.expert(*t).unwrap_or(Variant::Nil)
             }
-            Expr::Macro(_) => Variant::Nil,
+            // The parser expands macros inline; Program::compile expands those of
+            // expressions built by hand, such as by unparser::unparse_attr.
+            Expr::Macro(m) => panic!("unexpanded macro: @{}", m),
+            Expr::Prefix(p) => Variant::Buffer(p.addr().into()),
+        }
+    }
+
+    /// Compares two expressions; equality against a prefix means containment.
+    fn is_equal(&self, other: &Expr, ctx: &Context) -> bool {
+        match (self, other) {
+            (Expr::Prefix(p), e) | (e, Expr::Prefix(p)) => p.contains(&e.eval(ctx)),
+            (l, r) => l.eval(ctx).op_eq(&r.eval(ctx)),
         }
     }
 }
@@ -1,4 +1,11 @@
-use genet_abi::{fixed::MutFixed, layer::Layer};
+use genet_abi::{expert::Expert, fixed::MutFixed, layer::Layer, token::Token, variant::Variant};
+
+lazy_static! {
+    static ref EXPERT: Token = Token::from("_.expert");
+    static ref EXPERT_SEVERITY: Token = Token::from("_.expert.severity");
+    static ref EXPERT_GROUP: Token = Token::from("_.expert.group");
+    static ref EXPERT_MESSAGE: Token = Token::from("_.expert.message");
+}
 
 pub struct Context<'a> {
     layers: &'a [MutFixed<Layer>],
@@ -12,4 +19,37 @@ impl<'a> Context<'a> {
     pub fn layers(&self) -> &'a [MutFixed<Layer>] {
         self.layers
     }
+
+    /// Looks up an `_.expert` identifier.
+    ///
+    /// The group and message are taken from the most severe item in the frame.
+    pub fn expert(&self, id: Token) -> Option<Variant> {
+        if id != *EXPERT && id != *EXPERT_SEVERITY && id != *EXPERT_GROUP && id != *EXPERT_MESSAGE {
+            return None;
+        }
+        let expert = self.most_severe_expert()?;
+        let value = if id == *EXPERT {
+            Variant::Bool(true)
+        } else if id == *EXPERT_SEVERITY {
+            Variant::UInt64(expert.severity() as u64)
+        } else if id == *EXPERT_GROUP {
+            Variant::String(expert.group().to_string().into_boxed_str())
+        } else {
+            Variant::String(expert.message().into())
+        };
+        Some(value)
+    }
+
+    fn most_severe_expert(&self) -> Option<&'a Expert> {
+        let mut result: Option<&'a Expert> = None;
+        for layer in self.layers.iter().rev() {
+            let layer: &'a Layer = layer;
+            for expert in layer.experts() {
+                if result.map_or(true, |e| expert.severity() > e.severity()) {
+                    result = Some(expert);
+                }
+            }
+        }
+        result
+    }
 }
@@ -0,0 +1,155 @@
+use parser::Rule;
+use pest::error::{self, ErrorVariant, InputLocation};
+use std::{fmt, ops::Range};
+
+/// A filter compile error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    span: Range<usize>,
+    expected: Vec<String>,
+    message: String,
+}
+
+impl Error {
+    pub fn new(span: Range<usize>, message: &str) -> Error {
+        Error {
+            span,
+            expected: Vec::new(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Returns the byte range of the offending part of the filter.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the names of the rules expected at the error position.
+    pub fn expected(&self) -> &[String] {
+        &self.expected
+    }
+
+    /// Returns a short description of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<error::Error<Rule>> for Error {
+    fn from(err: error::Error<Rule>) -> Error {
+        let span = match err.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        };
+        match err.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                let mut expected = Vec::new();
+                for name in positives.into_iter().map(rule_name) {
+                    if !expected.contains(&name) {
+                        expected.push(name);
+                    }
+                }
+                let message = if !expected.is_empty() {
+                    format!("expected {}", expected.join(", "))
+                } else if !negatives.is_empty() {
+                    let unexpected = negatives.into_iter().map(rule_name).collect::<Vec<_>>();
+                    format!("unexpected {}", unexpected.join(", "))
+                } else {
+                    "unexpected token".to_string()
+                };
+                Error {
+                    span,
+                    expected,
+                    message,
+                }
+            }
+            ErrorVariant::CustomError { message } => Error {
+                span,
+                expected: Vec::new(),
+                message,
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.span.start)
+    }
+}
+
+/// A non-fatal diagnostic found while compiling a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    span: Range<usize>,
+    message: String,
+}
+
+impl Warning {
+    pub fn new(span: Range<usize>, message: &str) -> Warning {
+        Warning {
+            span,
+            message: message.to_string(),
+        }
+    }
+
+    /// Returns the byte range of the part of the filter the warning refers to.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns a short description of the warning.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.span.start)
+    }
+}
+
+fn rule_name(rule: Rule) -> String {
+    match rule {
+        Rule::EOI => "end of filter",
+        Rule::primary | Rule::expression => "operand",
+        Rule::set => "set",
+        Rule::set_item => "set item",
+        Rule::string => "string",
+        Rule::member => "identifier",
+        Rule::macro_exp => "macro",
+        Rule::op_eq
+        | Rule::op_ne
+        | Rule::op_lt
+        | Rule::op_gt
+        | Rule::op_lte
+        | Rule::op_gte
+        | Rule::op_logical_and
+        | Rule::op_logical_or
+        | Rule::op_in
+        | Rule::op_contains
+        | Rule::op_matches
+        | Rule::op_bit_and
+        | Rule::op_bit_or
+        | Rule::op_bit_xor
+        | Rule::op_shl
+        | Rule::op_shr
+        | Rule::op_add
+        | Rule::op_sub
+        | Rule::op_mul
+        | Rule::op_div
+        | Rule::op_rem => "operator",
+        _ => return format!("{:?}", rule),
+    }
+    .to_string()
+}
@@ -3,58 +3,68 @@ extern crate hwaddr;
 extern crate num_bigint;
 extern crate num_traits;
 extern crate pest;
+extern crate regex;
 extern crate serde;
 extern crate serde_json;
 
+#[macro_use]
+extern crate lazy_static;
+
 #[macro_use]
 extern crate pest_derive;
 
 #[macro_use]
 extern crate arrayref;
 
-use ast::Expr;
 use context::Context;
-use parser::parse;
-use result::Result;
-use std::fmt;
+use error::{Error, Warning};
+use genet_abi::token::Token;
+use parser::parse_with_identifiers;
+use program::{Machine, Program};
+use std::ops::Range;
 use variant::VariantExt;
 
 pub mod ast;
 pub mod context;
+pub mod error;
 pub mod parser;
+pub mod program;
 pub mod result;
 pub mod unparser;
 pub mod variant;
 
 #[derive(Clone, Debug)]
 pub struct Filter {
-    expr: Expr,
+    program: Program,
+    machine: Machine,
+    identifiers: Vec<(Token, Range<usize>)>,
 }
 
 impl Filter {
-    pub fn compile(filter: &str) -> Result<Filter> {
-        match parse(filter) {
-            Ok(expr) => Ok(Filter { expr }),
-            Err(err) => Err(Box::new(Error(format!("{}", err)))),
-        }
+    pub fn compile(filter: &str) -> Result<Filter, Error> {
+        let (expr, identifiers) = parse_with_identifiers(filter)?;
+        Ok(Filter {
+            program: Program::compile(&expr)?,
+            machine: Machine::new(),
+            identifiers,
+        })
     }
 
-    pub fn test(&self, ctx: &Context) -> bool {
-        self.expr.eval(ctx).is_truthy()
+    pub fn test(&mut self, ctx: &Context) -> bool {
+        self.machine.run(&self.program, ctx).is_truthy()
     }
-}
-
-#[derive(Debug)]
-struct Error(String);
-
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        &self.0
-    }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+    /// Returns warnings for identifiers rejected by `known`.
+    pub fn check<F>(&self, known: F) -> Vec<Warning>
+    where
+        F: Fn(Token) -> bool,
+    {
+        self.identifiers
+            .iter()
+            .filter(|(token, _)| !known(*token))
+            .map(|(token, span)| {
+                Warning::new(span.clone(), &format!("unknown identifier: {}", token))
+            })
+            .collect()
     }
 }
@@ -1,16 +1,21 @@
-use ast::Expr;
+use ast::{Expr, Pattern, Prefix, SetItem};
+use error;
 use genet_abi::{token::Token, variant::Variant};
 use hwaddr::HwAddr;
 use num_bigint::BigInt;
 use num_traits::Num;
 use pest::{
-    error::Error,
+    error::{Error, ErrorVariant},
     iterators::Pair,
     prec_climber::{Assoc, Operator, PrecClimber},
     Parser,
 };
 use serde_json;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::{
+    cell::RefCell,
+    net::{Ipv4Addr, Ipv6Addr},
+    ops::Range,
+};
 use variant::VariantExt;
 
 #[derive(Parser)]
@@ -18,64 +23,190 @@ use variant::VariantExt;
 pub struct FilterParser;
 
 pub fn parse(filter: &str) -> Result<Expr, Error<Rule>> {
-    let result = FilterParser::parse(Rule::filter, filter);
-    match result {
-        Ok(mut expr) => Ok(consume_expr(
-            expr.next().unwrap().into_inner().next().unwrap(),
-        )),
-        Err(e) => Err(e),
+    parse_with_identifiers(filter).map(|(expr, _)| expr)
+}
+
+/// Parses a filter and returns the expression with the byte range of each identifier.
+pub fn parse_with_identifiers(
+    filter: &str,
+) -> Result<(Expr, Vec<(Token, Range<usize>)>), Error<Rule>> {
+    let mut pairs = FilterParser::parse(Rule::filter, filter)?;
+    let expr = pairs.next().unwrap().into_inner().next().unwrap();
+    let identifiers = expr
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::member)
+        .map(|pair| {
+            let span = pair.as_span();
+            (Token::from(pair.as_str()), span.start()..span.end())
+        })
+        .collect();
+    consume_expr(expr).map(|expr| (expr, identifiers))
+}
+
+/// Parses the body of an `@` macro into a literal or a prefix.
+pub fn parse_macro(exp: &str) -> Option<Expr> {
+    if let Some(addr) = parse_addr(exp) {
+        return Some(Expr::Literal(Variant::Buffer(addr)));
+    }
+    if let Ok(addr) = exp.parse::<HwAddr>() {
+        return Some(Expr::Literal(Variant::Buffer(
+            addr.octets().to_vec().into_boxed_slice(),
+        )));
+    }
+    let mut cidr = exp.splitn(2, '/');
+    if let (Some(addr), Some(bits)) = (cidr.next(), cidr.next()) {
+        if let (Some(addr), Ok(bits)) = (parse_addr(addr), bits.parse::<usize>()) {
+            return Prefix::new(&addr, bits).map(Expr::Prefix);
+        }
+    }
+    None
+}
+
+/// Replaces the `@` macros in the expression with their parsed values.
+///
+/// Returns an error if any macro is invalid.
+pub fn expand_macros(expr: Expr) -> Result<Expr, error::Error> {
+    let invalid = RefCell::new(None);
+    let expr = expr.map(&|expr| match expr {
+        Expr::Macro(m) => parse_macro(&m).unwrap_or_else(|| {
+            invalid.borrow_mut().get_or_insert_with(|| m.clone());
+            Expr::Macro(m)
+        }),
+        expr => expr,
+    });
+    match invalid.into_inner() {
+        Some(m) => Err(error::Error::new(0..0, &format!("invalid macro: @{}", m))),
+        None => Ok(expr),
     }
 }
 
-fn parse_macro(exp: String) -> Expr {
+fn parse_addr(exp: &str) -> Option<Box<[u8]>> {
     if let Ok(addr) = exp.parse::<Ipv4Addr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+        return Some(addr.octets().to_vec().into_boxed_slice());
     }
     if let Ok(addr) = exp.parse::<Ipv6Addr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+        return Some(addr.octets().to_vec().into_boxed_slice());
     }
-    if let Ok(addr) = exp.parse::<HwAddr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+    None
+}
+
+fn parse_pattern(lhs: Expr, op: &Pair<Rule>, rhs: Expr) -> Result<Expr, Error<Rule>> {
+    let custom_error =
+        |message: String| Error::new_from_span(ErrorVariant::CustomError { message }, op.as_span());
+    if let Expr::Literal(Variant::String(re)) = rhs {
+        let pattern = Pattern::new(&re).map_err(|e| custom_error(format!("{}", e)))?;
+        Ok(Expr::Matches(Box::new(lhs), pattern))
+    } else {
+        Err(custom_error(
+            "matches requires a string literal pattern".to_string(),
+        ))
     }
-    Expr::Macro(exp)
 }
 
-fn consume_expr(pair: Pair<Rule>) -> Expr {
+/// An operand of the precedence climber; sets only appear on the right of `in`.
+enum Operand {
+    Expr(Expr),
+    Set(Vec<SetItem>),
+}
+
+impl Operand {
+    fn into_expr(self) -> Expr {
+        match self {
+            Operand::Expr(expr) => expr,
+            Operand::Set(_) => Expr::Literal(Variant::Nil),
+        }
+    }
+}
+
+fn consume_expr(pair: Pair<Rule>) -> Result<Expr, Error<Rule>> {
     let cmp = Operator::new(Rule::op_lt, Assoc::Left)
         | Operator::new(Rule::op_lte, Assoc::Left)
         | Operator::new(Rule::op_gt, Assoc::Left)
         | Operator::new(Rule::op_gte, Assoc::Left);
+    let eq = Operator::new(Rule::op_eq, Assoc::Left)
+        | Operator::new(Rule::op_ne, Assoc::Left)
+        | Operator::new(Rule::op_contains, Assoc::Left)
+        | Operator::new(Rule::op_matches, Assoc::Left)
+        | Operator::new(Rule::op_in, Assoc::Left);
     let climber = PrecClimber::new(vec![
-        cmp,
-        Operator::new(Rule::op_eq, Assoc::Left) | Operator::new(Rule::op_ne, Assoc::Left),
-        Operator::new(Rule::op_logical_and, Assoc::Left),
         Operator::new(Rule::op_logical_or, Assoc::Left),
+        Operator::new(Rule::op_logical_and, Assoc::Left),
+        eq,
+        cmp,
+        Operator::new(Rule::op_bit_or, Assoc::Left),
+        Operator::new(Rule::op_bit_xor, Assoc::Left),
+        Operator::new(Rule::op_bit_and, Assoc::Left),
+        Operator::new(Rule::op_shl, Assoc::Left) | Operator::new(Rule::op_shr, Assoc::Left),
+        Operator::new(Rule::op_add, Assoc::Left) | Operator::new(Rule::op_sub, Assoc::Left),
+        Operator::new(Rule::op_mul, Assoc::Left)
+            | Operator::new(Rule::op_div, Assoc::Left)
+            | Operator::new(Rule::op_rem, Assoc::Left),
     ]);
     let primary = |pair: Pair<Rule>| match pair.as_rule() {
-        Rule::primary => consume_primary(pair),
-        _ => Expr::Literal(Variant::Nil),
+        Rule::primary => consume_primary(pair).map(Operand::Expr),
+        Rule::set => consume_set(pair).map(Operand::Set),
+        _ => Ok(Operand::Expr(Expr::Literal(Variant::Nil))),
     };
-    let infix = |lhs: Expr, op: Pair<Rule>, rhs: Expr| -> Expr {
-        match op.as_rule() {
+    let infix = |lhs: Result<Operand, Error<Rule>>,
+                 op: Pair<Rule>,
+                 rhs: Result<Operand, Error<Rule>>|
+     -> Result<Operand, Error<Rule>> {
+        let lhs = lhs?.into_expr();
+        let rhs = match (op.as_rule(), rhs?) {
+            (Rule::op_in, Operand::Set(set)) => {
+                return Ok(Operand::Expr(Expr::In(Box::new(lhs), set)))
+            }
+            (_, rhs) => rhs.into_expr(),
+        };
+        Ok(Operand::Expr(match op.as_rule() {
             Rule::op_lt => Expr::CmpLt(Box::new(lhs), Box::new(rhs)),
             Rule::op_lte => Expr::CmpLte(Box::new(lhs), Box::new(rhs)),
             Rule::op_gt => Expr::CmpGt(Box::new(lhs), Box::new(rhs)),
             Rule::op_gte => Expr::CmpGte(Box::new(lhs), Box::new(rhs)),
             Rule::op_eq => Expr::CmpEq(Box::new(lhs), Box::new(rhs)),
             Rule::op_ne => Expr::CmpNotEq(Box::new(lhs), Box::new(rhs)),
+            Rule::op_contains => Expr::Contains(Box::new(lhs), Box::new(rhs)),
+            Rule::op_matches => parse_pattern(lhs, &op, rhs)?,
             Rule::op_logical_and => Expr::LogicalAnd(Box::new(lhs), Box::new(rhs)),
             Rule::op_logical_or => Expr::LogicalOr(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bit_and => Expr::BitAnd(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bit_or => Expr::BitOr(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bit_xor => Expr::BitXor(Box::new(lhs), Box::new(rhs)),
+            Rule::op_shl => Expr::Shl(Box::new(lhs), Box::new(rhs)),
+            Rule::op_shr => Expr::Shr(Box::new(lhs), Box::new(rhs)),
+            Rule::op_add => Expr::Add(Box::new(lhs), Box::new(rhs)),
+            Rule::op_sub => Expr::Sub(Box::new(lhs), Box::new(rhs)),
+            Rule::op_mul => Expr::Mul(Box::new(lhs), Box::new(rhs)),
+            Rule::op_div => Expr::Div(Box::new(lhs), Box::new(rhs)),
+            Rule::op_rem => Expr::Rem(Box::new(lhs), Box::new(rhs)),
             _ => Expr::Literal(Variant::Nil),
-        }
+        }))
     };
-    climber.climb(pair.into_inner(), primary, infix)
+    climber
+        .climb(pair.into_inner(), primary, infix)
+        .map(Operand::into_expr)
 }
 
-fn consume_primary(pair: Pair<Rule>) -> Expr {
+fn consume_set(pair: Pair<Rule>) -> Result<Vec<SetItem>, Error<Rule>> {
+    let mut set = Vec::new();
+    for item in pair.into_inner() {
+        let mut bounds = item.into_inner();
+        let start = consume_primary(bounds.next().unwrap())?;
+        set.push(match bounds.next() {
+            Some(end) => SetItem::Range(start, consume_primary(end)?),
+            None => SetItem::Value(start),
+        });
+    }
+    Ok(set)
+}
+
+fn consume_primary(pair: Pair<Rule>) -> Result<Expr, Error<Rule>> {
     let mut result = None;
     for item in pair.into_inner().rev() {
         result = Some(match item.as_rule() {
-            Rule::expression => consume_expr(item),
+            Rule::expression => consume_expr(item)?,
             Rule::op_unary_plus => Expr::UnaryPlus(Box::new(result.take().unwrap())),
             Rule::op_unary_negation => Expr::UnaryNegation(Box::new(result.take().unwrap())),
             Rule::op_logical_negation => Expr::LogicalNegation(Box::new(result.take().unwrap())),
@@ -98,7 +229,17 @@ fn consume_primary(pair: Pair<Rule>) -> Expr {
             Rule::string => Expr::Literal(Variant::String(
                 serde_json::from_str(item.as_str()).unwrap(),
             )),
-            Rule::macro_exp => parse_macro(item.as_str()[1..].to_string()),
+            Rule::macro_exp => match parse_macro(&item.as_str()[1..]) {
+                Some(expr) => expr,
+                None => {
+                    return Err(Error::new_from_span(
+                        ErrorVariant::CustomError {
+                            message: format!("invalid macro: {}", item.as_str()),
+                        },
+                        item.as_span(),
+                    ))
+                }
+            },
             Rule::float => Expr::Literal(Variant::Float64(item.as_str().parse().unwrap())),
             Rule::nil => Expr::Literal(Variant::Nil),
             Rule::boolean => Expr::Literal(Variant::Bool(item.as_str() == "true")),
@@ -106,15 +247,17 @@ fn consume_primary(pair: Pair<Rule>) -> Expr {
             _ => Expr::Literal(Variant::Nil),
         });
     }
-    result.unwrap()
+    Ok(result.unwrap())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use ast::Expr::*;
+    use context::Context;
     use genet_abi::{token::Token, variant::Variant};
     use num_bigint::BigInt;
+    use unparser::unparse;
 
     #[test]
     fn literal() {
@@ -272,6 +415,268 @@ mod tests {
         );
     }
 
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("a == 1 && b < 2 || c"),
+            Ok(LogicalOr(
+                Box::new(LogicalAnd(
+                    Box::new(CmpEq(
+                        Box::new(Token(Token::from("a"))),
+                        Box::new(Literal(Variant::UInt64(1)))
+                    )),
+                    Box::new(CmpLt(
+                        Box::new(Token(Token::from("b"))),
+                        Box::new(Literal(Variant::UInt64(2)))
+                    ))
+                )),
+                Box::new(Token(Token::from("c")))
+            ))
+        );
+    }
+
+    #[test]
+    fn matching() {
+        assert_eq!(
+            parse(r#"http.host contains "example""#),
+            Ok(Contains(
+                Box::new(Token(Token::from("http.host"))),
+                Box::new(Literal(Variant::String(
+                    "example".to_string().into_boxed_str()
+                )))
+            ))
+        );
+
+        assert_eq!(
+            parse(r#"http.host matches "^www\\.""#),
+            Ok(Matches(
+                Box::new(Token(Token::from("http.host"))),
+                Pattern::new("^www\\.").unwrap()
+            ))
+        );
+
+        assert_eq!(parse("containsx"), Ok(Token(Token::from("containsx"))));
+        assert!(parse(r#"http.host matches "(""#).is_err());
+        assert!(parse("http.host matches 1").is_err());
+    }
+
+    #[test]
+    fn set() {
+        assert_eq!(
+            parse("tcp.dst in {80, 443, 8000..8080} && tcp.src in {}"),
+            Ok(LogicalAnd(
+                Box::new(In(
+                    Box::new(Token(Token::from("tcp.dst"))),
+                    vec![
+                        SetItem::Value(Literal(Variant::UInt64(80))),
+                        SetItem::Value(Literal(Variant::UInt64(443))),
+                        SetItem::Range(
+                            Literal(Variant::UInt64(8000)),
+                            Literal(Variant::UInt64(8080))
+                        ),
+                    ]
+                )),
+                Box::new(In(Box::new(Token(Token::from("tcp.src"))), vec![]))
+            ))
+        );
+
+        assert_eq!(
+            parse("ipv4.src in {@10.0.0.1,@10.0.0.2}"),
+            Ok(In(
+                Box::new(Token(Token::from("ipv4.src"))),
+                vec![
+                    SetItem::Value(Literal(Variant::Buffer(Box::new([10, 0, 0, 1])))),
+                    SetItem::Value(Literal(Variant::Buffer(Box::new([10, 0, 0, 2])))),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse("ipv4.src in {@10.0.0.1..@10.0.0.5}"),
+            Ok(In(
+                Box::new(Token(Token::from("ipv4.src"))),
+                vec![SetItem::Range(
+                    Literal(Variant::Buffer(Box::new([10, 0, 0, 1]))),
+                    Literal(Variant::Buffer(Box::new([10, 0, 0, 5])))
+                )]
+            ))
+        );
+
+        for filter in &[
+            "tcp.dst in {80, 443, 8000..8080}",
+            "udp.dst in {-1..1} || udp.src in {}",
+        ] {
+            let expr = parse(filter).unwrap();
+            assert_eq!(unparse(&expr), *filter);
+            assert_eq!(parse(&unparse(&expr)), Ok(expr));
+        }
+
+        assert!(parse("tcp.dst in 80").is_err());
+        assert!(parse("tcp.dst in {80..}").is_err());
+        assert!(parse("{80}").is_err());
+    }
+
+    #[test]
+    fn macros() {
+        assert_eq!(
+            parse("@192.168.0.1"),
+            Ok(Literal(Variant::Buffer(Box::new([192, 168, 0, 1]))))
+        );
+        assert_eq!(
+            parse("@::1"),
+            Ok(Literal(Variant::Buffer(Box::new([
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
+            ]))))
+        );
+        assert_eq!(
+            parse("@00:1b:63:84:45:e6"),
+            Ok(Literal(Variant::Buffer(Box::new([
+                0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6
+            ]))))
+        );
+        assert_eq!(
+            parse("@10.1.2.3/12"),
+            Ok(Prefix(::ast::Prefix::new(&[10, 0, 0, 0], 12).unwrap()))
+        );
+
+        let prefix = ::ast::Prefix::new(&[10, 1, 2, 3], 12).unwrap();
+        assert_eq!(prefix.addr(), &[10, 0, 0, 0]);
+        assert!(prefix.contains(&Variant::Buffer(Box::new([10, 15, 255, 255]))));
+        assert!(!prefix.contains(&Variant::Buffer(Box::new([10, 16, 0, 0]))));
+        assert!(!prefix.contains(&Variant::Buffer(Box::new([10, 0, 0]))));
+        assert!(::ast::Prefix::new(&[0; 4], 0)
+            .unwrap()
+            .contains(&Variant::Buffer(Box::new([1, 2, 3, 4]))));
+
+        for filter in &[
+            "ipv4.src == @10.0.0.0/8",
+            "ipv6.dst in {@fe80::/10, @2001:db8::/32}",
+        ] {
+            let expr = parse(filter).unwrap();
+            assert_eq!(unparse(&expr), *filter);
+        }
+
+        assert!(parse("@10.0.0.0/33").is_err());
+        assert!(parse("@fe80::/129").is_err());
+        assert!(parse("@10.0.0.0/").is_err());
+        assert!(parse("@example").is_err());
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            parse("tcp.flags & 0x12 == 0x12"),
+            Ok(CmpEq(
+                Box::new(BitAnd(
+                    Box::new(Token(Token::from("tcp.flags"))),
+                    Box::new(Literal(Variant::UInt64(0x12)))
+                )),
+                Box::new(Literal(Variant::UInt64(0x12)))
+            ))
+        );
+
+        assert_eq!(
+            parse("a - b * 4 > 1000"),
+            Ok(CmpGt(
+                Box::new(Sub(
+                    Box::new(Token(Token::from("a"))),
+                    Box::new(Mul(
+                        Box::new(Token(Token::from("b"))),
+                        Box::new(Literal(Variant::UInt64(4)))
+                    ))
+                )),
+                Box::new(Literal(Variant::UInt64(1000)))
+            ))
+        );
+
+        let ctx = Context::new(&[]);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+        assert_eq!(eval("1 + 2 * 3"), Variant::UInt64(7));
+        assert_eq!(eval("(1 + 2) * 3"), Variant::UInt64(9));
+        assert_eq!(eval("10 - 3 - 2"), Variant::UInt64(5));
+        assert_eq!(eval("3 - 5"), Variant::Int64(-2));
+        assert_eq!(eval("17 / 5"), Variant::UInt64(3));
+        assert_eq!(eval("17 % 5"), Variant::UInt64(2));
+        assert_eq!(eval("1 / 0"), Variant::Nil);
+        assert_eq!(eval("1 % 0"), Variant::Nil);
+        assert_eq!(eval("1.5 * 2"), Variant::Float64(3.0));
+        assert_eq!(eval("0b1100 & 0b1010"), Variant::UInt64(0b1000));
+        assert_eq!(eval("0b1100 | 0b1010"), Variant::UInt64(0b1110));
+        assert_eq!(eval("0b1100 ^ 0b1010"), Variant::UInt64(0b0110));
+        assert_eq!(eval("1 | 2 ^ 3 & 4"), Variant::UInt64(3));
+        assert_eq!(eval("1 << 4 >> 2"), Variant::UInt64(4));
+        assert_eq!(eval("1 + 1 << 2"), Variant::UInt64(8));
+        assert_eq!(eval("-1 & 0xff"), Variant::UInt64(0xff));
+        assert_eq!(eval("1 << 99999"), Variant::Nil);
+        assert_eq!(eval("\"a\" + 1"), Variant::Nil);
+        assert_eq!(
+            eval("0xffffffffffffffff + 1"),
+            Variant::BigInt(
+                BigInt::from_str_radix("10000000000000000", 16)
+                    .unwrap()
+                    .to_signed_bytes_be()
+                    .into_boxed_slice()
+            )
+        );
+        assert_eq!(
+            eval("0xffffffffffffffff + 1 - 2"),
+            Variant::UInt64(0xffff_ffff_ffff_fffe)
+        );
+        assert_eq!(
+            eval("0xffffffffffffffff * 0xffffffffffffffff"),
+            Variant::BigInt(
+                BigInt::from_str_radix("fffffffffffffffe0000000000000001", 16)
+                    .unwrap()
+                    .to_signed_bytes_be()
+                    .into_boxed_slice()
+            )
+        );
+        assert_eq!(eval("-5 / 2"), Variant::Int64(-2));
+        assert_eq!(eval("-5 % 3"), Variant::Int64(-2));
+        assert_eq!(eval("-8 >> 1"), Variant::Int64(-4));
+        assert_eq!(eval("-1 << 63"), Variant::Int64(i64::min_value()));
+        assert_eq!(eval("@10.0.0.2 > @10.0.0.1"), Variant::Bool(true));
+        assert_eq!(eval("@0.0.1.0 == 256"), Variant::Bool(true));
+
+        for filter in &[
+            "(a + b) * c",
+            "a - (b - c)",
+            "-(a + b) & c == 0",
+            "!(a || b) && (c || d)",
+            "a == 1 == (b == 2)",
+        ] {
+            let expr = parse(filter).unwrap();
+            assert_eq!(unparse(&expr), *filter);
+        }
+    }
+
+    #[test]
+    fn diagnostics() {
+        let err = ::Filter::compile("tcp.dst == ").unwrap_err();
+        assert_eq!(err.span(), 11..11);
+        assert_eq!(err.expected(), &["operand".to_string()]);
+
+        let err = ::Filter::compile("tcp.dst 80").unwrap_err();
+        assert_eq!(err.span(), 8..8);
+        assert!(err.expected().contains(&"operator".to_string()));
+        assert!(err.expected().contains(&"end of filter".to_string()));
+
+        let err = ::Filter::compile(r#"http.host matches "(""#).unwrap_err();
+        assert_eq!(err.span(), 10..17);
+        assert!(err.expected().is_empty());
+
+        let err = ::Filter::compile("ipv4.src == @10.0.0.0/40").unwrap_err();
+        assert_eq!(err.span(), 12..24);
+        assert_eq!(err.message(), "invalid macro: @10.0.0.0/40");
+
+        let filter = ::Filter::compile("eth && (ipv4.src == 1 || ipv4.srcc)").unwrap();
+        let known = [Token::from("eth"), Token::from("ipv4.src")];
+        let warnings = filter.check(|token| known.contains(&token));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span(), 25..34);
+        assert_eq!(warnings[0].message(), "unknown identifier: ipv4.srcc");
+    }
+
     #[test]
     fn error() {
         assert!(parse("| 12.5").is_err());
@@ -0,0 +1,501 @@
+use ast::{Expr, Pattern, Prefix, SetItem};
+use context::Context;
+use error::Error;
+use genet_abi::{token::Token, variant::Variant};
+use parser::expand_macros;
+use variant::VariantExt;
+
+#[derive(Clone, Debug)]
+enum SetEntry {
+    Value(Variant),
+    Range(Variant, Variant),
+    Prefix(Prefix),
+}
+
+impl SetEntry {
+    fn contains(&self, var: &Variant) -> bool {
+        match self {
+            SetEntry::Value(v) => var.op_eq(v),
+            SetEntry::Range(start, end) => var.op_gte(start) && var.op_lte(end),
+            SetEntry::Prefix(p) => p.contains(var),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BinaryOp {
+    Eq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
+    Contains,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    fn apply(self, l: &Variant, r: &Variant) -> Variant {
+        match self {
+            BinaryOp::Eq => Variant::Bool(l.op_eq(r)),
+            BinaryOp::Lt => Variant::Bool(l.op_lt(r)),
+            BinaryOp::Gt => Variant::Bool(l.op_gt(r)),
+            BinaryOp::Lte => Variant::Bool(l.op_lte(r)),
+            BinaryOp::Gte => Variant::Bool(l.op_gte(r)),
+            BinaryOp::Contains => Variant::Bool(l.op_contains(r)),
+            BinaryOp::BitAnd => l.op_bit_and(r),
+            BinaryOp::BitOr => l.op_bit_or(r),
+            BinaryOp::BitXor => l.op_bit_xor(r),
+            BinaryOp::Shl => l.op_shl(r),
+            BinaryOp::Shr => l.op_shr(r),
+            BinaryOp::Add => l.op_add(r),
+            BinaryOp::Sub => l.op_sub(r),
+            BinaryOp::Mul => l.op_mul(r),
+            BinaryOp::Div => l.op_div(r),
+            BinaryOp::Rem => l.op_rem(r),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Const(Variant),
+    Load(usize),
+    Eval(Box<Expr>),
+    Truthy,
+    Not,
+    Plus,
+    Neg,
+    Matches(Pattern),
+    Prefix(Prefix),
+    In(Vec<SetEntry>),
+    Binary(BinaryOp),
+    /// A binary operation with a constant right operand, applied in place.
+    BinaryConst(BinaryOp, Variant),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+}
+
+/// A filter expression compiled into a flat instruction sequence.
+#[derive(Clone, Debug)]
+pub struct Program {
+    ops: Vec<Op>,
+    tokens: Vec<Token>,
+    /// True for tokens loaded more than once, whose values are cached during a run.
+    shared: Vec<bool>,
+}
+
+impl Program {
+    pub fn compile(expr: &Expr) -> Result<Program, Error> {
+        let mut program = Program {
+            ops: Vec::new(),
+            tokens: Vec::new(),
+            shared: Vec::new(),
+        };
+        program.emit(&expand_macros(expr.clone())?);
+        Ok(program)
+    }
+
+    fn emit(&mut self, expr: &Expr) {
+        if is_const(expr) {
+            let ctx = Context::new(&[]);
+            self.ops.push(Op::Const(expr.eval(&ctx)));
+            return;
+        }
+        match expr {
+            Expr::Token(t) => {
+                let slot = match self.tokens.iter().position(|token| token == t) {
+                    Some(slot) => {
+                        self.shared[slot] = true;
+                        slot
+                    }
+                    None => {
+                        self.tokens.push(*t);
+                        self.shared.push(false);
+                        self.tokens.len() - 1
+                    }
+                };
+                self.ops.push(Op::Load(slot));
+            }
+            Expr::CmpEq(l, r) => self.emit_eq(l, r),
+            Expr::CmpNotEq(l, r) => {
+                self.emit_eq(l, r);
+                self.ops.push(Op::Not);
+            }
+            Expr::CmpLt(l, r) => self.emit_binary(l, r, BinaryOp::Lt),
+            Expr::CmpGt(l, r) => self.emit_binary(l, r, BinaryOp::Gt),
+            Expr::CmpLte(l, r) => self.emit_binary(l, r, BinaryOp::Lte),
+            Expr::CmpGte(l, r) => self.emit_binary(l, r, BinaryOp::Gte),
+            Expr::Contains(l, r) => self.emit_binary(l, r, BinaryOp::Contains),
+            Expr::Matches(l, p) => {
+                self.emit(l);
+                self.ops.push(Op::Matches(p.clone()));
+            }
+            Expr::In(l, set) => {
+                let entries = set
+                    .iter()
+                    .map(|item| match item {
+                        SetItem::Value(Expr::Prefix(p)) => Some(SetEntry::Prefix(p.clone())),
+                        SetItem::Value(v) if is_const(v) => Some(SetEntry::Value(constant(v))),
+                        SetItem::Range(start, end) if is_const(start) && is_const(end) => {
+                            Some(SetEntry::Range(constant(start), constant(end)))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(entries) = entries {
+                    self.emit(l);
+                    self.ops.push(Op::In(entries));
+                } else {
+                    self.ops.push(Op::Eval(Box::new(expr.clone())));
+                }
+            }
+            Expr::LogicalAnd(l, r) => self.emit_logical(l, r, false),
+            Expr::LogicalOr(l, r) => self.emit_logical(l, r, true),
+            Expr::LogicalNegation(v) => {
+                self.emit(v);
+                self.ops.push(Op::Not);
+            }
+            Expr::UnaryPlus(v) => {
+                self.emit(v);
+                self.ops.push(Op::Plus);
+            }
+            Expr::UnaryNegation(v) => {
+                self.emit(v);
+                self.ops.push(Op::Neg);
+            }
+            Expr::BitAnd(l, r) => self.emit_binary(l, r, BinaryOp::BitAnd),
+            Expr::BitOr(l, r) => self.emit_binary(l, r, BinaryOp::BitOr),
+            Expr::BitXor(l, r) => self.emit_binary(l, r, BinaryOp::BitXor),
+            Expr::Shl(l, r) => self.emit_binary(l, r, BinaryOp::Shl),
+            Expr::Shr(l, r) => self.emit_binary(l, r, BinaryOp::Shr),
+            Expr::Add(l, r) => self.emit_binary(l, r, BinaryOp::Add),
+            Expr::Sub(l, r) => self.emit_binary(l, r, BinaryOp::Sub),
+            Expr::Mul(l, r) => self.emit_binary(l, r, BinaryOp::Mul),
+            Expr::Div(l, r) => self.emit_binary(l, r, BinaryOp::Div),
+            Expr::Rem(l, r) => self.emit_binary(l, r, BinaryOp::Rem),
+            _ => self.ops.push(Op::Eval(Box::new(expr.clone()))),
+        }
+    }
+
+    fn emit_binary(&mut self, l: &Expr, r: &Expr, op: BinaryOp) {
+        self.emit(l);
+        if is_const(r) {
+            self.ops.push(Op::BinaryConst(op, constant(r)));
+        } else {
+            self.emit(r);
+            self.ops.push(Op::Binary(op));
+        }
+    }
+
+    fn emit_eq(&mut self, l: &Expr, r: &Expr) {
+        match (l, r) {
+            (Expr::Prefix(p), e) | (e, Expr::Prefix(p)) => {
+                self.emit(e);
+                self.ops.push(Op::Prefix(p.clone()));
+            }
+            (l, r) => self.emit_binary(l, r, BinaryOp::Eq),
+        }
+    }
+
+    /// Emits `l && r` or `l || r`, skipping `r` once the result is known.
+    fn emit_logical(&mut self, l: &Expr, r: &Expr, or: bool) {
+        if is_const(l) {
+            if constant(l).is_truthy() == or {
+                self.ops.push(Op::Const(Variant::Bool(or)));
+            } else {
+                self.emit(r);
+                self.ops.push(Op::Truthy);
+            }
+            return;
+        }
+        self.emit(l);
+        let jump = self.ops.len();
+        self.ops.push(Op::Const(Variant::Nil));
+        self.emit(r);
+        self.ops.push(Op::Truthy);
+        let end = self.ops.len();
+        self.ops[jump] = if or {
+            Op::JumpIfTrue(end)
+        } else {
+            Op::JumpIfFalse(end)
+        };
+    }
+}
+
+/// Upper bound of lookups remembered per token.
+const MAX_LOOKUPS: usize = 64;
+
+/// A token resolved against a layer class.
+#[derive(Clone, Debug)]
+struct Lookup {
+    class: usize,
+    target: Token,
+    index: Option<usize>,
+}
+
+/// Reusable evaluation state for a Program.
+///
+/// Lookups are kept across runs, so a Machine must only be used with one Program.
+#[derive(Clone, Debug, Default)]
+pub struct Machine {
+    stack: Vec<Variant>,
+    cache: Vec<Option<Variant>>,
+    lookups: Vec<Vec<Lookup>>,
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine::default()
+    }
+
+    /// Evaluates the program against a frame.
+    pub fn run(&mut self, program: &Program, ctx: &Context) -> Variant {
+        self.stack.clear();
+        if self.cache.len() != program.tokens.len() {
+            self.cache.resize(program.tokens.len(), None);
+            self.lookups.resize(program.tokens.len(), Vec::new());
+        }
+        for var in &mut self.cache {
+            *var = None;
+        }
+
+        let ops = &program.ops;
+        let mut pc = 0;
+        while pc < ops.len() {
+            match &ops[pc] {
+                Op::Const(v) => self.stack.push(v.clone()),
+                Op::Load(slot) => {
+                    let var = self.load(program, *slot, ctx);
+                    self.stack.push(var);
+                }
+                Op::Eval(expr) => self.stack.push(expr.eval(ctx)),
+                Op::Truthy => self.unary(|v| Variant::Bool(v.is_truthy())),
+                Op::Not => self.unary(|v| Variant::Bool(!v.is_truthy())),
+                Op::Plus => self.unary(|v| v.op_unary_plus()),
+                Op::Neg => self.unary(|v| v.op_unary_negation()),
+                Op::Matches(p) => self.unary(|v| Variant::Bool(v.op_matches(p))),
+                Op::Prefix(p) => self.unary(|v| Variant::Bool(p.contains(v))),
+                Op::In(set) => self.unary(|v| Variant::Bool(set.iter().any(|e| e.contains(v)))),
+                Op::Binary(op) => self.binary(|l, r| op.apply(l, r)),
+                Op::BinaryConst(op, r) => self.unary(|l| op.apply(l, r)),
+                Op::JumpIfFalse(end) => {
+                    if self.jump(false) {
+                        pc = *end;
+                        continue;
+                    }
+                }
+                Op::JumpIfTrue(end) => {
+                    if self.jump(true) {
+                        pc = *end;
+                        continue;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        self.stack.pop().unwrap_or(Variant::Nil)
+    }
+
+    /// Pops the top of the stack, or replaces it with the boolean result if it equals `when`.
+    fn jump(&mut self, when: bool) -> bool {
+        let top = self.stack.pop().unwrap_or(Variant::Nil);
+        if top.is_truthy() == when {
+            self.stack.push(Variant::Bool(when));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unary<F: FnOnce(&Variant) -> Variant>(&mut self, f: F) {
+        if let Some(v) = self.stack.last_mut() {
+            *v = f(v);
+        }
+    }
+
+    fn binary<F: FnOnce(&Variant, &Variant) -> Variant>(&mut self, f: F) {
+        let r = self.stack.pop().unwrap_or(Variant::Nil);
+        if let Some(l) = self.stack.last_mut() {
+            *l = f(l, &r);
+        }
+    }
+
+    /// Looks up a token in the frame.
+    ///
+    /// Values of tokens loaded more than once are cached until the next run.
+    fn load(&mut self, program: &Program, slot: usize, ctx: &Context) -> Variant {
+        if let Some(var) = &self.cache[slot] {
+            return var.clone();
+        }
+        let token = program.tokens[slot];
+        let mut result = Variant::Nil;
+        for layer in ctx.layers().iter().rev() {
+            if layer.id() == token {
+                result = Variant::Bool(true);
+                break;
+            }
+            // Layer classes are never freed, so their addresses identify them.
+            let class = layer.class().as_ptr() as usize;
+            let headers = layer.headers();
+            let lookups = &mut self.lookups[slot];
+            let lookup = match lookups.iter().find(|l| l.class == class) {
+                Some(lookup) => lookup.clone(),
+                None => {
+                    let target = layer.resolve_alias(token);
+                    let lookup = Lookup {
+                        class,
+                        target,
+                        index: headers.iter().position(|a| a.id() == target),
+                    };
+                    if lookups.len() < MAX_LOOKUPS {
+                        lookups.push(lookup.clone());
+                    }
+                    lookup
+                }
+            };
+            let attr = match lookup.index {
+                Some(index) => Some(&headers[index]),
+                None => layer.attrs().iter().find(|a| a.id() == lookup.target),
+            };
+            if let Some(attr) = attr {
+                if let Ok(val) = attr.try_get(layer) {
+                    result = val;
+                    break;
+                }
+            }
+        }
+        if result == Variant::Nil {
+            if let Some(val) = ctx.expert(token) {
+                result = val;
+            }
+        }
+        if program.shared[slot] {
+            self.cache[slot] = Some(result.clone());
+        }
+        result
+    }
+}
+
+fn is_const(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) | Expr::Macro(_) | Expr::Prefix(_) => true,
+        Expr::Token(_) => false,
+        Expr::Matches(v, _)
+        | Expr::LogicalNegation(v)
+        | Expr::UnaryPlus(v)
+        | Expr::UnaryNegation(v) => is_const(v),
+        Expr::In(v, set) => {
+            is_const(v)
+                && set.iter().all(|item| match item {
+                    SetItem::Value(v) => is_const(v),
+                    SetItem::Range(start, end) => is_const(start) && is_const(end),
+                })
+        }
+        Expr::CmpEq(l, r)
+        | Expr::CmpNotEq(l, r)
+        | Expr::CmpLt(l, r)
+        | Expr::CmpGt(l, r)
+        | Expr::CmpLte(l, r)
+        | Expr::CmpGte(l, r)
+        | Expr::Contains(l, r)
+        | Expr::LogicalAnd(l, r)
+        | Expr::LogicalOr(l, r)
+        | Expr::BitAnd(l, r)
+        | Expr::BitOr(l, r)
+        | Expr::BitXor(l, r)
+        | Expr::Shl(l, r)
+        | Expr::Shr(l, r)
+        | Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Rem(l, r) => is_const(l) && is_const(r),
+    }
+}
+
+fn constant(expr: &Expr) -> Variant {
+    expr.eval(&Context::new(&[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+    };
+    use parser::parse;
+
+    fn attr(id: &'static str, value: Variant) -> Fixed<Attr> {
+        let class = Fixed::new(AttrClass::builder(id).value(value).build());
+        Fixed::new(Attr::builder(class).build())
+    }
+
+    #[test]
+    fn compile() {
+        let class = Fixed::new(
+            LayerClass::builder("ipv4")
+                .alias("_.proto", "ipv4.proto")
+                .header(attr("ipv4.proto", Variant::UInt64(6)))
+                .header(attr("ipv4.ttl", Variant::UInt64(64)))
+                .header(attr("ipv4.src", Variant::Buffer(vec![10, 0, 0, 1].into())))
+                .build(),
+        );
+        let layers = vec![MutFixed::new(Layer::new(class, ByteSlice::new()))];
+        let ctx = Context::new(&layers);
+
+        let filters = [
+            "ipv4",
+            "!ipv6",
+            "_.proto == 6",
+            "ipv4.ttl - 4 == 60",
+            "ipv4.ttl > 10 && ipv4.ttl < 100",
+            "ipv4.ttl == 1 || _.proto in {6, 17}",
+            "ipv4.src == @10.0.0.0/8",
+            "ipv4.src in {@192.168.0.0/16, @10.0.0.1}",
+            "ipv4.ttl in {1..10, ipv4.proto}",
+            "1 + 2 * 3 == 7 && ipv4",
+            "false && ipv4",
+            "true || ipv6",
+            "ipv6.src",
+        ];
+        let mut machine = Machine::new();
+        for filter in filters.iter() {
+            let expr = parse(filter).unwrap();
+            let program = Program::compile(&expr).unwrap();
+            assert_eq!(machine.run(&program, &ctx), expr.eval(&ctx), "{}", filter);
+            machine = Machine::new();
+        }
+
+        let expr = Expr::CmpEq(
+            Box::new(Expr::Token(Token::from("ipv4.src"))),
+            Box::new(Expr::Macro("10.0.0.1".to_string())),
+        );
+        let program = Program::compile(&expr).unwrap();
+        assert_eq!(program.ops.len(), 2);
+        assert_eq!(machine.run(&program, &ctx), Variant::Bool(true));
+
+        let expr = Expr::CmpEq(
+            Box::new(Expr::Token(Token::from("ipv4.src"))),
+            Box::new(Expr::Macro("10.0.0".to_string())),
+        );
+        let err = Program::compile(&expr).unwrap_err();
+        assert_eq!(err.message(), "invalid macro: @10.0.0");
+
+        let program = Program::compile(&parse("1 + 2 * 3 == 7").unwrap()).unwrap();
+        assert_eq!(program.ops.len(), 1);
+        let program = Program::compile(&parse("false && ipv4").unwrap()).unwrap();
+        assert_eq!(program.ops.len(), 1);
+    }
+}
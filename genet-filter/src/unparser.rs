@@ -1,6 +1,7 @@
-use ast::Expr;
+use ast::{Expr, SetItem};
 use genet_abi::{token::Token, variant::Variant};
 use hwaddr::HwAddr;
+use serde_json;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use variant::VariantExt;
 
@@ -32,28 +33,133 @@ pub fn unparse(expr: &Expr) -> String {
         Expr::Literal(var) => var.to_string(),
         Expr::Token(t) => t.to_string(),
         Expr::Macro(expr) => format!("@{}", expr),
+        Expr::Prefix(prefix) => {
+            let addr = prefix.addr();
+            match addr.len() {
+                4 => format!(
+                    "@{}/{}",
+                    Ipv4Addr::from(*array_ref![addr, 0, 4]),
+                    prefix.bits()
+                ),
+                16 => format!(
+                    "@{}/{}",
+                    Ipv6Addr::from(*array_ref![addr, 0, 16]),
+                    prefix.bits()
+                ),
+                _ => Variant::Buffer(addr.into()).to_string(),
+            }
+        }
         Expr::CmpEq(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
             (lhs, &Expr::Literal(Variant::Bool(true))) => unparse(lhs),
-            (lhs, &Expr::Literal(Variant::Bool(false))) => format!("!{}", unparse(lhs)),
+            (lhs, &Expr::Literal(Variant::Bool(false))) => format!("!{}", unparse_primary(lhs)),
             (&Expr::Literal(Variant::Bool(true)), rhs) => unparse(rhs),
-            (&Expr::Literal(Variant::Bool(false)), rhs) => format!("!{}", unparse(rhs)),
-            (lhs, rhs) => format!("{} == {}", unparse(lhs), unparse(rhs)),
+            (&Expr::Literal(Variant::Bool(false)), rhs) => format!("!{}", unparse_primary(rhs)),
+            (lhs, rhs) => unparse_binary("==", expr, lhs, rhs),
         },
         Expr::CmpNotEq(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
             (lhs, &Expr::Literal(Variant::Bool(false))) => unparse(lhs),
-            (lhs, &Expr::Literal(Variant::Bool(true))) => format!("!{}", unparse(lhs)),
+            (lhs, &Expr::Literal(Variant::Bool(true))) => format!("!{}", unparse_primary(lhs)),
             (&Expr::Literal(Variant::Bool(false)), rhs) => unparse(rhs),
-            (&Expr::Literal(Variant::Bool(true)), rhs) => format!("!{}", unparse(rhs)),
-            (lhs, rhs) => format!("{} != {}", unparse(lhs), unparse(rhs)),
+            (&Expr::Literal(Variant::Bool(true)), rhs) => format!("!{}", unparse_primary(rhs)),
+            (lhs, rhs) => unparse_binary("!=", expr, lhs, rhs),
         },
-        Expr::CmpLt(lhs, rhs) => format!("{} < {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpGt(lhs, rhs) => format!("{} > {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpLte(lhs, rhs) => format!("{} <= {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpGte(lhs, rhs) => format!("{} >= {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalAnd(lhs, rhs) => format!("{} && {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalOr(lhs, rhs) => format!("{} || {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalNegation(expr) => format!("!{}", unparse(expr)),
-        Expr::UnaryPlus(expr) => format!("+{}", unparse(expr)),
-        Expr::UnaryNegation(expr) => format!("-{}", unparse(expr)),
+        Expr::CmpLt(lhs, rhs) => unparse_binary("<", expr, lhs, rhs),
+        Expr::CmpGt(lhs, rhs) => unparse_binary(">", expr, lhs, rhs),
+        Expr::CmpLte(lhs, rhs) => unparse_binary("<=", expr, lhs, rhs),
+        Expr::CmpGte(lhs, rhs) => unparse_binary(">=", expr, lhs, rhs),
+        Expr::Contains(lhs, rhs) => unparse_binary("contains", expr, lhs, rhs),
+        Expr::Matches(lhs, pattern) => format!(
+            "{} matches {}",
+            unparse_operand(expr, lhs),
+            serde_json::to_string(pattern.as_str()).unwrap()
+        ),
+        Expr::In(lhs, set) => format!(
+            "{} in {{{}}}",
+            unparse_operand(expr, lhs),
+            set.iter()
+                .map(|item| match item {
+                    SetItem::Value(v) => unparse_primary(v),
+                    SetItem::Range(start, end) => {
+                        format!("{}..{}", unparse_primary(start), unparse_primary(end))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expr::LogicalAnd(lhs, rhs) => unparse_binary("&&", expr, lhs, rhs),
+        Expr::LogicalOr(lhs, rhs) => unparse_binary("||", expr, lhs, rhs),
+        Expr::BitAnd(lhs, rhs) => unparse_binary("&", expr, lhs, rhs),
+        Expr::BitOr(lhs, rhs) => unparse_binary("|", expr, lhs, rhs),
+        Expr::BitXor(lhs, rhs) => unparse_binary("^", expr, lhs, rhs),
+        Expr::Shl(lhs, rhs) => unparse_binary("<<", expr, lhs, rhs),
+        Expr::Shr(lhs, rhs) => unparse_binary(">>", expr, lhs, rhs),
+        Expr::Add(lhs, rhs) => unparse_binary("+", expr, lhs, rhs),
+        Expr::Sub(lhs, rhs) => unparse_binary("-", expr, lhs, rhs),
+        Expr::Mul(lhs, rhs) => unparse_binary("*", expr, lhs, rhs),
+        Expr::Div(lhs, rhs) => unparse_binary("/", expr, lhs, rhs),
+        Expr::Rem(lhs, rhs) => unparse_binary("%", expr, lhs, rhs),
+        Expr::LogicalNegation(expr) => format!("!{}", unparse_primary(expr)),
+        Expr::UnaryPlus(expr) => format!("+{}", unparse_primary(expr)),
+        Expr::UnaryNegation(expr) => format!("-{}", unparse_primary(expr)),
+    }
+}
+
+const PRIMARY: u8 = 11;
+
+/// Returns the binding power of the unparsed expression; higher binds tighter.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::CmpEq(lhs, rhs) => precedence_cmp(lhs, rhs, true),
+        Expr::CmpNotEq(lhs, rhs) => precedence_cmp(lhs, rhs, false),
+        Expr::LogicalOr(..) => 1,
+        Expr::LogicalAnd(..) => 2,
+        Expr::Contains(..) | Expr::Matches(..) | Expr::In(..) => 3,
+        Expr::CmpLt(..) | Expr::CmpGt(..) | Expr::CmpLte(..) | Expr::CmpGte(..) => 4,
+        Expr::BitOr(..) => 5,
+        Expr::BitXor(..) => 6,
+        Expr::BitAnd(..) => 7,
+        Expr::Shl(..) | Expr::Shr(..) => 8,
+        Expr::Add(..) | Expr::Sub(..) => 9,
+        Expr::Mul(..) | Expr::Div(..) | Expr::Rem(..) => 10,
+        _ => PRIMARY,
+    }
+}
+
+/// Comparisons against a boolean literal are unparsed as the operand itself or its negation.
+fn precedence_cmp(lhs: &Expr, rhs: &Expr, eq: bool) -> u8 {
+    match (lhs, rhs) {
+        (e, &Expr::Literal(Variant::Bool(b))) | (&Expr::Literal(Variant::Bool(b)), e) => {
+            if b == eq {
+                precedence(e)
+            } else {
+                PRIMARY
+            }
+        }
+        _ => 3,
+    }
+}
+
+fn unparse_binary(op: &str, parent: &Expr, lhs: &Expr, rhs: &Expr) -> String {
+    let rhs = if precedence(rhs) <= precedence(parent) {
+        format!("({})", unparse(rhs))
+    } else {
+        unparse(rhs)
+    };
+    format!("{} {} {}", unparse_operand(parent, lhs), op, rhs)
+}
+
+fn unparse_operand(parent: &Expr, expr: &Expr) -> String {
+    if precedence(expr) < precedence(parent) {
+        format!("({})", unparse(expr))
+    } else {
+        unparse(expr)
+    }
+}
+
+fn unparse_primary(expr: &Expr) -> String {
+    if precedence(expr) < PRIMARY {
+        format!("({})", unparse(expr))
+    } else {
+        unparse(expr)
     }
 }
@@ -1,8 +1,9 @@
 use genet_abi::variant::Variant;
 use num_bigint::{BigInt, Sign};
-use num_traits::ToPrimitive;
+use num_traits::{ToPrimitive, Zero};
+use regex::bytes::Regex;
 use serde_json;
-use std::cmp::Ordering;
+use std::{borrow::Cow, cmp::Ordering};
 
 pub trait VariantExt {
     fn shrink(self) -> Variant;
@@ -15,6 +16,21 @@ pub trait VariantExt {
     fn op_lte(&self, other: &Variant) -> bool;
     fn op_gte(&self, other: &Variant) -> bool;
     fn op_eq(&self, other: &Variant) -> bool;
+    fn op_contains(&self, other: &Variant) -> bool;
+    fn op_bit_and(&self, other: &Variant) -> Variant;
+    fn op_bit_or(&self, other: &Variant) -> Variant;
+    fn op_bit_xor(&self, other: &Variant) -> Variant;
+    fn op_shl(&self, other: &Variant) -> Variant;
+    fn op_shr(&self, other: &Variant) -> Variant;
+    fn op_add(&self, other: &Variant) -> Variant;
+    fn op_sub(&self, other: &Variant) -> Variant;
+    fn op_mul(&self, other: &Variant) -> Variant;
+    fn op_div(&self, other: &Variant) -> Variant;
+    fn op_rem(&self, other: &Variant) -> Variant;
+    fn op_matches(&self, re: &Regex) -> bool;
+    fn as_bytes(&self) -> Option<&[u8]>;
+    fn to_bigint(&self) -> Option<BigInt>;
+    fn to_f64(&self) -> Option<f64>;
     fn to_string(&self) -> String;
 }
 
@@ -62,34 +78,13 @@ impl VariantExt for Variant {
     }
 
     fn ord(&self, other: &Variant) -> Option<Ordering> {
-        let lhs = match self {
-            Variant::Buffer(v) => Variant::BigInt(
-                BigInt::from_bytes_be(Sign::Plus, &v)
-                    .to_signed_bytes_be()
-                    .into_boxed_slice(),
-            ),
-            Variant::Slice(v) => Variant::BigInt(
-                BigInt::from_bytes_be(Sign::Plus, &v)
-                    .to_signed_bytes_be()
-                    .into_boxed_slice(),
-            ),
-            _ => self.clone(),
-        };
-        let rhs = match other {
-            Variant::Buffer(v) => Variant::BigInt(
-                BigInt::from_bytes_be(Sign::Plus, &v)
-                    .to_signed_bytes_be()
-                    .into_boxed_slice(),
-            ),
-            Variant::Slice(v) => Variant::BigInt(
-                BigInt::from_bytes_be(Sign::Plus, &v)
-                    .to_signed_bytes_be()
-                    .into_boxed_slice(),
-            ),
-            _ => other.clone(),
-        };
+        if let (Some(a), Some(b)) = (unsigned_bytes(self), unsigned_bytes(other)) {
+            return Some(cmp_unsigned(a, b));
+        }
+        let lhs = as_number(self);
+        let rhs = as_number(other);
 
-        match (&lhs, &rhs) {
+        match (&*lhs, &*rhs) {
             (Variant::String(a), Variant::String(b)) => a.partial_cmp(b),
             (Variant::Int64(a), Variant::Int64(b)) => a.partial_cmp(b),
             (Variant::UInt64(a), Variant::UInt64(b)) => a.partial_cmp(b),
@@ -164,6 +159,140 @@ impl VariantExt for Variant {
         }
     }
 
+    fn op_contains(&self, other: &Variant) -> bool {
+        if let (Variant::String(a), Variant::String(b)) = (self, other) {
+            return a.contains(&**b);
+        }
+        match (self.as_bytes(), other.as_bytes()) {
+            (Some(a), Some(b)) => b.is_empty() || a.windows(b.len()).any(|w| w == b),
+            _ => false,
+        }
+    }
+
+    fn op_matches(&self, re: &Regex) -> bool {
+        self.as_bytes().map_or(false, |b| re.is_match(b))
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Variant::String(s) => Some(s.as_bytes()),
+            Variant::Buffer(b) => Some(b),
+            Variant::Slice(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn op_bit_and(&self, other: &Variant) -> Variant {
+        integer_op(self, other, |a, b| Some(a & b), |a, b| Some(a & b))
+    }
+
+    fn op_bit_or(&self, other: &Variant) -> Variant {
+        integer_op(self, other, |a, b| Some(a | b), |a, b| Some(a | b))
+    }
+
+    fn op_bit_xor(&self, other: &Variant) -> Variant {
+        integer_op(self, other, |a, b| Some(a ^ b), |a, b| Some(a ^ b))
+    }
+
+    fn op_shl(&self, other: &Variant) -> Variant {
+        integer_op(
+            self,
+            other,
+            |a, b| shift_amount(&b).map(|b| a << b),
+            |a, b| {
+                // Operands are at most 64 bits wide, so this cannot overflow.
+                if b >= 0 && b < 64 {
+                    Some(a << b)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    fn op_shr(&self, other: &Variant) -> Variant {
+        integer_op(
+            self,
+            other,
+            |a, b| shift_amount(&b).map(|b| a >> b),
+            |a, b| {
+                if a >= 0 && b >= 0 && b < 128 {
+                    Some(a >> b)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    fn op_add(&self, other: &Variant) -> Variant {
+        numeric_op(
+            self,
+            other,
+            |a, b| Some(a + b),
+            |a, b| a.checked_add(b),
+            |a, b| a + b,
+        )
+    }
+
+    fn op_sub(&self, other: &Variant) -> Variant {
+        numeric_op(
+            self,
+            other,
+            |a, b| Some(a - b),
+            |a, b| a.checked_sub(b),
+            |a, b| a - b,
+        )
+    }
+
+    fn op_mul(&self, other: &Variant) -> Variant {
+        numeric_op(
+            self,
+            other,
+            |a, b| Some(a * b),
+            |a, b| a.checked_mul(b),
+            |a, b| a * b,
+        )
+    }
+
+    fn op_div(&self, other: &Variant) -> Variant {
+        numeric_op(
+            self,
+            other,
+            |a, b| if b.is_zero() { None } else { Some(a / b) },
+            |a, b| a.checked_div(b),
+            |a, b| a / b,
+        )
+    }
+
+    fn op_rem(&self, other: &Variant) -> Variant {
+        numeric_op(
+            self,
+            other,
+            |a, b| if b.is_zero() { None } else { Some(a % b) },
+            |a, b| a.checked_rem(b),
+            |a, b| a % b,
+        )
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Variant::Int64(v) => Some(BigInt::from(*v)),
+            Variant::UInt64(v) => Some(BigInt::from(*v)),
+            Variant::BigInt(v) => Some(BigInt::from_signed_bytes_be(v)),
+            Variant::Buffer(v) => Some(BigInt::from_bytes_be(Sign::Plus, v)),
+            Variant::Slice(v) => Some(BigInt::from_bytes_be(Sign::Plus, v)),
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            Variant::Float64(v) => Some(*v),
+            _ => self.to_bigint().and_then(|v| v.to_f64()),
+        }
+    }
+
     fn to_string(&self) -> String {
         match self {
             Variant::Nil => "nil".to_string(),
@@ -181,3 +310,87 @@ impl VariantExt for Variant {
         }
     }
 }
+
+/// Shifts wider than this produce nil instead of allocating huge integers.
+const MAX_SHIFT: usize = 4096;
+
+fn shift_amount(v: &BigInt) -> Option<usize> {
+    v.to_usize().filter(|v| *v <= MAX_SHIFT)
+}
+
+/// Returns the bytes of a buffer, which compare as an unsigned big-endian integer.
+fn unsigned_bytes(v: &Variant) -> Option<&[u8]> {
+    match v {
+        Variant::Buffer(b) => Some(b),
+        Variant::Slice(b) => Some(b),
+        _ => None,
+    }
+}
+
+fn cmp_unsigned(a: &[u8], b: &[u8]) -> Ordering {
+    let a = &a[a.iter().position(|x| *x != 0).unwrap_or(a.len())..];
+    let b = &b[b.iter().position(|x| *x != 0).unwrap_or(b.len())..];
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn as_number(v: &Variant) -> Cow<Variant> {
+    match unsigned_bytes(v) {
+        Some(b) => Cow::Owned(Variant::BigInt(
+            BigInt::from_bytes_be(Sign::Plus, b)
+                .to_signed_bytes_be()
+                .into_boxed_slice(),
+        )),
+        None => Cow::Borrowed(v),
+    }
+}
+
+fn small_integer(v: &Variant) -> Option<i128> {
+    match v {
+        Variant::Int64(v) => Some(i128::from(*v)),
+        Variant::UInt64(v) => Some(i128::from(*v)),
+        _ => None,
+    }
+}
+
+/// Applies an integer operation.
+///
+/// 64-bit operands are computed with `small_op` without allocating, falling
+/// back to `op` on big integers if `small_op` returns None or the result
+/// does not fit in 64 bits.
+fn integer_op<F, G>(lhs: &Variant, rhs: &Variant, op: F, small_op: G) -> Variant
+where
+    F: Fn(BigInt, BigInt) -> Option<BigInt>,
+    G: Fn(i128, i128) -> Option<i128>,
+{
+    if let (Some(a), Some(b)) = (small_integer(lhs), small_integer(rhs)) {
+        if let Some(v) = small_op(a, b) {
+            if v >= 0 && v <= i128::from(u64::max_value()) {
+                return Variant::UInt64(v as u64);
+            } else if v >= i128::from(i64::min_value()) && v <= i128::from(i64::max_value()) {
+                return Variant::Int64(v as i64);
+            }
+        }
+    }
+    match (lhs.to_bigint(), rhs.to_bigint()) {
+        (Some(a), Some(b)) => match op(a, b) {
+            Some(v) => Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink(),
+            None => Variant::Nil,
+        },
+        _ => Variant::Nil,
+    }
+}
+
+fn numeric_op<F, G, H>(lhs: &Variant, rhs: &Variant, op: F, small_op: G, float_op: H) -> Variant
+where
+    F: Fn(BigInt, BigInt) -> Option<BigInt>,
+    G: Fn(i128, i128) -> Option<i128>,
+    H: Fn(f64, f64) -> f64,
+{
+    match (lhs, rhs) {
+        (Variant::Float64(_), _) | (_, Variant::Float64(_)) => match (lhs.to_f64(), rhs.to_f64()) {
+            (Some(a), Some(b)) => Variant::Float64(float_op(a, b)),
+            _ => Variant::Nil,
+        },
+        _ => integer_op(lhs, rhs, op, small_op),
+    }
+}
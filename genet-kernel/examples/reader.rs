@@ -41,7 +41,7 @@ impl Worker for TestWorker {
     fn read(&mut self) -> Result<Vec<Layer>> {
         let layers = iter::repeat(())
             .take(1000)
-            .map(|_| Layer::new(&ETH_CLASS, ByteSlice::from(tcp_ipv4_pcap())))
+            .map(|_| Layer::new(&ETH_CLASS, ByteSlice::from(tcp_ipv4_pcap().to_vec())))
             .collect();
         Ok(layers)
     }
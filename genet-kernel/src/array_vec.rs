@@ -9,6 +9,7 @@ where
 {
     buckets: Vec<*mut [T; BLOCK_SIZE]>,
     len: usize,
+    first: usize,
 }
 
 unsafe impl<T: Send> Send for ArrayVec<T> {}
@@ -19,6 +20,7 @@ impl<T> ArrayVec<T> {
         Self {
             buckets: Vec::new(),
             len: 0,
+            first: 0,
         }
     }
 
@@ -26,8 +28,13 @@ impl<T> ArrayVec<T> {
         self.len
     }
 
+    /// Returns the index of the first element which has not been evicted.
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
     pub fn get(&self, index: usize) -> Option<&T> {
-        if index < self.len {
+        if index >= self.first && index < self.len {
             let bucket = index / BLOCK_SIZE;
             let offset = index % BLOCK_SIZE;
             unsafe { Some(&(*self.buckets[bucket])[offset]) }
@@ -36,6 +43,16 @@ impl<T> ArrayVec<T> {
         }
     }
 
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.first && index < self.len {
+            let bucket = index / BLOCK_SIZE;
+            let offset = index % BLOCK_SIZE;
+            unsafe { Some(&mut (*self.buckets[bucket])[offset]) }
+        } else {
+            None
+        }
+    }
+
     pub fn push(&mut self, val: T) {
         let bucket = self.len / BLOCK_SIZE;
         let offset = self.len % BLOCK_SIZE;
@@ -49,8 +66,35 @@ impl<T> ArrayVec<T> {
         self.len += 1;
     }
 
+    /// Removes the elements before `end` and frees the blocks no longer in use.
+    ///
+    /// Indices of the remaining elements are unchanged.
+    pub fn evict(&mut self, end: usize) -> Vec<T> {
+        let end = end.min(self.len);
+        let mut evicted = Vec::new();
+        while self.first < end {
+            let bucket = self.first / BLOCK_SIZE;
+            let offset = self.first % BLOCK_SIZE;
+            unsafe {
+                evicted.push(ptr::read(&(*self.buckets[bucket])[offset]));
+            }
+            self.first += 1;
+            if self.first % BLOCK_SIZE == 0 {
+                unsafe {
+                    let data = self.buckets[bucket] as *mut [mem::ManuallyDrop<T>; BLOCK_SIZE];
+                    drop(Box::from_raw(data));
+                }
+                self.buckets[bucket] = ptr::null_mut();
+            }
+        }
+        evicted
+    }
+
     pub fn iter(&self) -> Iter<T> {
-        Iter { v: self, offset: 0 }
+        Iter {
+            v: self,
+            offset: self.first,
+        }
     }
 }
 
@@ -72,6 +116,7 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.v.len(), Some(self.v.len()))
+        let len = self.v.len().saturating_sub(self.offset);
+        (len, Some(len))
     }
 }
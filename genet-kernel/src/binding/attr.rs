@@ -1,4 +1,5 @@
-use genet_abi::{self, attr::Attr, layer::Layer, variant::Variant};
+use frame::LayerRef;
+use genet_abi::{self, attr::Attr, layer::Layer, token::Token, variant::Variant};
 use genet_filter::{
     ast::Expr,
     unparser::{unparse, unparse_attr},
@@ -7,7 +8,7 @@ use genet_napi::napi::{
     CallbackInfo, Env, PropertyAttributes, PropertyDescriptor, Result, TypedArrayType, Value,
     ValueRef,
 };
-use std::rc::Rc;
+use std::{ptr, rc::Rc};
 
 fn variant_to_js<'env>(
     env: &'env Env,
@@ -32,29 +33,51 @@ fn variant_to_js<'env>(
         Ok(Variant::Slice(v)) => env.create_typedarray(
             TypedArrayType::Uint8Array,
             v.len(),
-            env.create_arraybuffer_from_slice(&v)?,
+            env.create_arraybuffer_copy(&v)?,
             0,
         ),
         _ => env.get_null(),
     }
 }
 
+/// Returns the index of the attribute in the headers and attributes of the layer.
+pub fn attr_index(layer: &Layer, id: Token) -> Option<usize> {
+    let attr = layer.attr(id)?;
+    layer
+        .headers()
+        .iter()
+        .chain(layer.attrs().iter())
+        .position(|item| ptr::eq::<Attr>(&**item, attr))
+}
+
 pub struct AttrWrapper {
-    attr: *const Attr,
-    layer: *const Layer,
+    layer: LayerRef,
+    index: usize,
 }
 
 impl AttrWrapper {
-    pub fn new(attr: &Attr, layer: &Layer) -> AttrWrapper {
-        AttrWrapper { attr, layer }
+    pub fn new(layer: LayerRef, index: usize) -> AttrWrapper {
+        AttrWrapper { layer, index }
     }
 
-    fn attr(&self) -> &Attr {
-        unsafe { &*self.attr }
-    }
-
-    fn layer(&self) -> &Layer {
-        unsafe { &*self.layer }
+    /// Calls `f` with the attribute and its layer, or returns null if they are no longer available.
+    fn with<'env, F>(&self, env: &'env Env, f: F) -> Result<&'env Value>
+    where
+        F: FnOnce(&Attr, &Layer) -> Result<&'env Value>,
+    {
+        let index = self.index;
+        let result = self.layer.with(|layer| {
+            layer
+                .headers()
+                .iter()
+                .chain(layer.attrs().iter())
+                .nth(index)
+                .map(|attr| f(attr, layer))
+        });
+        match result {
+            Some(Some(value)) => value,
+            _ => env.get_null(),
+        }
     }
 }
 
@@ -64,48 +87,51 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     }
 
     fn attr_id<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let attr = env.unwrap::<AttrWrapper>(info.this())?.attr();
-        env.create_string(&attr.id().to_string())
+        let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
+        wrapper.with(env, |attr, _| env.create_string(&attr.id().to_string()))
     }
 
     fn attr_type<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let attr = env.unwrap::<AttrWrapper>(info.this())?.attr();
-        env.create_string(&attr.typ().to_string())
+        let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
+        wrapper.with(env, |attr, _| env.create_string(&attr.typ().to_string()))
     }
 
     fn attr_bit_range<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let attr = env.unwrap::<AttrWrapper>(info.this())?.attr();
-        let range = attr.bit_range();
-        let array = env.create_array(2)?;
-        env.set_element(array, 0, env.create_uint32(range.start as u32)?)?;
-        env.set_element(array, 1, env.create_uint32(range.end as u32)?)?;
-        Ok(array)
+        let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
+        wrapper.with(env, |attr, _| {
+            let range = attr.bit_range();
+            let array = env.create_array(2)?;
+            env.set_element(array, 0, env.create_uint32(range.start as u32)?)?;
+            env.set_element(array, 1, env.create_uint32(range.end as u32)?)?;
+            Ok(array)
+        })
     }
 
     fn attr_range<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let attr = env.unwrap::<AttrWrapper>(info.this())?.attr();
-        let range = attr.range();
-        let array = env.create_array(2)?;
-        env.set_element(array, 0, env.create_uint32(range.start as u32)?)?;
-        env.set_element(array, 1, env.create_uint32(range.end as u32)?)?;
-        Ok(array)
+        let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
+        wrapper.with(env, |attr, _| {
+            let range = attr.range();
+            let array = env.create_array(2)?;
+            env.set_element(array, 0, env.create_uint32(range.start as u32)?)?;
+            env.set_element(array, 1, env.create_uint32(range.end as u32)?)?;
+            Ok(array)
+        })
     }
 
     fn attr_value<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
-        variant_to_js(env, &wrapper.attr().try_get(wrapper.layer()))
+        wrapper.with(env, |attr, layer| variant_to_js(env, &attr.try_get(layer)))
     }
 
     fn attr_filter_expression<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let wrapper = env.unwrap::<AttrWrapper>(info.this())?;
-        let attr = wrapper.attr();
-        match attr.try_get(wrapper.layer()) {
+        wrapper.with(env, |attr, layer| match attr.try_get(layer) {
             Ok(val) => env.create_string(&unparse(&Expr::CmpEq(
                 Box::new(Expr::Token(attr.id())),
                 Box::new(unparse_attr(attr.typ(), &val)),
             ))),
             Err(_) => env.get_null(),
-        }
+        })
     }
 
     let class = env
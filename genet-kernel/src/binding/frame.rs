@@ -1,5 +1,8 @@
-use binding::{attr::AttrWrapper, JsClass};
-use frame::Frame;
+use binding::{
+    attr::{attr_index, AttrWrapper},
+    JsClass,
+};
+use frame::{Frame, FrameRef, LayerRef};
 use genet_abi::token::Token;
 use genet_napi::napi::{
     CallbackInfo, Env, PropertyAttributes, PropertyDescriptor, Result, Status, Value, ValueRef,
@@ -7,65 +10,99 @@ use genet_napi::napi::{
 };
 use std::rc::Rc;
 
+/// Calls `f` with the frame, or returns null if it is no longer available.
+fn with_frame<'env, F>(env: &'env Env, info: &CallbackInfo, f: F) -> Result<&'env Value>
+where
+    F: FnOnce(&FrameRef, &Frame) -> Result<&'env Value>,
+{
+    let frame_ref = env.unwrap::<FrameRef>(info.this())?;
+    match frame_ref.with(|frame| f(frame_ref, frame)) {
+        Some(value) => value,
+        None => env.get_null(),
+    }
+}
+
 pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     fn ctor<'env>(env: &'env Env, _info: &CallbackInfo) -> Result<&'env Value> {
         env.get_null()
     }
 
     fn frame_index<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
-        env.create_uint32(frame.index())
+        with_frame(env, info, |_, frame| env.create_uint32(frame.index()))
     }
 
-    fn frame_tree_indices<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
-        let indices = frame.tree_indices();
-        let array = env.create_array(indices.len())?;
-        for (i, item) in indices.iter().enumerate() {
-            env.set_element(array, i as u32, env.create_uint32(u32::from(*item))?)?;
+    fn frame_parent<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        if let Some(index) = info.argv().get(0) {
+            let index = env.get_value_uint32(index)? as usize;
+            with_frame(env, info, |_, frame| {
+                if let Some(parent) = frame.parent(index) {
+                    env.create_uint32(parent as u32)
+                } else {
+                    env.get_null()
+                }
+            })
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
+    fn frame_children<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        if let Some(index) = info.argv().get(0) {
+            let index = env.get_value_uint32(index)? as usize;
+            with_frame(env, info, |_, frame| {
+                let children = frame.children(index).collect::<Vec<_>>();
+                let array = env.create_array(children.len())?;
+                for (i, child) in children.into_iter().enumerate() {
+                    env.set_element(array, i as u32, env.create_uint32(child as u32)?)?;
+                }
+                Ok(array)
+            })
+        } else {
+            Err(Status::InvalidArg)
         }
-        Ok(array)
     }
 
     fn frame_query<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
         if let Some(id) = info.argv().get(0) {
             let id = match env.type_of(id)? {
                 ValueType::Number => Token::from(env.get_value_uint32(id)?),
                 _ => Token::from(env.get_value_string(env.coerce_to_string(id)?)?.as_str()),
             };
-
-            for layer in frame.layers().iter().rev() {
-                if layer.id() == id {
-                    let layer_class = env.get_constructor(JsClass::Layer as usize).unwrap();
-                    let instance = env.new_instance(&layer_class, &[])?;
-                    env.wrap_mut_fixed(instance, layer)?;
-                    return Ok(instance);
-                }
-                if let Some(attr) = layer.attr(id) {
-                    let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
-                    let instance = env.new_instance(&attr_class, &[])?;
-                    env.wrap(instance, AttrWrapper::new(attr, layer))?;
-                    return Ok(instance);
+            with_frame(env, info, |frame_ref, frame| {
+                for (index, layer) in frame.layers().iter().enumerate().rev() {
+                    let layer_ref = LayerRef::new(frame_ref.clone(), index);
+                    if layer.id() == id {
+                        let layer_class = env.get_constructor(JsClass::Layer as usize).unwrap();
+                        let instance = env.new_instance(&layer_class, &[])?;
+                        env.wrap(instance, layer_ref)?;
+                        return Ok(instance);
+                    }
+                    if let Some(attr) = attr_index(layer, id) {
+                        let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
+                        let instance = env.new_instance(&attr_class, &[])?;
+                        env.wrap(instance, AttrWrapper::new(layer_ref, attr))?;
+                        return Ok(instance);
+                    }
                 }
-            }
-            env.get_null()
+                env.get_null()
+            })
         } else {
             Err(Status::InvalidArg)
         }
     }
 
     fn frame_layers<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
-        let layers = frame.layers();
-        let layer_class = env.get_constructor(JsClass::Layer as usize).unwrap();
-        let array = env.create_array(layers.len())?;
-        for (i, item) in layers.iter().enumerate() {
-            let instance = env.new_instance(&layer_class, &[])?;
-            env.wrap_mut_fixed(instance, item)?;
-            env.set_element(array, i as u32, instance)?;
-        }
-        Ok(array)
+        with_frame(env, info, |frame_ref, frame| {
+            let layers = frame.layers();
+            let layer_class = env.get_constructor(JsClass::Layer as usize).unwrap();
+            let array = env.create_array(layers.len())?;
+            for index in 0..layers.len() {
+                let instance = env.new_instance(&layer_class, &[])?;
+                env.wrap(instance, LayerRef::new(frame_ref.clone(), index))?;
+                env.set_element(array, index as u32, instance)?;
+            }
+            Ok(array)
+        })
     }
 
     let class = env
@@ -87,12 +124,17 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
                     frame_layers,
                     false,
                 ),
-                PropertyDescriptor::new_property(
+                PropertyDescriptor::new_method(
                     env,
-                    "treeIndices",
+                    "parent",
                     PropertyAttributes::DEFAULT,
-                    frame_tree_indices,
-                    false,
+                    frame_parent,
+                ),
+                PropertyDescriptor::new_method(
+                    env,
+                    "children",
+                    PropertyAttributes::DEFAULT,
+                    frame_children,
                 ),
                 PropertyDescriptor::new_method(
                     env,
@@ -1,4 +1,8 @@
-use binding::{attr::AttrWrapper, JsClass};
+use binding::{
+    attr::{attr_index, AttrWrapper},
+    JsClass,
+};
+use frame::LayerRef;
 use genet_abi::{layer::Layer, token::Token};
 use genet_filter::{ast::Expr, unparser::unparse};
 use genet_napi::napi::{
@@ -7,95 +11,139 @@ use genet_napi::napi::{
 };
 use std::rc::Rc;
 
+/// Calls `f` with the layer, or returns null if it is no longer available.
+fn with_layer<'env, F>(env: &'env Env, info: &CallbackInfo, f: F) -> Result<&'env Value>
+where
+    F: FnOnce(&LayerRef, &Layer) -> Result<&'env Value>,
+{
+    let layer_ref = env.unwrap::<LayerRef>(info.this())?;
+    match layer_ref.with(|layer| f(layer_ref, layer)) {
+        Some(value) => value,
+        None => env.get_null(),
+    }
+}
+
 pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     fn ctor<'env>(env: &'env Env, _info: &CallbackInfo) -> Result<&'env Value> {
         env.get_null()
     }
 
     fn layer_id<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
-        env.create_string(&layer.id().to_string())
+        with_layer(env, info, |_, layer| {
+            env.create_string(&layer.id().to_string())
+        })
     }
 
     fn layer_attr<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
         if let Some(id) = info.argv().get(0) {
             let id = match env.type_of(id)? {
                 ValueType::Number => Token::from(env.get_value_uint32(id)?),
                 _ => Token::from(env.get_value_string(env.coerce_to_string(id)?)?.as_str()),
             };
-            if let Some(attr) = layer.attr(id) {
-                let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
-                let instance = env.new_instance(&attr_class, &[])?;
-                env.wrap(instance, AttrWrapper::new(attr, layer))?;
-                Ok(instance)
-            } else {
-                env.get_null()
-            }
+            with_layer(env, info, |layer_ref, layer| {
+                if let Some(index) = attr_index(layer, id) {
+                    let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
+                    let instance = env.new_instance(&attr_class, &[])?;
+                    env.wrap(instance, AttrWrapper::new(layer_ref.clone(), index))?;
+                    Ok(instance)
+                } else {
+                    env.get_null()
+                }
+            })
         } else {
             Err(Status::InvalidArg)
         }
     }
 
     fn layer_attrs<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
-        let headers = layer.headers();
-        let attrs = layer.attrs();
-        let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
-        let array = env.create_array(headers.len() + attrs.len())?;
-        for (i, item) in headers.iter().enumerate() {
-            let instance = env.new_instance(&attr_class, &[])?;
-            env.wrap(instance, AttrWrapper::new(item, layer))?;
-            env.set_element(array, i as u32, instance)?;
-        }
-        for (i, item) in attrs.iter().enumerate() {
-            let instance = env.new_instance(&attr_class, &[])?;
-            env.wrap(instance, AttrWrapper::new(item, layer))?;
-            env.set_element(array, (headers.len() + i) as u32, instance)?;
-        }
-        Ok(array)
+        with_layer(env, info, |layer_ref, layer| {
+            let len = layer.headers().len() + layer.attrs().len();
+            let attr_class = env.get_constructor(JsClass::Attr as usize).unwrap();
+            let array = env.create_array(len)?;
+            for index in 0..len {
+                let instance = env.new_instance(&attr_class, &[])?;
+                env.wrap(instance, AttrWrapper::new(layer_ref.clone(), index))?;
+                env.set_element(array, index as u32, instance)?;
+            }
+            Ok(array)
+        })
     }
 
     fn layer_payloads<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
-        let payloads = layer.payloads();
-        let array = env.create_array(payloads.len())?;
-        for (i, paylaod) in payloads.iter().enumerate() {
-            let object = env.create_object()?;
-            env.set_named_property(object, "id", env.create_string(&paylaod.id().to_string())?)?;
-            env.set_named_property(
-                object,
-                "type",
-                env.create_string(&paylaod.typ().to_string())?,
-            )?;
-            env.set_named_property(
-                object,
-                "data",
-                env.create_typedarray(
-                    TypedArrayType::Uint8Array,
-                    paylaod.data().len(),
-                    env.create_arraybuffer_from_slice(&paylaod.data())?,
-                    0,
-                )?,
-            )?;
-            env.set_element(array, i as u32, object)?;
-        }
-        Ok(array)
+        with_layer(env, info, |_, layer| {
+            let payloads = layer.payloads();
+            let array = env.create_array(payloads.len())?;
+            for (i, paylaod) in payloads.iter().enumerate() {
+                let object = env.create_object()?;
+                env.set_named_property(
+                    object,
+                    "id",
+                    env.create_string(&paylaod.id().to_string())?,
+                )?;
+                env.set_named_property(
+                    object,
+                    "type",
+                    env.create_string(&paylaod.typ().to_string())?,
+                )?;
+                env.set_named_property(
+                    object,
+                    "data",
+                    env.create_typedarray(
+                        TypedArrayType::Uint8Array,
+                        paylaod.data().len(),
+                        env.create_arraybuffer_copy(&paylaod.data())?,
+                        0,
+                    )?,
+                )?;
+                env.set_element(array, i as u32, object)?;
+            }
+            Ok(array)
+        })
+    }
+
+    fn layer_experts<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        with_layer(env, info, |_, layer| {
+            let experts = layer.experts();
+            let array = env.create_array(experts.len())?;
+            for (i, expert) in experts.iter().enumerate() {
+                let object = env.create_object()?;
+                env.set_named_property(
+                    object,
+                    "severity",
+                    env.create_string(&expert.severity().to_string())?,
+                )?;
+                env.set_named_property(
+                    object,
+                    "group",
+                    env.create_string(&expert.group().to_string())?,
+                )?;
+                env.set_named_property(object, "message", env.create_string(expert.message())?)?;
+                let range = expert.range();
+                let range_array = env.create_array(2)?;
+                env.set_element(range_array, 0, env.create_uint32(range.start as u32)?)?;
+                env.set_element(range_array, 1, env.create_uint32(range.end as u32)?)?;
+                env.set_named_property(object, "range", range_array)?;
+                env.set_element(array, i as u32, object)?;
+            }
+            Ok(array)
+        })
     }
 
     fn layer_data<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
-        env.create_typedarray(
-            TypedArrayType::Uint8Array,
-            layer.data().len(),
-            env.create_arraybuffer_from_slice(&layer.data())?,
-            0,
-        )
+        with_layer(env, info, |_, layer| {
+            env.create_typedarray(
+                TypedArrayType::Uint8Array,
+                layer.data().len(),
+                env.create_arraybuffer_copy(&layer.data())?,
+                0,
+            )
+        })
     }
 
     fn layer_filter_expression<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let layer = env.unwrap::<Layer>(info.this())?;
-        env.create_string(&unparse(&Expr::Token(layer.id())))
+        with_layer(env, info, |_, layer| {
+            env.create_string(&unparse(&Expr::Token(layer.id())))
+        })
     }
 
     let class = env
@@ -130,6 +178,13 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
                     layer_payloads,
                     false,
                 ),
+                PropertyDescriptor::new_property(
+                    env,
+                    "experts",
+                    PropertyAttributes::DEFAULT,
+                    layer_experts,
+                    false,
+                ),
                 PropertyDescriptor::new_property(
                     env,
                     "data",
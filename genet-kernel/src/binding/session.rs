@@ -44,6 +44,32 @@ impl SessionCallback {
     }
 }
 
+/// Compiles a filter, throwing an error with the offending span on failure.
+fn compile_filter(env: &Env, filter: &str) -> Result<Option<Filter>> {
+    if filter.is_empty() {
+        return Ok(None);
+    }
+    match Filter::compile(filter) {
+        Ok(filter) => Ok(Some(filter)),
+        Err(err) => {
+            let span = err.span();
+            let error = env.create_error(
+                env.create_string("filter")?,
+                env.create_string(&err.to_string())?,
+            )?;
+            env.set_named_property(error, "start", env.create_uint32(span.start as u32)?)?;
+            env.set_named_property(error, "end", env.create_uint32(span.end as u32)?)?;
+            let expected = env.create_array(err.expected().len())?;
+            for (i, name) in err.expected().iter().enumerate() {
+                env.set_element(expected, i as u32, env.create_string(name)?)?;
+            }
+            env.set_named_property(error, "expected", expected)?;
+            env.throw(error)?;
+            Err(Status::PendingException)
+        }
+    }
+}
+
 pub fn init(env: &Env, exports: &Value) -> Result<()> {
     fn profile_ctor<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let profile = Profile::new();
@@ -89,6 +115,29 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn profile_register_token<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.register_token(&env.get_value_string(value)?);
+            env.get_null()
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
+    fn profile_set_decoder_enabled<'env>(
+        env: &'env Env,
+        info: &CallbackInfo,
+    ) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some([id, enabled]) = info.argv().get(0..2) {
+            profile.set_decoder_enabled(&env.get_value_string(id)?, env.get_value_bool(enabled)?);
+            env.get_null()
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
     fn profile_concurrency<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let profile = env.unwrap::<Profile>(info.this())?;
         if let Some(value) = info.argv().get(0) {
@@ -99,6 +148,36 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn profile_memory_limit<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_memory_limit(env.get_value_double(value)?.max(0.0) as usize);
+            env.get_null()
+        } else {
+            env.create_double(profile.memory_limit() as f64)
+        }
+    }
+
+    fn profile_ring_frames<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_ring_frames(env.get_value_uint32(value)?);
+            env.get_null()
+        } else {
+            env.create_uint32(profile.ring_frames())
+        }
+    }
+
+    fn profile_ring_seconds<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_ring_seconds(env.get_value_uint32(value)?);
+            env.get_null()
+        } else {
+            env.create_uint32(profile.ring_seconds())
+        }
+    }
+
     fn session_frames<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some([start, end]) = info.argv().get(0..2) {
@@ -107,9 +186,9 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
             let frames = session.frames(start as usize..end as usize);
             let frame_class = env.get_constructor(JsClass::Frame as usize).unwrap();
             let array = env.create_array(frames.len())?;
-            for (i, item) in frames.iter().enumerate() {
+            for (i, item) in frames.into_iter().enumerate() {
                 let instance = env.new_instance(&frame_class, &[])?;
-                env.wrap_ptr(instance, *item)?;
+                env.wrap(instance, item)?;
                 env.set_element(array, i as u32, instance)?;
             }
             Ok(array)
@@ -135,25 +214,40 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn session_set_profile<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile_class = env
+            .get_constructor(JsClass::SessionProfile as usize)
+            .unwrap();
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some(profile) = info.argv().get(0) {
+            if env.instanceof(profile, &profile_class)? {
+                let profile = env.unwrap::<Profile>(profile)?.clone();
+                session.set_profile(profile);
+                return env.get_null();
+            }
+        }
+        Err(Status::InvalidArg)
+    }
+
     fn session_set_filter<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some([id, filter]) = info.argv().get(0..2) {
-            let filter = env.get_value_string(filter)?;
-            session.set_filter(
-                env.get_value_uint32(id)?,
-                if filter.is_empty() {
-                    None
-                } else {
-                    match Filter::compile(&filter) {
-                        Ok(filter) => Some(filter),
-                        Err(err) => {
-                            env.throw_error("load_library", &err.to_string())?;
-                            None
-                        }
-                    }
-                },
-            );
-            env.get_null()
+            let filter = compile_filter(env, &env.get_value_string(filter)?)?;
+            let warnings = match &filter {
+                Some(filter) => session.profile().check_filter(filter),
+                None => Vec::new(),
+            };
+            session.set_filter(env.get_value_uint32(id)?, filter);
+            let array = env.create_array(warnings.len())?;
+            for (i, warning) in warnings.iter().enumerate() {
+                let span = warning.span();
+                let object = env.create_object()?;
+                env.set_named_property(object, "message", env.create_string(warning.message())?)?;
+                env.set_named_property(object, "start", env.create_uint32(span.start as u32)?)?;
+                env.set_named_property(object, "end", env.create_uint32(span.end as u32)?)?;
+                env.set_element(array, i as u32, object)?;
+            }
+            Ok(array)
         } else {
             Err(Status::InvalidArg)
         }
@@ -174,20 +268,11 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some([id, arg, filter]) = info.argv().get(0..3) {
             let filter = env.get_value_string(filter)?;
+            let filter = compile_filter(env, &filter)?;
             let handle = session.create_writer(
                 &env.get_value_string(id)?,
                 &env.get_value_string(arg)?,
-                if filter.is_empty() {
-                    None
-                } else {
-                    match Filter::compile(&filter) {
-                        Ok(filter) => Some(filter),
-                        Err(err) => {
-                            env.throw_error("load_library", &err.to_string())?;
-                            None
-                        }
-                    }
-                },
+                filter,
             );
             env.create_uint32(handle)
         } else {
@@ -238,6 +323,12 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 PropertyAttributes::DEFAULT,
                 session_set_filter,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "setProfile",
+                PropertyAttributes::DEFAULT,
+                session_set_profile,
+            ),
             PropertyDescriptor::new_method(
                 env,
                 "createReader",
@@ -289,6 +380,18 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 PropertyAttributes::DEFAULT,
                 profile_load_library,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "registerToken",
+                PropertyAttributes::DEFAULT,
+                profile_register_token,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "setDecoderEnabled",
+                PropertyAttributes::DEFAULT,
+                profile_set_decoder_enabled,
+            ),
             PropertyDescriptor::new_property(
                 env,
                 "concurrency",
@@ -296,6 +399,27 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 profile_concurrency,
                 true,
             ),
+            PropertyDescriptor::new_property(
+                env,
+                "memoryLimit",
+                PropertyAttributes::DEFAULT,
+                profile_memory_limit,
+                true,
+            ),
+            PropertyDescriptor::new_property(
+                env,
+                "ringFrames",
+                PropertyAttributes::DEFAULT,
+                profile_ring_frames,
+                true,
+            ),
+            PropertyDescriptor::new_property(
+                env,
+                "ringSeconds",
+                PropertyAttributes::DEFAULT,
+                profile_ring_seconds,
+                true,
+            ),
         ],
     )?;
 
@@ -0,0 +1,79 @@
+use array_vec::ArrayVec;
+use crossbeam_channel;
+use decoder::dispatcher::Dispatcher;
+use frame::Frame;
+use genet_abi::decoder::ExecType;
+use parking_lot::RwLock;
+use profile::Profile;
+use std::{
+    ops::Range,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+pub trait Callback: Sync + Send {
+    fn done(&self, range: Range<usize>);
+}
+
+/// Runs async decoders on stored frames in order.
+///
+/// Stored frames are only modified while the frames are locked for writing,
+/// so readers holding a `FrameRef` never observe a frame being decoded.
+pub struct Pool {
+    sender: crossbeam_channel::Sender<Option<Range<usize>>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    pub fn new<C: 'static + Callback>(
+        profile: Profile,
+        frames: Arc<RwLock<ArrayVec<Frame>>>,
+        callback: C,
+    ) -> Pool {
+        let (send, recv) = crossbeam_channel::unbounded::<Option<Range<usize>>>();
+        let mut handles = Vec::new();
+        let active = profile
+            .ordered_decoders()
+            .iter()
+            .any(|d| d.metadata().exec_type == ExecType::Async);
+
+        let handle = thread::spawn(move || {
+            let mut disp = Dispatcher::new(&ExecType::Async, &profile);
+            while let Some(Some(range)) = recv.recv() {
+                if active {
+                    for index in range.clone() {
+                        // Frames are locked one by one so that they remain readable meanwhile.
+                        let mut frames = frames.write();
+                        if let Some(frame) = frames.get_mut(index) {
+                            if !frame.is_released() {
+                                disp.process_frame(frame);
+                            }
+                        }
+                    }
+                }
+                callback.done(range);
+            }
+        });
+        handles.push(handle);
+
+        Pool {
+            sender: send,
+            handles,
+        }
+    }
+
+    pub fn process(&mut self, range: Range<usize>) {
+        self.sender.send(Some(range));
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for _ in 0..self.handles.len() {
+            self.sender.send(None);
+        }
+        while let Some(h) = self.handles.pop() {
+            h.join().unwrap();
+        }
+    }
+}
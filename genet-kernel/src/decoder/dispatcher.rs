@@ -1,12 +1,25 @@
 use frame::Frame;
 use genet_abi::{
+    attr::{Attr, AttrClass},
     context::Context,
     decoder::{DecoderBox, ExecType, Metadata, WorkerBox},
+    error::Panic,
     fixed::MutFixed,
     layer::{Layer, Parent},
+    token::Token,
+    variant::Variant,
 };
 use profile::Profile;
 
+/// The number of panics after which a decoder is no longer executed.
+const MAX_PANICS: u32 = 3;
+
+lazy_static! {
+    static ref ERROR_CLASS: AttrClass = AttrClass::builder("_.error").build();
+    static ref ERROR_DECODER_CLASS: AttrClass = AttrClass::builder("_.error.decoder").build();
+    static ref ERROR_PANIC_CLASS: AttrClass = AttrClass::builder("_.error.panic").build();
+}
+
 pub struct Dispatcher {
     runners: Vec<Runner>,
 }
@@ -14,12 +27,20 @@ pub struct Dispatcher {
 impl Dispatcher {
     pub fn new(typ: &ExecType, profile: &Profile) -> Dispatcher {
         let runners = profile
-            .decoders()
-            .map(|d| Runner::new(typ, profile.context(), *d))
+            .ordered_decoders()
+            .into_iter()
+            .map(|d| Runner::new(typ, profile.context(), d))
             .collect();
         Dispatcher { runners }
     }
 
+    /// Creates a dispatcher to decode released frames again.
+    ///
+    /// Only parallel decoders run, since released frames retain the layers of later stages.
+    pub fn restorer(profile: &Profile) -> Dispatcher {
+        Dispatcher::new(&ExecType::ParallelSync, profile)
+    }
+
     pub fn runners(&mut self) -> Vec<OnceRunner> {
         self.runners
             .iter_mut()
@@ -27,71 +48,79 @@ impl Dispatcher {
             .collect()
     }
 
+    /// Decodes the layers of the frame with the decoders which have not visited them yet.
+    ///
+    /// Each decoder runs once per layer rather than once per frame, so that nested layers
+    /// of the same protocol, such as the datagram embedded in an ICMP error, are decoded
+    /// as well. A decoder never decodes the layers it has added itself, which would
+    /// otherwise nest them without end.
     pub fn process_frame(&mut self, frame: &mut Frame) {
-        let mut indices = frame.fetch_tree_indices();
-        let mut layers = frame.fetch_layers();
-        let mut offset = 0;
-        let mut runners = self.runners();
-        loop {
-            let len = layers.len() - offset;
-            for index in offset..layers.len() {
-                if let Some(n) = indices.get(index) {
-                    if *n > 0 {
+        let len = frame.layers().len();
+        let mut index = 0;
+        while index < frame.layers().len() {
+            let decoder = frame.decoder(index);
+            let mut runners = self.runners();
+            for r in &mut runners {
+                r.used = decoder == Some(r.runner.id) || frame.is_visited(index, r.runner.id);
+            }
+            let mut extended = index >= len;
+            loop {
+                let mut executed = 0;
+                for r in runners.iter_mut() {
+                    if r.runner.follows() && !extended {
                         continue;
                     }
-                }
-                let mut children = 0;
-                loop {
-                    let mut executed = 0;
-                    for mut r in &mut runners.iter_mut() {
-                        let mut layer =
-                            Parent::from_mut_ref(unsafe { &mut *layers[index].as_mut_ptr() });
-                        let done = r.execute(&layers, &mut layer);
-                        if done {
-                            executed += 1;
+                    let mut layer =
+                        Parent::from_mut_ref(unsafe { &mut *frame.layers()[index].as_mut_ptr() });
+                    let payloads = layer.payloads().len();
+                    let done = r.execute(frame.layers(), &mut layer);
+                    if done {
+                        executed += 1;
+                        if r.runner.worker.is_some() {
+                            frame.visit(index, r.runner.id);
                         }
-                        let mut results: Vec<MutFixed<Layer>> = layer
-                            .children()
-                            .iter()
-                            .map(|v| unsafe { MutFixed::from_ptr(*v) })
-                            .collect();
-                        children += results.len();
-                        layers.append(&mut results);
                     }
-                    if executed == 0 {
-                        break;
+                    extended |= layer.payloads().len() > payloads || !layer.children().is_empty();
+                    for child in layer.children() {
+                        frame.add_layer(index, unsafe { MutFixed::from_ptr(*child) }, r.runner.id);
                     }
                 }
-                indices.push(children as u8);
-            }
-
-            offset += len;
-            if offset >= layers.len() {
-                break;
+                if executed == 0 {
+                    break;
+                }
             }
+            index += 1;
         }
-
-        frame.set_layers(layers);
-        frame.set_tree_indices(indices);
     }
 }
 
 struct Runner {
+    id: Token,
     ctx: Context,
     typ: ExecType,
     decoder: DecoderBox,
     metadata: Metadata,
     worker: Option<WorkerBox>,
+    panics: u32,
 }
 
 impl Runner {
     fn new(typ: &ExecType, ctx: Context, decoder: DecoderBox) -> Runner {
+        let metadata = decoder.metadata();
+        let id = if metadata.id.is_empty() {
+            // Anonymous decoders are told apart by their address.
+            Token::from(format!("@anonymous:{:p}", decoder.as_ptr()))
+        } else {
+            Token::from(metadata.id.as_str())
+        };
         let mut runner = Runner {
+            id,
             ctx,
             typ: typ.clone(),
             decoder,
-            metadata: decoder.metadata(),
+            metadata,
             worker: None,
+            panics: 0,
         };
         runner.reset();
         runner
@@ -101,15 +130,66 @@ impl Runner {
         if let Some(worker) = &mut self.worker {
             match worker.decode(&mut self.ctx, layers, layer) {
                 Ok(done) => done,
-                Err(_) => true,
+                Err(err) => {
+                    let panicked = err.downcast_ref::<Panic>().is_some();
+                    self.add_error(layer, &err.to_string(), panicked);
+                    if panicked {
+                        // The worker may be left in an inconsistent state.
+                        self.panics += 1;
+                        self.reset();
+                    }
+                    true
+                }
             }
         } else {
             true
         }
     }
 
+    /// Records a decoder failure on the parent layer.
+    fn add_error(&self, layer: &mut Parent, message: &str, panicked: bool) {
+        let error = Token::from("_.error");
+        let decoder = Token::from("_.error.decoder");
+        let value = Variant::String(message.into());
+        let id = Variant::String(self.metadata.id.clone().into_boxed_str());
+        // Released frames are decoded again from the same root layer.
+        let attrs = layer.attrs();
+        let recorded = attrs.iter().enumerate().any(|(i, attr)| {
+            let recorded_id = attrs
+                .get(i + 1)
+                .filter(|next| next.id() == decoder)
+                .and_then(|next| next.try_get(layer).ok())
+                .unwrap_or_else(|| Variant::String("".into()));
+            attr.id() == error
+                && attr.try_get(layer).ok().as_ref() == Some(&value)
+                && recorded_id == id
+        });
+        if recorded {
+            return;
+        }
+        layer.add_attr(Attr::builder(&ERROR_CLASS).value(value).build());
+        if !self.metadata.id.is_empty() {
+            layer.add_attr(
+                Attr::builder(&ERROR_DECODER_CLASS)
+                    .value(self.metadata.id.clone().into_boxed_str())
+                    .build(),
+            );
+        }
+        if panicked {
+            layer.add_attr(Attr::builder(&ERROR_PANIC_CLASS).value(true).build());
+        }
+    }
+
+    /// Returns true if self is a parallel decoder running in a later stage.
+    ///
+    /// It decodes layers extended in that stage, such as reassembled payloads.
+    fn follows(&self) -> bool {
+        self.typ != ExecType::ParallelSync && self.metadata.exec_type == ExecType::ParallelSync
+    }
+
     fn reset(&mut self) {
-        self.worker = if self.metadata.exec_type == self.typ {
+        let enabled = self.metadata.exec_type == self.typ || self.follows();
+        self.worker = if enabled && self.panics < MAX_PANICS {
             Some(self.decoder.new_worker(&self.ctx))
         } else {
             None
@@ -142,3 +222,297 @@ impl<'a> OnceRunner<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use decoder::dispatcher::{Dispatcher, Runner, MAX_PANICS};
+    use frame::Frame;
+    use genet_abi::{
+        context::Context,
+        decoder::{Decoder, DecoderBox, ExecType, Metadata, Status, Worker},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass, LayerStack, Parent},
+        result::Result,
+        token::Token,
+    };
+    use profile::Profile;
+
+    /// Adds a child layer `to` to the layers `from`.
+    struct TestWorker {
+        from: Token,
+        to: &'static str,
+        panic: bool,
+    }
+
+    impl Worker for TestWorker {
+        fn decode(
+            &mut self,
+            _ctx: &mut Context,
+            _stack: &LayerStack,
+            parent: &mut Parent,
+        ) -> Result<Status> {
+            if parent.id() != self.from {
+                return Ok(Status::Skip);
+            }
+            if self.panic {
+                panic!("test");
+            }
+            if !self.to.is_empty() {
+                let class = Fixed::new(LayerClass::builder(self.to).build());
+                parent.add_child(Layer::new(class, vec![]));
+            }
+            Ok(Status::Done)
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestDecoder {
+        id: &'static str,
+        exec_type: ExecType,
+        from: &'static str,
+        to: &'static str,
+        panic: bool,
+    }
+
+    impl TestDecoder {
+        fn new(id: &'static str, from: &'static str, to: &'static str) -> TestDecoder {
+            TestDecoder {
+                id,
+                exec_type: ExecType::ParallelSync,
+                from,
+                to,
+                panic: false,
+            }
+        }
+    }
+
+    impl Decoder for TestDecoder {
+        fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+            Box::new(TestWorker {
+                from: Token::from(self.from),
+                to: self.to,
+                panic: self.panic,
+            })
+        }
+
+        fn metadata(&self) -> Metadata {
+            Metadata {
+                id: self.id.into(),
+                exec_type: self.exec_type.clone(),
+                ..Metadata::default()
+            }
+        }
+    }
+
+    fn runner(id: &'static str) -> Runner {
+        let decoder = DecoderBox::new(TestDecoder::new(id, "", ""));
+        Runner::new(&ExecType::ParallelSync, Profile::new().context(), decoder)
+    }
+
+    fn dispatcher(typ: &ExecType, decoders: Vec<TestDecoder>) -> Dispatcher {
+        let runners = decoders
+            .into_iter()
+            .map(|d| Runner::new(typ, Profile::new().context(), DecoderBox::new(d)))
+            .collect();
+        Dispatcher { runners }
+    }
+
+    fn layer(id: &str) -> MutFixed<Layer> {
+        let class = Fixed::new(LayerClass::builder(id).build());
+        MutFixed::new(Layer::new(class, vec![]))
+    }
+
+    fn ids(frame: &Frame) -> Vec<Token> {
+        frame.layers().iter().map(|layer| layer.id()).collect()
+    }
+
+    #[test]
+    fn add_error() {
+        let class = Fixed::new(LayerClass::builder("[link-1]").build());
+        let mut layer = Layer::new(class, vec![]);
+        let mut layer = Parent::from_mut_ref(&mut layer);
+        let (a, b) = (runner("a"), runner("b"));
+        a.add_error(&mut layer, "error", false);
+        a.add_error(&mut layer, "error", false);
+        b.add_error(&mut layer, "error", true);
+        b.add_error(&mut layer, "error", true);
+
+        let error = Token::from("_.error");
+        assert_eq!(layer.attrs().iter().filter(|a| a.id() == error).count(), 2);
+        assert_eq!(layer.attrs().len(), 5);
+    }
+
+    #[test]
+    fn process_frame() {
+        let mut disp = dispatcher(
+            &ExecType::ParallelSync,
+            vec![
+                TestDecoder::new("ipv4", "eth", "ipv4"),
+                TestDecoder::new("eth", "[link-1]", "eth"),
+                TestDecoder::new("trailer", "[link-1]", "trailer"),
+            ],
+        );
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        disp.process_frame(&mut frame);
+        assert_eq!(
+            ids(&frame),
+            vec![
+                Token::from("[link-1]"),
+                Token::from("eth"),
+                Token::from("trailer"),
+                Token::from("ipv4"),
+            ]
+        );
+        assert_eq!(frame.children(0).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(frame.parent(3), Some(1));
+        assert_eq!(frame.decoder(3), Some(Token::from("ipv4")));
+        frame.dispose();
+    }
+
+    #[test]
+    fn nested_layers() {
+        let mut disp = dispatcher(
+            &ExecType::ParallelSync,
+            vec![
+                TestDecoder::new("ipv4", "[link-1]", "ipv4"),
+                TestDecoder::new("ipip", "ipv4", "ipv4"),
+            ],
+        );
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        disp.process_frame(&mut frame);
+
+        // The decoder which added the nested layer does not decode it again.
+        assert_eq!(frame.layers().len(), 3);
+        assert_eq!(frame.parent(2), Some(1));
+        assert_eq!(frame.decoder(2), Some(Token::from("ipip")));
+        frame.dispose();
+    }
+
+    #[test]
+    fn anonymous_decoders() {
+        let mut disp = dispatcher(
+            &ExecType::ParallelSync,
+            vec![
+                TestDecoder::new("", "eth", "ipv4"),
+                TestDecoder::new("", "[link-1]", "eth"),
+            ],
+        );
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        disp.process_frame(&mut frame);
+        assert_eq!(frame.layers().len(), 3);
+        let (eth, ipv4) = (frame.decoder(1).unwrap(), frame.decoder(2).unwrap());
+        assert_ne!(eth, ipv4);
+        assert_ne!(eth, Token::from(""));
+
+        // The identity of a decoder does not depend on the dispatcher.
+        let mut disp = Dispatcher {
+            runners: disp.runners.drain(..).rev().collect(),
+        };
+        let mut copy = Frame::new(0, layer("[link-1]"));
+        disp.process_frame(&mut copy);
+        assert_eq!(copy.decoder(1), Some(eth));
+        assert_eq!(copy.decoder(2), Some(ipv4));
+        copy.dispose();
+        frame.dispose();
+    }
+
+    #[test]
+    fn visited_layers() {
+        let mut disp = dispatcher(
+            &ExecType::ParallelSync,
+            vec![TestDecoder::new("eth", "[link-1]", "eth")],
+        );
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        disp.process_frame(&mut frame);
+        disp.process_frame(&mut frame);
+        assert_eq!(frame.layers().len(), 2);
+        assert!(frame.is_visited(0, Token::from("eth")));
+        frame.dispose();
+
+        // Serial decoders decode the layers which parallel decoders have decoded.
+        let defrag = TestDecoder {
+            exec_type: ExecType::SerialSync,
+            ..TestDecoder::new("defrag", "ipv4", "reassembled")
+        };
+        let mut disp = dispatcher(&ExecType::SerialSync, vec![defrag]);
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("ipv4"), Token::from("ipv4"));
+        frame.add_layer(1, layer("udp"), Token::from("udp"));
+        disp.process_frame(&mut frame);
+        assert_eq!(frame.layers()[3].id(), Token::from("reassembled"));
+        assert_eq!(frame.parent(3), Some(1));
+        frame.dispose();
+    }
+
+    #[test]
+    fn follows() {
+        let defrag = TestDecoder {
+            exec_type: ExecType::SerialSync,
+            ..TestDecoder::new("defrag", "ipv4", "reassembled")
+        };
+        let tcp = TestDecoder::new("tcp", "reassembled", "tcp");
+
+        // Parallel decoders only run on the layers extended in a later stage.
+        let mut disp = dispatcher(&ExecType::SerialSync, vec![tcp.clone()]);
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("reassembled"), Token::from("defrag"));
+        disp.process_frame(&mut frame);
+        assert_eq!(frame.layers().len(), 2);
+        frame.dispose();
+
+        let mut disp = dispatcher(&ExecType::SerialSync, vec![defrag, tcp]);
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("ipv4"), Token::from("ipv4"));
+        disp.process_frame(&mut frame);
+        assert_eq!(
+            ids(&frame),
+            vec![
+                Token::from("[link-1]"),
+                Token::from("ipv4"),
+                Token::from("reassembled"),
+                Token::from("tcp"),
+            ]
+        );
+        frame.dispose();
+    }
+
+    #[test]
+    fn unchanged_layers() {
+        let stream = TestDecoder {
+            exec_type: ExecType::SerialSync,
+            ..TestDecoder::new("stream", "ipv4", "")
+        };
+        let tcp = TestDecoder::new("tcp", "ipv4", "tcp");
+
+        // Parallel decoders do not run on the layers a later stage has left unchanged.
+        let mut disp = dispatcher(&ExecType::SerialSync, vec![stream, tcp]);
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("ipv4"), Token::from("ipv4"));
+        disp.process_frame(&mut frame);
+        assert_eq!(frame.layers().len(), 2);
+        assert!(frame.is_visited(1, Token::from("stream")));
+        assert!(!frame.is_visited(1, Token::from("tcp")));
+        frame.dispose();
+    }
+
+    #[test]
+    fn panic() {
+        let eth = TestDecoder {
+            panic: true,
+            ..TestDecoder::new("eth", "[link-1]", "eth")
+        };
+        let mut disp = dispatcher(&ExecType::ParallelSync, vec![eth]);
+        for i in 0..MAX_PANICS + 1 {
+            let mut frame = Frame::new(i, layer("[link-1]"));
+            disp.process_frame(&mut frame);
+            let panics = if i < MAX_PANICS {
+                vec!["eth".to_string()]
+            } else {
+                vec![]
+            };
+            assert_eq!(frame.panics(), panics);
+            frame.dispose();
+        }
+        assert!(disp.runners[0].worker.is_none());
+    }
+}
@@ -1,3 +1,4 @@
-mod dispatcher;
+pub mod asynchronous;
+pub mod dispatcher;
 pub mod parallel;
 pub mod serial;
@@ -37,8 +37,9 @@ impl Pool {
             loop {
                 if let Some(frames) = recv.recv() {
                     if let Some(mut frames) = frames {
-                        for mut f in &mut frames {
+                        for f in &mut frames {
                             disp.process_frame(f);
+                            f.record_stage();
                         }
                         callback.done(frames);
                     } else {
@@ -18,7 +18,7 @@ pub struct Pool {
 }
 
 impl Pool {
-    pub fn new<C: 'static + Callback>(profile: Profile, callback: C) -> Pool {
+    pub fn new<C: 'static + Callback>(profile: Profile, next: usize, callback: C) -> Pool {
         let callback = Box::new(callback);
         let (send, recv) = crossbeam_channel::unbounded::<Option<Vec<Frame>>>();
         let mut handles = Vec::new();
@@ -26,7 +26,7 @@ impl Pool {
         let handle = thread::spawn(move || {
             let mut disp = Dispatcher::new(&ExecType::SerialSync, &profile);
             let mut map = BTreeMap::new();
-            let mut next = 0;
+            let mut next = next;
             loop {
                 if let Some(frames) = recv.recv() {
                     if let Some(frames) = frames {
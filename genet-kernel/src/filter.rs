@@ -0,0 +1,161 @@
+use array_vec::ArrayVec;
+use crossbeam_channel;
+use decoder::dispatcher::Dispatcher;
+use frame::Frame;
+use genet_filter::{context::Context, Filter};
+use parking_lot::RwLock;
+use profile::Profile;
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// The number of frames tested while the frames are locked.
+const BATCH_SIZE: usize = 256;
+
+pub trait Callback: Sync + Send + Clone {
+    fn done(&self, id: u32, generation: u32, range: Range<usize>, indices: Vec<u32>);
+}
+
+/// A chunk of frames to be tested against a filter.
+pub struct Job {
+    pub id: u32,
+    pub generation: u32,
+    pub range: Range<usize>,
+    pub filter: Filter,
+    pub cancelled: Arc<AtomicBool>,
+}
+
+pub struct Pool {
+    sender: crossbeam_channel::Sender<Option<Job>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    pub fn new<C: 'static + Callback>(
+        profile: &Profile,
+        frames: &Arc<RwLock<ArrayVec<Frame>>>,
+        callback: &C,
+    ) -> Pool {
+        let (send, recv) = crossbeam_channel::unbounded::<Option<Job>>();
+        let mut handles = Vec::new();
+        for _ in 0..profile.concurrency() {
+            handles.push(Self::spawn(
+                profile.clone(),
+                frames.clone(),
+                callback.clone(),
+                recv.clone(),
+            ));
+        }
+        Pool {
+            sender: send,
+            handles,
+        }
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.handles.len()
+    }
+
+    fn spawn<C: 'static + Callback>(
+        profile: Profile,
+        frames: Arc<RwLock<ArrayVec<Frame>>>,
+        callback: C,
+        recv: crossbeam_channel::Receiver<Option<Job>>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut disp = None;
+            while let Some(Some(mut job)) = recv.recv() {
+                let mut indices = Vec::new();
+                let mut start = job.range.start;
+                while start < job.range.end && !job.cancelled.load(Ordering::Relaxed) {
+                    let range = start..job.range.end.min(start + BATCH_SIZE);
+                    start = range.end;
+
+                    // Released frames are decoded again while the frames are not locked.
+                    let mut copies = {
+                        let frames = frames.read();
+                        range
+                            .clone()
+                            .map(|index| {
+                                frames
+                                    .get(index)
+                                    .filter(|frame| frame.is_released())
+                                    .map(|frame| frame.detached_copy())
+                            })
+                            .collect::<Vec<_>>()
+                    };
+                    for copy in copies.iter_mut().flatten() {
+                        let disp = disp.get_or_insert_with(|| Dispatcher::restorer(&profile));
+                        disp.process_frame(copy);
+                    }
+
+                    let frames = frames.read();
+                    for (index, copy) in range.zip(copies) {
+                        let frame = match frames.get(index) {
+                            Some(frame) => frame,
+                            None => {
+                                if let Some(copy) = copy {
+                                    copy.dispose();
+                                }
+                                continue;
+                            }
+                        };
+                        let matched = match copy {
+                            Some(mut copy)
+                                if frame.is_released()
+                                    && frame.generation() == copy.generation() =>
+                            {
+                                copy.borrow_retained(frame);
+                                let matched = job.filter.test(&Context::new(copy.layers()));
+                                copy.dispose();
+                                matched
+                            }
+                            copy => {
+                                // The frame has been restored, released or replaced meanwhile.
+                                if let Some(copy) = copy {
+                                    copy.dispose();
+                                }
+                                if frame.is_released() {
+                                    let disp =
+                                        disp.get_or_insert_with(|| Dispatcher::restorer(&profile));
+                                    let copy = frame.decode_copy(disp);
+                                    let matched = job.filter.test(&Context::new(copy.layers()));
+                                    copy.dispose();
+                                    matched
+                                } else {
+                                    job.filter.test(&Context::new(frame.layers()))
+                                }
+                            }
+                        };
+                        if matched {
+                            indices.push(frame.index());
+                        }
+                    }
+                }
+                if !job.cancelled.load(Ordering::Relaxed) {
+                    callback.done(job.id, job.generation, job.range, indices);
+                }
+            }
+        })
+    }
+
+    pub fn process(&mut self, job: Job) {
+        self.sender.send(Some(job));
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for _ in 0..self.handles.len() {
+            self.sender.send(None);
+        }
+        while let Some(h) = self.handles.pop() {
+            h.join().unwrap();
+        }
+    }
+}
@@ -1,10 +1,95 @@
-use genet_abi::{attr::Attr, fixed::MutFixed, layer::Layer, token::Token};
-use std::{fmt, mem};
+use array_vec::ArrayVec;
+use decoder::dispatcher::Dispatcher;
+use genet_abi::{
+    attr::Attr,
+    expert::{Expert, Severity},
+    fixed::MutFixed,
+    layer::Layer,
+    slice::ByteSlice,
+    token::Token,
+    variant::Variant,
+};
+use parking_lot::RwLock;
+use segment::Segment;
+use std::{
+    fmt, io, mem, slice,
+    sync::{Arc, Weak},
+};
+
+/// The numbers of attributes, payloads and expert items of a layer.
+type Stage = (usize, usize, usize);
+
+fn stage_of(layer: &Layer) -> Stage {
+    (
+        layer.attrs().len(),
+        layer.payloads().len(),
+        layer.experts().len(),
+    )
+}
+
+fn free(layer: MutFixed<Layer>) {
+    unsafe { drop(Box::from_raw(layer.as_mut_ptr())) };
+}
+
+/// Frees link data created from a boxed slice, as `reader::Worker` requires of readers.
+fn free_data(data: &ByteSlice) {
+    if !data.is_empty() {
+        let data = unsafe { slice::from_raw_parts_mut(data.as_ptr() as *mut u8, data.len()) };
+        unsafe { drop(Box::from_raw(data)) };
+    }
+}
+
+/// The first and last children and the next sibling of a layer.
+///
+/// Indices are zero if absent, since the root layer is never a child.
+#[derive(Clone, Copy, Default)]
+struct Link {
+    first: u32,
+    last: u32,
+    next: u32,
+}
+
+/// Iterates over the children of a layer.
+struct Children<'a> {
+    links: &'a [Link],
+    next: u32,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next == 0 {
+            return None;
+        }
+        let index = self.next as usize;
+        self.next = self.links.get(index).map_or(0, |link| link.next);
+        Some(index)
+    }
+}
+
+/// Layers which are kept while a frame is released.
+struct Retained {
+    layers: Vec<(usize, MutFixed<Layer>)>,
+    parents: Vec<u32>,
+    decoders: Vec<Token>,
+    visits: Vec<(u32, Token)>,
+}
 
 pub struct Frame {
     index: u32,
+    generation: u32,
     layers: Vec<MutFixed<Layer>>,
-    tree_indices: Vec<u8>,
+    parents: Vec<u32>,
+    links: Vec<Link>,
+    decoders: Vec<Token>,
+    /// The decoders which have visited each layer.
+    visits: Vec<(u32, Token)>,
+    stage: Vec<Stage>,
+    retained: Option<Retained>,
+    borrowed: Vec<usize>,
+    owned: bool,
+    root_attrs: usize,
 }
 
 impl fmt::Debug for Frame {
@@ -17,10 +102,20 @@ unsafe impl Send for Frame {}
 
 impl Frame {
     pub fn new(index: u32, root: MutFixed<Layer>) -> Frame {
+        let root_attrs = root.attrs().len();
         Frame {
             index,
+            generation: 0,
             layers: vec![root],
-            tree_indices: Vec::new(),
+            parents: Vec::new(),
+            links: vec![Link::default()],
+            decoders: Vec::new(),
+            visits: Vec::new(),
+            stage: Vec::new(),
+            retained: None,
+            borrowed: Vec::new(),
+            owned: true,
+            root_attrs,
         }
     }
 
@@ -28,6 +123,33 @@ impl Frame {
         self.index
     }
 
+    /// Returns the number of times the frame has been decoded again with another profile.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Replaces self with the same frame decoded again and returns the old one.
+    ///
+    /// The link data is handed over to the new frame.
+    pub fn replace(&mut self, mut frame: Frame) -> Frame {
+        frame.generation = self.generation.wrapping_add(1);
+        frame.owned = self.owned;
+        self.owned = false;
+        mem::replace(self, frame)
+    }
+
+    /// Moves the link data of the root layer to the segment file.
+    pub(crate) fn move_data(&mut self, segment: &mut Segment) -> io::Result<()> {
+        let data = self.layers[0].data();
+        let copy = segment.write(self.index, &data)?;
+        self.layers[0].set_data(copy);
+        if self.owned {
+            free_data(&data);
+            self.owned = false;
+        }
+        Ok(())
+    }
+
     pub fn layers(&self) -> &[MutFixed<Layer>] {
         &self.layers
     }
@@ -41,27 +163,570 @@ impl Frame {
         None
     }
 
-    pub fn fetch_layers(&mut self) -> Vec<MutFixed<Layer>> {
-        let mut v = Vec::new();
-        mem::swap(&mut self.layers, &mut v);
-        v
+    /// Returns the index of the parent of the layer at `index`.
+    ///
+    /// The root layer has no parent.
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        if index > 0 {
+            self.parents.get(index - 1).map(|&p| p as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the indices of the children of the layer at `index` in order.
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        Children {
+            links: &self.links,
+            next: self.links.get(index).map_or(0, |link| link.first),
+        }
     }
 
-    pub fn set_layers(&mut self, layers: Vec<MutFixed<Layer>>) {
-        self.layers = layers;
+    /// Returns the index of the primary layer, which is reached by following the first child.
+    pub fn primary(&self) -> usize {
+        let mut index = 0;
+        while let Some(child) = self.children(index).next() {
+            index = child;
+        }
+        index
     }
 
-    pub fn tree_indices(&self) -> &[u8] {
-        &self.tree_indices
+    /// Adds a layer as the last child of the layer at `parent`.
+    ///
+    /// `decoder` identifies the decoder which added the layer.
+    pub fn add_layer(&mut self, parent: usize, layer: MutFixed<Layer>, decoder: Token) {
+        self.layers.push(layer);
+        self.parents.push(parent as u32);
+        self.decoders.push(decoder);
+        self.link(parent, self.layers.len() - 1);
     }
 
-    pub fn fetch_tree_indices(&mut self) -> Vec<u8> {
-        let mut v = Vec::new();
-        mem::swap(&mut self.tree_indices, &mut v);
-        v
+    /// Appends the layer at `index` to the children of the layer at `parent`.
+    fn link(&mut self, parent: usize, index: usize) {
+        self.links.resize(self.layers.len(), Link::default());
+        let last = self.links[parent].last as usize;
+        if last == 0 {
+            self.links[parent].first = index as u32;
+        } else {
+            self.links[last].next = index as u32;
+        }
+        self.links[parent].last = index as u32;
+    }
+
+    /// Rebuilds the links between the layers from their parents.
+    fn relink(&mut self) {
+        self.links.clear();
+        self.links.resize(self.layers.len(), Link::default());
+        for index in 1..self.layers.len() {
+            if let Some(parent) = self.parent(index) {
+                self.link(parent, index);
+            }
+        }
     }
 
-    pub fn set_tree_indices(&mut self, tree_indices: Vec<u8>) {
-        self.tree_indices = tree_indices;
+    /// Returns the decoder which added the layer at `index`.
+    pub fn decoder(&self, index: usize) -> Option<Token> {
+        if index > 0 {
+            self.decoders.get(index - 1).cloned()
+        } else {
+            None
+        }
+    }
+
+    /// Records that the decoder has visited the layer at `index`.
+    pub fn visit(&mut self, index: usize, decoder: Token) {
+        self.visits.push((index as u32, decoder));
+    }
+
+    /// Returns true if the decoder has visited the layer at `index`.
+    pub fn is_visited(&self, index: usize, decoder: Token) -> bool {
+        self.visits.contains(&(index as u32, decoder))
+    }
+
+    /// Returns the number of decoder errors recorded on the layers.
+    pub fn errors(&self) -> usize {
+        let id = Token::from("_.error");
+        self.layers
+            .iter()
+            .map(|layer| layer.attrs().iter().filter(|attr| attr.id() == id).count())
+            .sum()
+    }
+
+    /// Returns the ids of the decoders which panicked while decoding the layers.
+    pub fn panics(&self) -> Vec<String> {
+        let error = Token::from("_.error");
+        let decoder = Token::from("_.error.decoder");
+        let panic = Token::from("_.error.panic");
+        let mut panics = Vec::new();
+        for layer in &self.layers {
+            let mut id = String::new();
+            for attr in layer.attrs() {
+                if attr.id() == error {
+                    id.clear();
+                } else if attr.id() == decoder {
+                    if let Ok(Variant::String(s)) = attr.try_get(layer) {
+                        id = s.to_string();
+                    }
+                } else if attr.id() == panic {
+                    panics.push(id.clone());
+                }
+            }
+        }
+        panics
+    }
+
+    /// Returns the expert items recorded on the layers.
+    pub fn experts(&self) -> impl Iterator<Item = &Expert> {
+        self.layers.iter().flat_map(|layer| layer.experts().iter())
+    }
+
+    /// Returns the approximate heap size of the layers added by decoders.
+    pub fn decoded_size(&self) -> usize {
+        let layers = self
+            .layers
+            .iter()
+            .skip(1)
+            .map(|layer| {
+                mem::size_of::<Layer>()
+                    + mem::size_of_val(layer.attrs())
+                    + mem::size_of_val(layer.payloads())
+                    + layer.buffer_size()
+            })
+            .sum::<usize>();
+        layers
+            + mem::size_of_val(self.parents.as_slice())
+            + mem::size_of_val(self.links.as_slice())
+            + mem::size_of_val(self.decoders.as_slice())
+            + mem::size_of_val(self.visits.as_slice())
+    }
+
+    /// Records the layers decoded by parallel decoders, which can be decoded again after `release`.
+    pub fn record_stage(&mut self) {
+        self.stage = self.layers.iter().map(|layer| stage_of(layer)).collect();
+    }
+
+    /// Returns true if the decoded layers have been released.
+    pub fn is_released(&self) -> bool {
+        self.retained.is_some()
+    }
+
+    /// Frees the layers added by parallel decoders and keeps the root layer.
+    ///
+    /// The layers added or extended by serial and async decoders are retained,
+    /// since they depend on the preceding frames.
+    pub fn release(&mut self) {
+        if self.retained.is_some() {
+            return;
+        }
+        let staged = self.stage.len().max(1).min(self.layers.len());
+        let mut retained = Retained {
+            layers: Vec::new(),
+            parents: self.parents.split_off(staged - 1),
+            decoders: self.decoders.split_off(staged - 1),
+            visits: Vec::new(),
+        };
+        for (index, layer) in self.layers.drain(1..).enumerate() {
+            let index = index + 1;
+            if index >= staged || self.stage[index] != stage_of(&layer) {
+                retained.layers.push((index, layer));
+            } else {
+                free(layer);
+            }
+        }
+        // The visits of the freed layers are recorded again by `restore`.
+        retained.visits = self
+            .visits
+            .drain(..)
+            .filter(|(index, _)| retained.layers.iter().any(|(i, _)| *i == *index as usize))
+            .collect();
+        self.parents.clear();
+        self.decoders.clear();
+        self.relink();
+        self.retained = Some(retained);
+    }
+
+    /// Decodes the root layer of a released frame again into a new frame.
+    ///
+    /// The new frame must be passed to `restore` or freed by `dispose`. Unlike `restore`,
+    /// this does not modify self, so it can run while the frames are locked for reading.
+    pub fn decode_root(&self, disp: &mut Dispatcher) -> Frame {
+        // Unlike `root_copy`, the root layer keeps the attributes added by the decoders.
+        let mut frame = self.copy_with_root((*self.layers[0]).clone());
+        disp.process_frame(&mut frame);
+        frame
+    }
+
+    /// Returns an undecoded copy of a released frame, which owns a copy of the link data.
+    ///
+    /// Unlike the copies sharing the link data, it can be decoded while the frames are not
+    /// locked. It must be freed by `dispose`.
+    pub fn detached_copy(&self) -> Frame {
+        let mut root = (*self.layers[0]).clone();
+        let data = root.add_buffer(root.data().to_vec());
+        root.set_data(data);
+        self.copy_with_root(root)
+    }
+
+    fn copy_with_root(&self, root: Layer) -> Frame {
+        let mut frame = Frame::new(self.index, MutFixed::new(root));
+        frame.generation = self.generation;
+        frame.owned = false;
+        frame.root_attrs = self.root_attrs;
+        frame.stage = self.stage.clone();
+        frame
+    }
+
+    /// Puts back the layers decoded by `decode_root` and the retained layers after `release`.
+    pub fn restore(&mut self, decoded: Frame) {
+        let Frame {
+            layers,
+            parents,
+            decoders,
+            visits,
+            ..
+        } = decoded;
+        let mut layers = layers.into_iter();
+        if let Some(root) = layers.next() {
+            free(root);
+        }
+        if let Some(retained) = self.retained.take() {
+            self.layers.extend(layers);
+            self.parents = parents;
+            self.decoders = decoders;
+            self.visits = visits;
+            self.relink();
+            self.attach(retained, false);
+        } else {
+            for layer in layers {
+                free(layer);
+            }
+        }
+    }
+
+    /// Puts back retained layers in place of the layers decoded again.
+    ///
+    /// Borrowed layers are left to their owner by `dispose`.
+    fn attach(&mut self, retained: Retained, borrow: bool) {
+        if self.layers.len() != self.stage.len() {
+            // The parallel decoders did not reproduce the same layers,
+            // so the retained layers no longer fit in the frame.
+            if !borrow {
+                for (_, layer) in retained.layers {
+                    free(layer);
+                }
+            }
+            let message = "the layers of serial and async decoders were lost \
+                           since the frame was decoded differently";
+            let root = &mut self.layers[0];
+            if root.experts().iter().all(|e| e.message() != message) {
+                root.add_expert(Expert::new(Severity::Error, "decoder", message));
+            }
+            return;
+        }
+        for (index, layer) in retained.layers {
+            if index < self.layers.len() {
+                free(mem::replace(&mut self.layers[index], layer));
+            } else {
+                self.layers.push(layer);
+            }
+            if borrow {
+                self.borrowed.push(index);
+            }
+        }
+        self.parents.extend(retained.parents);
+        self.decoders.extend(retained.decoders);
+        self.visits.extend(retained.visits);
+        self.relink();
+    }
+
+    /// Returns an undecoded copy of the frame, which must be freed by `dispose`.
+    ///
+    /// The copy shares the link data of self and only has the attributes added by the reader.
+    pub fn root_copy(&self) -> Frame {
+        let root = &self.layers[0];
+        let mut layer = Layer::new(root.class(), root.data());
+        for attr in root.attrs().iter().take(self.root_attrs) {
+            layer.add_attr(attr.clone());
+        }
+        let root = MutFixed::new(layer);
+        let mut frame = Frame::new(self.index, root);
+        frame.owned = false;
+        frame
+    }
+
+    /// Returns a decoded copy of a released frame, which must be freed by `dispose`.
+    ///
+    /// The copy borrows the retained layers of self.
+    pub fn decode_copy(&self, disp: &mut Dispatcher) -> Frame {
+        let mut frame = self.decode_root(disp);
+        frame.borrow_retained(self);
+        frame
+    }
+
+    /// Puts the retained layers of the released frame `origin` into a decoded copy of it.
+    ///
+    /// The layers are borrowed, so self must be freed by `dispose` before `origin` changes.
+    pub fn borrow_retained(&mut self, origin: &Frame) {
+        if let Some(retained) = &origin.retained {
+            let retained = Retained {
+                layers: retained
+                    .layers
+                    .iter()
+                    .map(|(index, layer)| {
+                        (*index, unsafe { MutFixed::from_ptr(layer.as_mut_ptr()) })
+                    })
+                    .collect(),
+                parents: retained.parents.clone(),
+                decoders: retained.decoders.clone(),
+                visits: retained.visits.clone(),
+            };
+            self.attach(retained, true);
+        }
+    }
+
+    /// Frees all the layers of the frame and the link data it owns.
+    pub fn dispose(mut self) {
+        #[cfg(test)]
+        tests::DISPOSED.lock().push(self.layers[0].id());
+        if self.owned {
+            free_data(&self.layers[0].data());
+        }
+        for (index, layer) in self.layers.drain(..).enumerate() {
+            if !self.borrowed.contains(&index) {
+                free(layer);
+            }
+        }
+        if let Some(retained) = self.retained.take() {
+            for (_, layer) in retained.layers {
+                free(layer);
+            }
+        }
+    }
+}
+
+/// A handle to a stored frame, which is looked up again on each access.
+#[derive(Clone)]
+pub struct FrameRef {
+    frames: Weak<RwLock<ArrayVec<Frame>>>,
+    index: usize,
+    generation: u32,
+}
+
+impl FrameRef {
+    pub(crate) fn new(frames: &Arc<RwLock<ArrayVec<Frame>>>, frame: &Frame) -> FrameRef {
+        FrameRef {
+            frames: Arc::downgrade(frames),
+            index: frame.index() as usize,
+            generation: frame.generation(),
+        }
+    }
+
+    /// Calls `f` with the frame while the frames are locked for reading.
+    ///
+    /// Returns `None` if the frame has been evicted or decoded again with another profile.
+    pub fn with<T, F: FnOnce(&Frame) -> T>(&self, f: F) -> Option<T> {
+        let frames = self.frames.upgrade()?;
+        let frames = frames.read();
+        match frames.get(self.index) {
+            Some(frame) if frame.generation() == self.generation => Some(f(frame)),
+            _ => None,
+        }
+    }
+}
+
+/// A handle to a layer of a stored frame.
+#[derive(Clone)]
+pub struct LayerRef {
+    frame: FrameRef,
+    index: usize,
+}
+
+impl LayerRef {
+    pub fn new(frame: FrameRef, index: usize) -> LayerRef {
+        LayerRef { frame, index }
+    }
+
+    /// Calls `f` with the layer while the frames are locked for reading.
+    ///
+    /// Returns `None` if the layer has been released or the frame is no longer available.
+    pub fn with<T, F: FnOnce(&Layer) -> T>(&self, f: F) -> Option<T> {
+        let index = self.index;
+        self.frame
+            .with(|frame| frame.layers().get(index).map(|layer| f(layer)))
+            .and_then(|value| value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use decoder::dispatcher::Dispatcher;
+    use frame::Frame;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        decoder::ExecType,
+        expert::{Expert, Severity},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        token::Token,
+    };
+    use parking_lot::Mutex;
+    use profile::Profile;
+
+    lazy_static! {
+        /// The root layer ids of the disposed frames.
+        pub static ref DISPOSED: Mutex<Vec<Token>> = Mutex::new(Vec::new());
+    }
+
+    fn layer(id: &str) -> MutFixed<Layer> {
+        let class = Fixed::new(LayerClass::builder(id).build());
+        MutFixed::new(Layer::new(class, vec![]))
+    }
+
+    #[test]
+    fn parent() {
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("eth"), Token::from("eth"));
+        frame.add_layer(1, layer("ipv4"), Token::from("ipv4"));
+        assert_eq!(frame.parent(0), None);
+        assert_eq!(frame.parent(1), Some(0));
+        assert_eq!(frame.parent(2), Some(1));
+        assert_eq!(frame.parent(3), None);
+        frame.dispose();
+    }
+
+    #[test]
+    fn children() {
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("eth"), Token::from("eth"));
+        frame.add_layer(1, layer("ipv4"), Token::from("ipv4"));
+        frame.add_layer(0, layer("trailer"), Token::from("trailer"));
+        frame.add_layer(1, layer("ipv4-defrag"), Token::from("ipv4-defrag"));
+        assert_eq!(frame.children(0).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(frame.children(1).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(frame.children(2).count(), 0);
+        assert_eq!(frame.children(5).count(), 0);
+        frame.dispose();
+    }
+
+    #[test]
+    fn primary() {
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        assert_eq!(frame.primary(), 0);
+        frame.add_layer(0, layer("eth"), Token::from("eth"));
+        frame.add_layer(0, layer("trailer"), Token::from("trailer"));
+        frame.add_layer(1, layer("ipv4"), Token::from("ipv4"));
+        assert_eq!(frame.primary(), 3);
+        frame.dispose();
+    }
+
+    #[test]
+    fn root_copy() {
+        let class = Fixed::new(LayerClass::builder("[link-1]").build());
+        let attr = Fixed::new(AttrClass::builder("link.length").build());
+        let mut root = Layer::new(class, vec![1, 2, 3]);
+        root.add_attr(Attr::builder(attr.clone()).value(3u64).build());
+        let mut frame = Frame::new(0, MutFixed::new(root));
+        frame.add_layer(0, layer("eth"), Token::from("eth"));
+        frame.layers[0].add_attr(Attr::builder(attr).value(0u64).build());
+        frame.layers[0].add_expert(Expert::new(Severity::Warning, "malformed", "truncated"));
+
+        let copy = frame.root_copy();
+        assert_eq!(copy.layers().len(), 1);
+        assert_eq!(copy.layers()[0].attrs().len(), 1);
+        assert!(copy.layers()[0].experts().is_empty());
+
+        // The link data is handed over to the frame which replaces the original.
+        let mut frame = frame;
+        let old = frame.replace(copy);
+        old.dispose();
+        assert_eq!(&frame.layers()[0].data()[..], &[1, 2, 3]);
+        frame.dispose();
+    }
+
+    #[test]
+    fn restore() {
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.record_stage();
+        frame.add_layer(0, layer("tcp-stream"), Token::from("tcp-stream"));
+        frame.visit(0, Token::from("eth"));
+        frame.visit(1, Token::from("tcp"));
+
+        frame.release();
+        assert!(frame.is_released());
+        assert_eq!(frame.layers().len(), 1);
+
+        let mut disp = Dispatcher::new(&ExecType::ParallelSync, &Profile::new());
+        let copy = frame.decode_copy(&mut disp);
+        assert_eq!(copy.layers()[1].id(), Token::from("tcp-stream"));
+        copy.dispose();
+
+        let decoded = frame.decode_root(&mut disp);
+        assert!(frame.is_released());
+        frame.restore(decoded);
+        assert!(!frame.is_released());
+        assert_eq!(frame.layers()[1].id(), Token::from("tcp-stream"));
+        assert_eq!(frame.parent(1), Some(0));
+        assert_eq!(frame.children(0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(frame.decoder(1), Some(Token::from("tcp-stream")));
+
+        // The visits of the retained layers are kept.
+        assert!(!frame.is_visited(0, Token::from("eth")));
+        assert!(frame.is_visited(1, Token::from("tcp")));
+        frame.dispose();
+    }
+
+    #[test]
+    fn lost_layers() {
+        let mut frame = Frame::new(0, layer("[link-1]"));
+        frame.add_layer(0, layer("eth"), Token::from("eth"));
+        frame.record_stage();
+        frame.add_layer(1, layer("tcp-stream"), Token::from("tcp-stream"));
+        frame.release();
+
+        // The parallel decoders no longer add the eth layer.
+        let mut disp = Dispatcher::new(&ExecType::ParallelSync, &Profile::new());
+        let copy = frame.decode_copy(&mut disp);
+        assert_eq!(copy.layers().len(), 1);
+        assert_eq!(copy.experts().count(), 1);
+        copy.dispose();
+
+        for _ in 0..2 {
+            let decoded = frame.decode_root(&mut disp);
+            frame.restore(decoded);
+            frame.release();
+        }
+        assert_eq!(frame.layers().len(), 1);
+        assert_eq!(frame.experts().count(), 1);
+        assert_eq!(frame.experts().next().unwrap().severity(), Severity::Error);
+        frame.dispose();
+    }
+
+    #[test]
+    fn detached_copy() {
+        let mut frame = Frame::new(
+            0,
+            MutFixed::new(Layer::new(
+                Fixed::new(LayerClass::builder("[link-1]").build()),
+                vec![1, 2, 3],
+            )),
+        );
+        frame.record_stage();
+        frame.add_layer(0, layer("tcp-stream"), Token::from("tcp-stream"));
+        frame.release();
+
+        let mut copy = frame.detached_copy();
+        assert_eq!(&copy.layers()[0].data()[..], &[1, 2, 3]);
+        assert_ne!(
+            copy.layers()[0].data().as_ptr(),
+            frame.layers()[0].data().as_ptr()
+        );
+
+        let mut disp = Dispatcher::new(&ExecType::ParallelSync, &Profile::new());
+        disp.process_frame(&mut copy);
+        copy.borrow_retained(&frame);
+        assert_eq!(copy.layers()[1].id(), Token::from("tcp-stream"));
+        copy.dispose();
+        assert_eq!(frame.layers().len(), 1);
+        frame.dispose();
     }
 }
@@ -8,5 +8,6 @@ pub trait Output: Send + Debug {
 }
 
 pub trait Input: Send + Debug {
+    /// Reads the next batch of frames, or an empty batch at the end of the input.
     fn read(&mut self) -> Result<Vec<MutFixed<Layer>>>;
 }
@@ -3,12 +3,16 @@ extern crate fnv;
 extern crate genet_abi;
 extern crate genet_filter;
 extern crate genet_napi;
+#[macro_use]
+extern crate lazy_static;
 extern crate libc;
 extern crate libloading;
+extern crate memmap;
 extern crate num_cpus;
 extern crate parking_lot;
 extern crate serde;
 extern crate serde_json;
+extern crate tempfile;
 
 #[macro_use]
 extern crate serde_derive;
@@ -19,7 +23,9 @@ pub mod session;
 
 mod array_vec;
 mod decoder;
-mod frame;
+mod filter;
+pub mod frame;
 mod io;
 mod result;
+mod segment;
 mod store;
@@ -1,4 +1,4 @@
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
 use genet_abi::{
     context::Context,
     decoder::DecoderBox,
@@ -8,17 +8,24 @@ use genet_abi::{
     token::Token,
     writer::WriterBox,
 };
+use genet_filter::{error::Warning, Filter};
 use libloading::Library;
 use num_cpus;
-use std::{fmt, io, mem};
+use std::{cmp::Reverse, fmt, io, mem};
 
 #[derive(Serialize, Clone, Default)]
 pub struct Profile {
     concurrency: u32,
+    memory_limit: usize,
+    ring_frames: u32,
+    ring_seconds: u32,
     decoders: Vec<DecoderBox>,
+    disabled_decoders: FnvHashSet<String>,
     readers: Vec<ReaderBox>,
     writers: Vec<WriterBox>,
     config: FnvHashMap<String, String>,
+    #[serde(skip)]
+    tokens: FnvHashSet<Token>,
 }
 
 impl fmt::Debug for Profile {
@@ -31,10 +38,15 @@ impl Profile {
     pub fn new() -> Profile {
         Profile {
             concurrency: 4,
+            memory_limit: 0,
+            ring_frames: 0,
+            ring_seconds: 0,
             decoders: Vec::new(),
+            disabled_decoders: FnvHashSet::default(),
             readers: Vec::new(),
             writers: Vec::new(),
             config: FnvHashMap::default(),
+            tokens: FnvHashSet::default(),
         }
     }
 
@@ -50,6 +62,40 @@ impl Profile {
         self.concurrency
     }
 
+    /// Sets the memory budget for decoded layers in bytes.
+    ///
+    /// Layers of the oldest frames are released beyond the budget and decoded again on demand.
+    /// Link data is kept in a temporary file meanwhile. 0 means unlimited.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Sets the maximum number of frames to keep.
+    ///
+    /// The oldest frames are evicted beyond the limit. 0 means unlimited.
+    pub fn set_ring_frames(&mut self, frames: u32) {
+        self.ring_frames = frames;
+    }
+
+    pub fn ring_frames(&self) -> u32 {
+        self.ring_frames
+    }
+
+    /// Sets the maximum time span of frames to keep in seconds.
+    ///
+    /// Frames older than the latest frame by more than the span are evicted. 0 means unlimited.
+    pub fn set_ring_seconds(&mut self, seconds: u32) {
+        self.ring_seconds = seconds;
+    }
+
+    pub fn ring_seconds(&self) -> u32 {
+        self.ring_seconds
+    }
+
     pub fn get_config(&self, key: &str) -> Option<String> {
         self.config.get(key).map(|s| s.to_string())
     }
@@ -60,10 +106,63 @@ impl Profile {
             .or_insert_with(|| String::from(value));
     }
 
+    /// Registers a layer or attr token provided by the loaded packages.
+    pub fn register_token(&mut self, id: &str) {
+        self.tokens.insert(Token::from(id));
+    }
+
+    /// Returns warnings for identifiers in the filter that match no registered token.
+    ///
+    /// No warnings are reported if no tokens have been registered.
+    pub fn check_filter(&self, filter: &Filter) -> Vec<Warning> {
+        if self.tokens.is_empty() {
+            return Vec::new();
+        }
+        filter.check(|token| self.tokens.contains(&token))
+    }
+
     pub fn decoders(&self) -> impl Iterator<Item = &DecoderBox> {
         self.decoders.iter()
     }
 
+    /// Enables or disables the decoder with the given `Metadata.id`.
+    pub fn set_decoder_enabled(&mut self, id: &str, enabled: bool) {
+        if enabled {
+            self.disabled_decoders.remove(id);
+        } else {
+            self.disabled_decoders.insert(id.to_string());
+        }
+    }
+
+    pub fn is_decoder_enabled(&self, id: &str) -> bool {
+        !self.disabled_decoders.contains(id)
+    }
+
+    /// Returns the enabled decoders in execution order.
+    ///
+    /// Decoders run in descending order of priority, but never before the decoders listed in
+    /// their `runs_after`. Decoders with the same priority keep the load order.
+    pub fn ordered_decoders(&self) -> Vec<DecoderBox> {
+        let mut decoders = self
+            .decoders
+            .iter()
+            .map(|d| (*d, d.metadata()))
+            .filter(|(_, m)| self.is_decoder_enabled(&m.id))
+            .collect::<Vec<_>>();
+        decoders.sort_by_key(|(_, m)| Reverse(m.priority));
+        let mut ordered = Vec::with_capacity(decoders.len());
+        while !decoders.is_empty() {
+            let ready = decoders.iter().position(|(_, m)| {
+                m.runs_after
+                    .iter()
+                    .all(|id| decoders.iter().all(|(_, d)| d.id != *id))
+            });
+            // Falls back to the priority order if the dependencies form a cycle.
+            ordered.push(decoders.remove(ready.unwrap_or(0)).0);
+        }
+        ordered
+    }
+
     pub fn readers(&self) -> impl Iterator<Item = &ReaderBox> {
         self.readers.iter()
     }
@@ -91,7 +190,7 @@ impl Profile {
         {
             let func = unsafe { lib.get::<FnVersion>(b"genet_abi_version")? };
 
-            // In the initial development, minor version changes may break ABI.
+            // In the initial development, minor versions and ABI revisions may break ABI.
             fn canonical(ver: u64) -> u64 {
                 if ver >> 32 == 0 {
                     ver
@@ -121,9 +220,18 @@ impl Profile {
         if let Ok(func) = unsafe { lib.get::<FnGetDecoders>(b"genet_abi_v1_get_decoders") } {
             let mut len = 0;
             let ptr = func(&mut len);
-            for i in 0..len {
-                self.decoders.push(unsafe { (*ptr.offset(i as isize)) });
+            let decoders = (0..len)
+                .map(|i| unsafe { (*ptr.offset(i as isize)) })
+                .collect::<Vec<_>>();
+            for decoder in &decoders {
+                if let Err(err) = decoder.try_metadata() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed decoder metadata: {}", err),
+                    ));
+                }
             }
+            self.decoders.extend(decoders);
         }
 
         if let Ok(func) = unsafe { lib.get::<FnGetReaders>(b"genet_abi_v1_get_readers") } {
@@ -146,3 +254,119 @@ impl Profile {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use genet_abi::{
+        context::Context,
+        decoder::{Decoder, DecoderBox, Metadata, Status, Worker},
+        layer::{LayerStack, Parent},
+        result::Result,
+    };
+    use profile::Profile;
+
+    struct TestWorker {}
+
+    impl Worker for TestWorker {
+        fn decode(
+            &mut self,
+            _ctx: &mut Context,
+            _stack: &LayerStack,
+            _parent: &mut Parent,
+        ) -> Result<Status> {
+            Ok(Status::Skip)
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestDecoder {
+        id: &'static str,
+        priority: i32,
+        runs_after: &'static [&'static str],
+    }
+
+    impl Decoder for TestDecoder {
+        fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+            Box::new(TestWorker {})
+        }
+
+        fn metadata(&self) -> Metadata {
+            Metadata {
+                id: self.id.into(),
+                priority: self.priority,
+                runs_after: self.runs_after.iter().map(|id| id.to_string()).collect(),
+                ..Metadata::default()
+            }
+        }
+    }
+
+    fn ordered_ids(decoders: &[TestDecoder]) -> Vec<String> {
+        let mut profile = Profile::new();
+        for decoder in decoders {
+            profile.decoders.push(DecoderBox::new(decoder.clone()));
+        }
+        profile
+            .ordered_decoders()
+            .iter()
+            .map(|d| d.metadata().id)
+            .collect()
+    }
+
+    fn decoder(
+        id: &'static str,
+        priority: i32,
+        runs_after: &'static [&'static str],
+    ) -> TestDecoder {
+        TestDecoder {
+            id,
+            priority,
+            runs_after,
+        }
+    }
+
+    #[test]
+    fn priority() {
+        let ids = ordered_ids(&[
+            decoder("a", 0, &[]),
+            decoder("b", 1, &[]),
+            decoder("c", 0, &[]),
+        ]);
+        assert_eq!(ids, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn runs_after() {
+        let ids = ordered_ids(&[
+            decoder("a", 1, &["c"]),
+            decoder("b", 0, &[]),
+            decoder("c", 0, &["unknown"]),
+        ]);
+        assert_eq!(ids, vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn cycle() {
+        let ids = ordered_ids(&[
+            decoder("a", 0, &["b"]),
+            decoder("b", 1, &["a"]),
+            decoder("c", 0, &[]),
+        ]);
+        assert_eq!(ids, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut profile = Profile::new();
+        profile
+            .decoders
+            .push(DecoderBox::new(decoder("a", 0, &["b"])));
+        profile.decoders.push(DecoderBox::new(decoder("b", 0, &[])));
+        profile.set_decoder_enabled("b", false);
+        let ids = profile
+            .ordered_decoders()
+            .iter()
+            .map(|d| d.metadata().id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["a"]);
+    }
+}
@@ -0,0 +1,93 @@
+use genet_abi::slice::ByteSlice;
+use memmap::{MmapMut, MmapOptions};
+use std::{collections::VecDeque, io::Result};
+use tempfile;
+
+/// The size of the chunks mapped at once.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+struct Chunk {
+    map: MmapMut,
+    last: u32,
+}
+
+/// Append-only temporary files which keep the link data of frames.
+///
+/// Each chunk is a separate file which stays mapped until the frames in it are released,
+/// so that slices into the data remain valid while the operating system pages it out.
+pub struct Segment {
+    chunks: VecDeque<Chunk>,
+    offset: usize,
+}
+
+impl Segment {
+    pub fn new() -> Segment {
+        Segment {
+            chunks: VecDeque::new(),
+            offset: 0,
+        }
+    }
+
+    /// Copies the data of the frame at `index` and returns a slice of the copy.
+    pub fn write(&mut self, index: u32, data: &[u8]) -> Result<ByteSlice> {
+        if data.is_empty() {
+            return Ok(ByteSlice::new());
+        }
+        let fits = self
+            .chunks
+            .back()
+            .map_or(false, |chunk| chunk.map.len() - self.offset >= data.len());
+        if !fits {
+            let size = (data.len() + CHUNK_SIZE - 1) / CHUNK_SIZE * CHUNK_SIZE;
+            let file = tempfile::tempfile()?;
+            file.set_len(size as u64)?;
+            let map = unsafe { MmapOptions::new().len(size).map_mut(&file)? };
+            self.chunks.push_back(Chunk { map, last: index });
+            self.offset = 0;
+        }
+        let chunk = self.chunks.back_mut().unwrap();
+        chunk.last = index;
+        let copy = &mut chunk.map[self.offset..self.offset + data.len()];
+        copy.copy_from_slice(data);
+        self.offset += data.len();
+        Ok(unsafe { ByteSlice::from_raw_parts(copy.as_ptr(), copy.len()) })
+    }
+
+    /// Unmaps the chunks which only hold the data of frames before `first`.
+    pub fn release(&mut self, first: u32) {
+        while self.chunks.len() > 1 && self.chunks[0].last < first {
+            self.chunks.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use segment::{Segment, CHUNK_SIZE};
+
+    #[test]
+    fn write() {
+        let mut segment = Segment::new();
+        let small = segment.write(0, &[1, 2, 3]).unwrap();
+        let large = segment.write(1, &vec![4; CHUNK_SIZE + 1]).unwrap();
+        let empty = segment.write(2, &[]).unwrap();
+        assert_eq!(&small[..], &[1, 2, 3]);
+        assert_eq!(large.len(), CHUNK_SIZE + 1);
+        assert!(large.iter().all(|&b| b == 4));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn release() {
+        let mut segment = Segment::new();
+        segment.write(0, &vec![0; CHUNK_SIZE]).unwrap();
+        segment.write(1, &vec![1; CHUNK_SIZE]).unwrap();
+        let last = segment.write(2, &[2]).unwrap();
+        assert_eq!(segment.chunks.len(), 3);
+        segment.release(1);
+        assert_eq!(segment.chunks.len(), 2);
+        segment.release(3);
+        assert_eq!(segment.chunks.len(), 1);
+        assert_eq!(&last[..], &[2]);
+    }
+}
@@ -1,5 +1,5 @@
-use frame::Frame;
-use genet_abi::{self, fixed::MutFixed, layer::Layer, reader, writer};
+use frame::{Frame, FrameRef};
+use genet_abi::{self, expert::Severity, fixed::MutFixed, layer::Layer, reader, writer};
 use genet_filter::Filter;
 use io::{Input, Output};
 use profile::Profile;
@@ -7,6 +7,8 @@ use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::{fmt, ops::Range};
 use store::{self, Store};
 
+pub use store::Stats;
+
 pub struct Session {
     store: Store,
     callback: Box<Callback>,
@@ -29,7 +31,7 @@ impl Session {
         }
     }
 
-    pub fn frames(&self, range: Range<usize>) -> Vec<*const Frame> {
+    pub fn frames(&mut self, range: Range<usize>) -> Vec<FrameRef> {
         self.store.frames(range)
     }
 
@@ -41,6 +43,14 @@ impl Session {
         self.store.set_filter(id, filter);
     }
 
+    /// Decodes the frames in the session again with the given profile.
+    ///
+    /// Derived layers are discarded and the filters are re-applied afterwards.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.store.set_profile(profile.clone());
+        self.profile = profile;
+    }
+
     pub fn create_reader(&mut self, id: &str, arg: &str) -> u32 {
         if let Some(reader) = self
             .profile
@@ -55,7 +65,7 @@ impl Session {
                     return self.io_cnt;
                 }
                 Err(err) => {
-                    let err = Error(err.description().to_string());
+                    let err = Error(err.to_string());
                     self.callback.on_event(Event::Error(Box::new(err)));
                 }
             }
@@ -78,7 +88,7 @@ impl Session {
                     return self.io_cnt;
                 }
                 Err(err) => {
-                    let err = Error(err.description().to_string());
+                    let err = Error(err.to_string());
                     self.callback.on_event(Event::Error(Box::new(err)));
                 }
             }
@@ -94,6 +104,10 @@ impl Session {
         self.store.len()
     }
 
+    pub fn stats(&self) -> Stats {
+        self.store.stats()
+    }
+
     pub fn profile(&self) -> &Profile {
         &self.profile
     }
@@ -136,6 +150,27 @@ impl store::Callback for StoreCallback {
         self.callback.on_event(Event::FilteredFrames(id, frames));
     }
 
+    fn on_filter_progress(&self, id: u32, scanned: u32, total: u32) {
+        self.callback
+            .on_event(Event::FilterProgress(id, scanned, total));
+    }
+
+    fn on_filter_complete(&self, id: u32, frames: u32) {
+        self.callback.on_event(Event::FilterComplete(id, frames));
+    }
+
+    fn on_frames_evicted(&self, first: u32) {
+        self.callback.on_event(Event::Evicted(first));
+    }
+
+    fn on_stats_updated(&self, stats: Stats) {
+        self.callback.on_event(Event::Stats(stats));
+    }
+
+    fn on_decoder_panicked(&self, decoder: String, frame: u32) {
+        self.callback.on_event(Event::Panic(decoder, frame));
+    }
+
     fn on_output_done(&self, id: u32, error: Option<Box<::std::error::Error + Send>>) {
         self.callback.on_event(Event::Output(id, error));
     }
@@ -154,11 +189,23 @@ pub enum Event {
     Frames(u32),
     AsyncFrames(u32),
     FilteredFrames(u32, u32),
+    FilterProgress(u32, u32, u32),
+    FilterComplete(u32, u32),
+    Evicted(u32),
+    Stats(Stats),
+    Panic(String, u32),
     Input(u32, Option<Box<::std::error::Error + Send>>),
     Output(u32, Option<Box<::std::error::Error + Send>>),
     Error(Box<::std::error::Error + Send>),
 }
 
+#[derive(Serialize)]
+struct ExpertCount {
+    severity: Severity,
+    group: String,
+    count: u32,
+}
+
 pub trait Callback: CallbackClone + Send {
     fn on_event(&self, event: Event);
 }
@@ -207,6 +254,56 @@ impl Serialize for Event {
                 s.serialize_entry("length", &len)?;
                 s.end()
             }
+            Event::FilterProgress(id, scanned, total) => {
+                let mut s = serializer.serialize_map(Some(4))?;
+                s.serialize_entry("type", "filter_progress")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("scanned", &scanned)?;
+                s.serialize_entry("total", &total)?;
+                s.end()
+            }
+            Event::FilterComplete(id, len) => {
+                let mut s = serializer.serialize_map(Some(3))?;
+                s.serialize_entry("type", "filter_complete")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("length", &len)?;
+                s.end()
+            }
+            Event::Evicted(first) => {
+                let mut s = serializer.serialize_map(Some(2))?;
+                s.serialize_entry("type", "evicted")?;
+                s.serialize_entry("first", &first)?;
+                s.end()
+            }
+            Event::Stats(stats) => {
+                let mut experts = stats
+                    .experts
+                    .iter()
+                    .map(|(&(severity, group), &count)| ExpertCount {
+                        severity,
+                        group: group.to_string(),
+                        count,
+                    })
+                    .collect::<Vec<_>>();
+                experts.sort_by(|a, b| {
+                    b.severity
+                        .cmp(&a.severity)
+                        .then_with(|| a.group.cmp(&b.group))
+                });
+                let mut s = serializer.serialize_map(Some(4))?;
+                s.serialize_entry("type", "stats")?;
+                s.serialize_entry("errors", &stats.errors)?;
+                s.serialize_entry("errorFrames", &stats.error_frames)?;
+                s.serialize_entry("experts", &experts)?;
+                s.end()
+            }
+            Event::Panic(decoder, frame) => {
+                let mut s = serializer.serialize_map(Some(3))?;
+                s.serialize_entry("type", "panic")?;
+                s.serialize_entry("decoder", decoder)?;
+                s.serialize_entry("frame", &frame)?;
+                s.end()
+            }
             Event::Input(id, err) => {
                 let mut s = serializer.serialize_map(Some(3))?;
                 s.serialize_entry("type", "input")?;
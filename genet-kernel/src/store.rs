@@ -1,29 +1,41 @@
 use array_vec::ArrayVec;
 use crossbeam_channel;
-use decoder::{parallel, serial};
+use decoder::{asynchronous, dispatcher::Dispatcher, parallel, serial};
+use filter;
 use fnv::FnvHashMap;
-use frame::Frame;
-use genet_abi::{fixed::MutFixed, layer::Layer};
+use frame::{Frame, FrameRef};
+use genet_abi::{expert::Severity, fixed::MutFixed, layer::Layer, token::Token, variant::Value};
 use genet_filter::{self, Filter};
 use io::{Input, Output};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use profile::Profile;
 use result::Result;
+use segment::Segment;
 use std::{
+    collections::{BTreeMap, VecDeque},
     fmt,
     ops::Range,
     panic::{self, AssertUnwindSafe},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread::{self, JoinHandle},
 };
 
 const OUTPUT_BLOCK_SIZE: usize = 65536;
 const MAX_FILTER_SIZE: usize = 16384;
+const REDECODE_BLOCK_SIZE: usize = 1024;
 
 pub trait Callback: Send {
     fn on_frames_updated(&self, _frames: u32) {}
     fn on_async_frames_updated(&self, _frames: u32) {}
     fn on_filtered_frames_updated(&self, _id: u32, _frames: u32) {}
+    fn on_filter_progress(&self, _id: u32, _scanned: u32, _total: u32) {}
+    fn on_filter_complete(&self, _id: u32, _frames: u32) {}
+    fn on_frames_evicted(&self, _first: u32) {}
+    fn on_stats_updated(&self, _stats: Stats) {}
+    fn on_decoder_panicked(&self, _decoder: String, _frame: u32) {}
     fn on_output_done(&self, _id: u32, _error: Option<Box<::std::error::Error + Send>>) {}
     fn on_input_done(&self, _id: u32, _error: Option<Box<::std::error::Error + Send>>) {}
     fn on_error(&self, _error: Box<::std::error::Error + Send>) {}
@@ -34,8 +46,11 @@ enum Command {
     PushFrames(Option<u32>, Result<Vec<MutFixed<Layer>>>),
     PushSerialFrames(Vec<Frame>),
     StoreFrames(Vec<Frame>),
+    StoreAsyncFrames(Range<usize>),
     SetFilter(u32, Option<Filter>),
+    StoreFilteredFrames(u32, u32, Range<usize>, Vec<u32>),
     PushOutput(u32, Box<Output>, Option<Filter>),
+    SetProfile(Profile),
     Close,
 }
 
@@ -56,42 +71,161 @@ impl fmt::Display for Error {
 
 type FrameStore = Arc<RwLock<ArrayVec<Frame>>>;
 type FilteredFrameStore = Arc<RwLock<FnvHashMap<u32, Vec<u32>>>>;
+type ResidencyStore = Arc<Mutex<Residency>>;
+type StatsStore = Arc<Mutex<Stats>>;
+type InputDone = (u32, u32, Option<Box<::std::error::Error + Send>>);
+
+/// Tracks frames holding decoded layers, in the order they were decoded.
+#[derive(Default)]
+struct Residency {
+    size: usize,
+    seq: u64,
+    latest: FnvHashMap<u32, u64>,
+    queue: VecDeque<(u64, u32)>,
+}
+
+impl Residency {
+    fn push(&mut self, frame: &Frame) {
+        self.seq += 1;
+        self.size += frame.decoded_size();
+        self.latest.insert(frame.index(), self.seq);
+        self.queue.push_back((self.seq, frame.index()));
+    }
+
+    /// Releases the layers of the least recently decoded frames until they fit in `limit`.
+    fn shrink(&mut self, frames: &mut ArrayVec<Frame>, limit: usize) {
+        while self.size > limit {
+            let (seq, index) = match self.queue.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self.latest.get(&index) != Some(&seq) {
+                continue;
+            }
+            self.latest.remove(&index);
+            if let Some(frame) = frames.get_mut(index as usize) {
+                self.size = self.size.saturating_sub(frame.decoded_size());
+                frame.release();
+            }
+        }
+    }
+
+    fn remove(&mut self, frame: &Frame) {
+        if self.latest.remove(&frame.index()).is_some() {
+            self.size = self.size.saturating_sub(frame.decoded_size());
+        }
+    }
+}
+
+/// Decoding statistics of a session.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// The number of decoder errors.
+    pub errors: u32,
+    /// The number of frames with decoder errors.
+    pub error_frames: u32,
+    /// The number of expert items by severity and group.
+    pub experts: FnvHashMap<(Severity, Token), u32>,
+}
 
-#[derive(Debug)]
 pub struct Store {
     sender: crossbeam_channel::Sender<Command>,
     ev: EventLoop,
+    profile: Profile,
     frames: FrameStore,
     filtered: FilteredFrameStore,
+    residency: ResidencyStore,
+    stats: StatsStore,
+    restorer: Option<Dispatcher>,
     inputs: FnvHashMap<u32, InputContext>,
     inputs_trash: Vec<InputContext>,
 }
 
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Store")
+    }
+}
+
 impl Store {
     pub fn new<C: 'static + Callback + Clone>(profile: Profile, callback: C) -> Store {
         let frames = Arc::new(RwLock::new(ArrayVec::new()));
         let filtered = Arc::new(RwLock::new(FnvHashMap::default()));
-        let (ev, send) = EventLoop::new(profile, callback, frames.clone(), filtered.clone());
+        let residency = Arc::new(Mutex::new(Residency::default()));
+        let stats = Arc::new(Mutex::new(Stats::default()));
+        let (ev, send) = EventLoop::new(
+            profile.clone(),
+            callback,
+            frames.clone(),
+            filtered.clone(),
+            residency.clone(),
+            stats.clone(),
+        );
         Store {
             sender: send,
             ev,
+            profile,
             frames,
             filtered,
+            residency,
+            stats,
+            restorer: None,
             inputs: FnvHashMap::default(),
             inputs_trash: Vec::new(),
         }
     }
 
-    pub fn frames(&self, range: Range<usize>) -> Vec<*const Frame> {
-        self.frames
-            .read()
-            .iter()
-            .skip(range.start)
-            .take(range.end.saturating_sub(range.start))
-            .map(|f| f as *const Frame)
+    pub fn frames(&mut self, range: Range<usize>) -> Vec<FrameRef> {
+        let limit = self.profile.memory_limit();
+        if limit > 0 {
+            self.restore_frames(range.clone(), limit);
+        }
+        let frames = self.frames.read();
+        range
+            .filter_map(|index| frames.get(index))
+            .map(|f| FrameRef::new(&self.frames, f))
             .collect::<Vec<_>>()
     }
 
+    /// Decodes released frames in the range again.
+    ///
+    /// The frames are decoded while they are locked for reading, and only put back
+    /// while they are locked for writing, so that the other threads are not blocked.
+    fn restore_frames(&mut self, range: Range<usize>, limit: usize) {
+        let decoded = {
+            let frames = self.frames.read();
+            let start = range.start.max(frames.first());
+            let end = range.end.min(frames.len());
+            let profile = &self.profile;
+            let restorer = &mut self.restorer;
+            (start..end)
+                .filter_map(|index| frames.get(index).map(|frame| (index, frame)))
+                .filter(|(_, frame)| frame.is_released())
+                .map(|(index, frame)| {
+                    let restorer = restorer.get_or_insert_with(|| Dispatcher::restorer(profile));
+                    (index, frame.generation(), frame.decode_root(restorer))
+                })
+                .collect::<Vec<_>>()
+        };
+        if decoded.is_empty() {
+            return;
+        }
+
+        let mut frames = self.frames.write();
+        let mut residency = self.residency.lock();
+        residency.shrink(&mut frames, limit);
+        for (index, generation, copy) in decoded {
+            match frames.get_mut(index) {
+                // The frame may have been evicted or replaced in the meantime.
+                Some(frame) if frame.is_released() && frame.generation() == generation => {
+                    frame.restore(copy);
+                    residency.push(frame);
+                }
+                _ => copy.dispose(),
+            }
+        }
+    }
+
     pub fn filtered_frames(&self, id: u32, range: Range<usize>) -> Vec<u32> {
         let filtered = self.filtered.read();
         if let Some(vec) = filtered.get(&id) {
@@ -110,10 +244,21 @@ impl Store {
         frames.len()
     }
 
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().clone()
+    }
+
     pub fn set_filter(&mut self, id: u32, filter: Option<Filter>) {
         self.sender.send(Command::SetFilter(id, filter));
     }
 
+    /// Decodes all frames again with the given profile.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile.clone();
+        self.restorer = None;
+        self.sender.send(Command::SetProfile(profile));
+    }
+
     pub fn push_output<O: 'static + Output>(&mut self, id: u32, output: O, filter: Option<Filter>) {
         self.sender
             .send(Command::PushOutput(id, Box::new(output), filter));
@@ -127,12 +272,14 @@ impl Store {
             while let Some(sender) = sender.upgrade() {
                 match input.read() {
                     Ok(layers) => {
-                        if !layers.is_empty() {
-                            sender.send(Command::PushFrames(Some(id), Ok(layers)));
+                        let end = layers.is_empty();
+                        sender.send(Command::PushFrames(Some(id), Ok(layers)));
+                        if end {
+                            break;
                         }
                     }
                     Err(err) => {
-                        let err = Error(err.description().to_string());
+                        let err = Error(err.to_string());
                         sender.send(Command::PushFrames(Some(id), Err(Box::new(err))));
                         break;
                     }
@@ -191,9 +338,91 @@ impl serial::Callback for SerialCallback {
     }
 }
 
+#[derive(Clone)]
+struct AsyncCallback {
+    sender: crossbeam_channel::Sender<Command>,
+}
+
+impl asynchronous::Callback for AsyncCallback {
+    fn done(&self, range: Range<usize>) {
+        self.sender.send(Command::StoreAsyncFrames(range));
+    }
+}
+
+#[derive(Clone)]
+struct FilterCallback {
+    sender: crossbeam_channel::Sender<Command>,
+}
+
+impl filter::Callback for FilterCallback {
+    fn done(&self, id: u32, generation: u32, range: Range<usize>, indices: Vec<u32>) {
+        self.sender
+            .send(Command::StoreFilteredFrames(id, generation, range, indices));
+    }
+}
+
 struct FilterContext {
     filter: Filter,
+    generation: u32,
+    cancelled: Arc<AtomicBool>,
     offset: usize,
+    scanned: usize,
+    total: usize,
+    first: usize,
+    pending: BTreeMap<usize, (usize, Vec<u32>)>,
+    complete: bool,
+}
+
+impl Drop for FilterContext {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Pools {
+    parallel: parallel::Pool,
+    serial: serial::Pool,
+    asynchronous: asynchronous::Pool,
+    filter: filter::Pool,
+}
+
+impl Pools {
+    fn new(
+        profile: &Profile,
+        next: usize,
+        frames: &FrameStore,
+        sender: &crossbeam_channel::Sender<Command>,
+    ) -> Pools {
+        Pools {
+            parallel: parallel::Pool::new(
+                profile,
+                &ParallelCallback {
+                    sender: sender.clone(),
+                },
+            ),
+            serial: serial::Pool::new(
+                profile.clone(),
+                next,
+                SerialCallback {
+                    sender: sender.clone(),
+                },
+            ),
+            asynchronous: asynchronous::Pool::new(
+                profile.clone(),
+                frames.clone(),
+                AsyncCallback {
+                    sender: sender.clone(),
+                },
+            ),
+            filter: filter::Pool::new(
+                profile,
+                frames,
+                &FilterCallback {
+                    sender: sender.clone(),
+                },
+            ),
+        }
+    }
 }
 
 struct EventLoop {
@@ -207,62 +436,163 @@ impl EventLoop {
         callback: C,
         frames: FrameStore,
         filtered: FilteredFrameStore,
+        residency: ResidencyStore,
+        stats: StatsStore,
     ) -> (EventLoop, crossbeam_channel::Sender<Command>) {
         let (send, recv) = crossbeam_channel::unbounded();
         let sender = send.clone();
         let handle = thread::spawn(move || {
             let err_callback = callback.clone();
             let result = panic::catch_unwind(AssertUnwindSafe(move || {
+                let mut profile = profile;
                 let mut filter_map = FnvHashMap::default();
-                let mut ppool = parallel::Pool::new(
-                    &profile,
-                    &ParallelCallback {
-                        sender: sender.clone(),
-                    },
-                );
-                let mut spool = serial::Pool::new(
-                    profile.clone(),
-                    SerialCallback {
-                        sender: sender.clone(),
-                    },
-                );
+                let mut generation = 0;
+                let mut pools = Pools::new(&profile, 0, &frames, &sender);
                 let mut cnt = 0;
+                let mut async_len = 0;
+                let mut in_flight = 0;
+                let mut next_profile = None;
+                let mut held_inputs = Vec::new();
+                let mut inputs_done = Vec::new();
+                let mut segment = Segment::new();
+                let mut evicted = VecDeque::new();
+                let mut redecoding = None;
                 callback.on_frames_updated(0);
                 callback.on_async_frames_updated(0);
                 loop {
                     if let Some(cmd) = recv.recv() {
                         match cmd {
                             Command::PushFrames(id, result) => {
-                                Self::process_input(id, result, &mut cnt, &mut ppool, &callback)
+                                if next_profile.is_some() {
+                                    held_inputs.push((id, result));
+                                } else {
+                                    in_flight += Self::process_input(
+                                        id,
+                                        result,
+                                        Self::segment(&profile, &mut segment),
+                                        &mut cnt,
+                                        &mut inputs_done,
+                                        &mut pools.parallel,
+                                        &callback,
+                                    );
+                                }
                             }
                             Command::PushSerialFrames(vec) => {
-                                spool.process(vec);
+                                pools.serial.process(vec);
+                            }
+                            Command::StoreFrames(vec) => {
+                                Self::process_stats(&vec, &stats, &callback);
+                                let range = vec.first().map_or(0, |f| f.index() as usize)
+                                    ..vec.last().map_or(0, |f| f.index() as usize + 1);
+                                evicted.extend(Self::process_store_frames(
+                                    vec, &profile, &frames, &filtered, &residency, &callback,
+                                ));
+                                if redecoding.map_or(false, |end| range.end >= end) {
+                                    redecoding = None;
+                                }
+                                pools.asynchronous.process(range);
+                            }
+                            Command::StoreAsyncFrames(range) => {
+                                in_flight -= range.len();
+                                async_len = range.end;
+                                Self::process_async_frames(
+                                    range, &profile, &frames, &residency, &callback,
+                                );
+                            }
+                            Command::SetFilter(id, filter) => {
+                                generation += 1;
+                                Self::process_push_filter(
+                                    id,
+                                    filter,
+                                    generation,
+                                    &filtered,
+                                    &mut filter_map,
+                                    &callback,
+                                )
                             }
-                            Command::StoreFrames(mut vec) => {
-                                let len = {
-                                    let mut frames = frames.write();
-                                    for f in vec {
-                                        frames.push(f);
-                                    }
-                                    frames.len()
-                                };
-                                callback.on_frames_updated(len as u32);
-                                callback.on_async_frames_updated(len as u32);
+                            Command::StoreFilteredFrames(id, generation, range, indices) => {
+                                Self::process_filtered_frames(
+                                    id,
+                                    generation,
+                                    range,
+                                    indices,
+                                    &filtered,
+                                    &mut filter_map,
+                                    &callback,
+                                )
                             }
-                            Command::SetFilter(id, filter) => Self::process_push_filter(
-                                id,
-                                filter,
-                                &filtered,
-                                &mut filter_map,
-                                &callback,
+                            Command::PushOutput(id, output, filter) => Self::process_output(
+                                id, output, filter, &profile, &frames, &callback,
                             ),
-                            Command::PushOutput(id, output, filter) => {
-                                Self::process_output(id, output, &filter, &frames, &callback)
+                            Command::SetProfile(profile) => {
+                                next_profile = Some(profile);
+                            }
+                            Command::Close => {
+                                drop(pools);
+                                for frame in evicted {
+                                    frame.dispose();
+                                }
+                                return;
+                            }
+                        }
+                    }
+
+                    // Swap the pools once the frames in flight have been stored.
+                    if in_flight == 0 {
+                        if let Some(next) = next_profile.take() {
+                            profile = next;
+                            let filters = filter_map
+                                .iter()
+                                .map(|(id, fctx)| (*id, fctx.filter.clone()))
+                                .collect::<Vec<_>>();
+                            for (id, filter) in filters {
+                                generation += 1;
+                                Self::process_push_filter(
+                                    id,
+                                    Some(filter),
+                                    generation,
+                                    &filtered,
+                                    &mut filter_map,
+                                    &callback,
+                                );
+                            }
+                            let first = frames.read().first();
+                            pools = Pools::new(&profile, first, &frames, &sender);
+                            async_len = first;
+                            *stats.lock() = Stats::default();
+                            callback.on_stats_updated(Stats::default());
+                            callback.on_async_frames_updated(first as u32);
+                            let len = Self::process_redecode(&frames, &mut pools.parallel);
+                            if len > 0 {
+                                in_flight += len;
+                                redecoding = Some(frames.read().len());
+                            }
+                            for (id, result) in held_inputs.drain(..) {
+                                in_flight += Self::process_input(
+                                    id,
+                                    result,
+                                    Self::segment(&profile, &mut segment),
+                                    &mut cnt,
+                                    &mut inputs_done,
+                                    &mut pools.parallel,
+                                    &callback,
+                                );
                             }
-                            Command::Close => return,
                         }
                     }
-                    Self::process_filters(&frames, &filtered, &mut filter_map, &callback);
+                    // Root copies being decoded again share the data of the stored frames.
+                    if redecoding.is_none() {
+                        Self::process_release(&mut evicted, &frames, &mut segment);
+                    }
+                    Self::process_inputs_done(&frames, async_len, &mut inputs_done, &callback);
+                    Self::process_filters(
+                        &frames,
+                        async_len,
+                        &filtered,
+                        &mut filter_map,
+                        &mut pools.filter,
+                        &callback,
+                    );
                 }
             }));
             if let Err(err) = result {
@@ -286,21 +616,32 @@ impl EventLoop {
         (ev, send)
     }
 
+    /// Returns the segment file if the profile has a memory budget.
+    fn segment<'a>(profile: &Profile, segment: &'a mut Segment) -> Option<&'a mut Segment> {
+        if profile.memory_limit() > 0 {
+            Some(segment)
+        } else {
+            None
+        }
+    }
+
     fn process_input(
         id: Option<u32>,
         result: Result<Vec<MutFixed<Layer>>>,
+        segment: Option<&mut Segment>,
         cnt: &mut u32,
+        inputs_done: &mut Vec<InputDone>,
         pool: &mut parallel::Pool,
         callback: &Callback,
-    ) {
+    ) -> usize {
         match result {
             Ok(layers) => {
                 if layers.is_empty() {
                     if let Some(id) = id {
-                        callback.on_input_done(id, None);
+                        inputs_done.push((id, *cnt, None));
                     }
                 } else {
-                    let frames = layers
+                    let mut frames = layers
                         .into_iter()
                         .enumerate()
                         .map(|(i, root)| {
@@ -308,49 +649,281 @@ impl EventLoop {
                             Frame::new(index, root)
                         })
                         .collect::<Vec<_>>();
-                    *cnt += frames.len() as u32;
+                    if let Some(segment) = segment {
+                        for frame in &mut frames {
+                            if let Err(err) = frame.move_data(segment) {
+                                let err = Error(err.to_string());
+                                callback.on_error(Box::new(err));
+                                break;
+                            }
+                        }
+                    }
+                    let len = frames.len();
+                    *cnt += len as u32;
                     pool.process(frames);
+                    return len;
                 }
             }
             Err(err) => {
                 if let Some(id) = id {
-                    callback.on_input_done(id, Some(err));
+                    inputs_done.push((id, *cnt, Some(err)));
+                }
+            }
+        }
+        0
+    }
+
+    /// Frees the evicted frames and their link data.
+    ///
+    /// Clients access frames through handles, which no longer find evicted frames,
+    /// so the frames can be freed right away.
+    fn process_release(evicted: &mut VecDeque<Frame>, frames: &FrameStore, segment: &mut Segment) {
+        if evicted.is_empty() {
+            return;
+        }
+        for frame in evicted.drain(..) {
+            frame.dispose();
+        }
+        segment.release(frames.read().first() as u32);
+    }
+
+    /// Feeds undecoded copies of the stored frames to the pool.
+    fn process_redecode(frames: &FrameStore, pool: &mut parallel::Pool) -> usize {
+        let frames = frames.read();
+        let mut copies = frames.iter().map(|frame| frame.root_copy()).peekable();
+        let mut len = 0;
+        while copies.peek().is_some() {
+            let block = copies
+                .by_ref()
+                .take(REDECODE_BLOCK_SIZE)
+                .collect::<Vec<_>>();
+            len += block.len();
+            pool.process(block);
+        }
+        len
+    }
+
+    /// Reports finished inputs once all of their frames have been decoded.
+    fn process_inputs_done(
+        frames: &FrameStore,
+        async_len: usize,
+        inputs_done: &mut Vec<InputDone>,
+        callback: &Callback,
+    ) {
+        if inputs_done.is_empty() {
+            return;
+        }
+        let len = frames.read().len().min(async_len);
+        let mut index = 0;
+        while index < inputs_done.len() {
+            if inputs_done[index].1 as usize <= len {
+                let (id, _, err) = inputs_done.remove(index);
+                callback.on_input_done(id, err);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn process_stats(vec: &[Frame], stats: &StatsStore, callback: &Callback) {
+        for frame in vec {
+            for decoder in frame.panics() {
+                callback.on_decoder_panicked(decoder, frame.index());
+            }
+        }
+        let (errors, error_frames) = vec
+            .iter()
+            .map(|frame| frame.errors())
+            .filter(|&errors| errors > 0)
+            .fold((0, 0), |(errors, frames), n| (errors + n, frames + 1));
+        let mut experts = vec.iter().flat_map(|frame| frame.experts()).peekable();
+        if error_frames > 0 || experts.peek().is_some() {
+            let stats = {
+                let mut stats = stats.lock();
+                stats.errors += errors as u32;
+                stats.error_frames += error_frames;
+                for expert in experts {
+                    *stats
+                        .experts
+                        .entry((expert.severity(), expert.group()))
+                        .or_insert(0) += 1;
+                }
+                stats.clone()
+            };
+            callback.on_stats_updated(stats);
+        }
+    }
+
+    fn process_store_frames(
+        vec: Vec<Frame>,
+        profile: &Profile,
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        residency: &ResidencyStore,
+        callback: &Callback,
+    ) -> Vec<Frame> {
+        let (len, evicted, replaced) = {
+            let mut frames = frames.write();
+            let mut residency = residency.lock();
+            let mut replaced = Vec::new();
+            for f in vec {
+                let index = f.index() as usize;
+                if index >= frames.len() {
+                    frames.push(f);
+                } else if let Some(frame) = frames.get_mut(index) {
+                    // A decoded copy replaces the stored frame.
+                    residency.remove(frame);
+                    replaced.push(frame.replace(f));
+                } else {
+                    replaced.push(f);
                 }
             }
+            let end = Self::ring_end(profile, &frames);
+            let evicted = frames.evict(end);
+            for f in evicted.iter() {
+                residency.remove(f);
+            }
+            (frames.len(), evicted, replaced)
+        };
+        for f in replaced {
+            f.dispose();
+        }
+        if !evicted.is_empty() {
+            let first = frames.read().first();
+            Self::process_eviction(first, filtered, callback);
+        }
+        callback.on_frames_updated(len as u32);
+        evicted
+    }
+
+    /// Makes frames which have passed the async stage subject to the memory budget.
+    fn process_async_frames(
+        range: Range<usize>,
+        profile: &Profile,
+        frames: &FrameStore,
+        residency: &ResidencyStore,
+        callback: &Callback,
+    ) {
+        let limit = profile.memory_limit();
+        if limit > 0 {
+            let mut frames = frames.write();
+            let mut residency = residency.lock();
+            for index in range.clone() {
+                if let Some(frame) = frames.get(index) {
+                    if !frame.is_released() {
+                        residency.push(frame);
+                    }
+                }
+            }
+            residency.shrink(&mut frames, limit);
+        }
+        callback.on_async_frames_updated(range.end as u32);
+    }
+
+    /// Returns the index of the first frame to keep in the ring buffer.
+    fn ring_end(profile: &Profile, frames: &ArrayVec<Frame>) -> usize {
+        let mut end = frames.first();
+        let max_frames = profile.ring_frames() as usize;
+        if max_frames > 0 {
+            end = end.max(frames.len().saturating_sub(max_frames));
+        }
+        let max_seconds = u64::from(profile.ring_seconds());
+        if max_seconds > 0 {
+            let token = Token::from("link.timestamp.sec");
+            let timestamp = |index: usize| {
+                frames.get(index).and_then(|frame| {
+                    let root = &frame.layers()[0];
+                    root.attr(token)
+                        .and_then(|attr| attr.try_get(root).ok())
+                        .and_then(|value| Value::<u64>::try_into(value).ok())
+                })
+            };
+            if let Some(latest) = timestamp(frames.len().wrapping_sub(1)) {
+                let min = latest.saturating_sub(max_seconds);
+                while end < frames.len() && timestamp(end).map_or(false, |ts| ts < min) {
+                    end += 1;
+                }
+            }
+        }
+        end
+    }
+
+    fn process_eviction(first: usize, filtered: &FilteredFrameStore, callback: &Callback) {
+        let mut updated = Vec::new();
+        {
+            let mut filtered = filtered.write();
+            for (id, vec) in filtered.iter_mut() {
+                let len = vec
+                    .iter()
+                    .take_while(|&&index| (index as usize) < first)
+                    .count();
+                if len > 0 {
+                    vec.drain(..len);
+                    updated.push((*id, vec.len()));
+                }
+            }
+        }
+        callback.on_frames_evicted(first as u32);
+        for (id, len) in updated {
+            callback.on_filtered_frames_updated(id, len as u32);
         }
     }
 
     fn process_output(
         id: u32,
         output: Box<Output>,
-        filter: &Option<Filter>,
+        mut filter: Option<Filter>,
+        profile: &Profile,
         frames: &FrameStore,
         callback: &Callback,
     ) {
         let frames = frames.read();
-        let mut offset = 0;
+        let mut disp = None;
+        let mut offset = frames.first();
         {
             let mut output = output;
             while offset < frames.len() {
                 let len = OUTPUT_BLOCK_SIZE.min(frames.len() - offset);
-                let frames = frames
+                let block = frames
                     .iter()
-                    .skip(offset)
+                    .skip(offset - frames.first())
                     .take(len)
-                    .filter(|frame| {
-                        let ctx = genet_filter::context::Context::new(frame.layers());
-                        filter.as_ref().map_or(true, |f| f.test(&ctx))
+                    .collect::<Vec<_>>();
+                let copies = block
+                    .iter()
+                    .map(|frame| {
+                        if frame.is_released() {
+                            let disp = disp.get_or_insert_with(|| Dispatcher::restorer(profile));
+                            Some(frame.decode_copy(disp))
+                        } else {
+                            None
+                        }
                     })
                     .collect::<Vec<_>>();
-                if let Err(err) = output.write(frames.as_slice()) {
-                    let err = Error(err.description().to_string());
+                let result = {
+                    let frames = block
+                        .iter()
+                        .zip(copies.iter())
+                        .map(|(frame, copy)| copy.as_ref().unwrap_or(frame))
+                        .filter(|frame| {
+                            let ctx = genet_filter::context::Context::new(frame.layers());
+                            filter.as_mut().map_or(true, |f| f.test(&ctx))
+                        })
+                        .collect::<Vec<_>>();
+                    output.write(frames.as_slice())
+                };
+                for copy in copies.into_iter().flatten() {
+                    copy.dispose();
+                }
+                if let Err(err) = result {
+                    let err = Error(err.to_string());
                     callback.on_output_done(id, Some(Box::new(err)));
                     return;
                 }
                 offset += len;
             }
             if let Err(err) = output.end() {
-                let err = Error(err.description().to_string());
+                let err = Error(err.to_string());
                 callback.on_output_done(id, Some(Box::new(err)));
                 return;
             }
@@ -361,6 +934,7 @@ impl EventLoop {
     fn process_push_filter(
         id: u32,
         filter: Option<Filter>,
+        generation: u32,
         filtered: &FilteredFrameStore,
         filter_map: &mut FnvHashMap<u32, FilterContext>,
         callback: &Callback,
@@ -369,8 +943,15 @@ impl EventLoop {
             filter_map.insert(
                 id,
                 FilterContext {
+                    filter,
+                    generation,
+                    cancelled: Arc::new(AtomicBool::new(false)),
                     offset: 0,
-                    filter: filter.clone(),
+                    scanned: 0,
+                    total: 0,
+                    first: 0,
+                    pending: BTreeMap::new(),
+                    complete: false,
                 },
             );
             callback.on_filtered_frames_updated(id, 0);
@@ -380,44 +961,86 @@ impl EventLoop {
         filtered.write().remove(&id);
     }
 
+    fn process_filtered_frames(
+        id: u32,
+        generation: u32,
+        range: Range<usize>,
+        indices: Vec<u32>,
+        filtered: &FilteredFrameStore,
+        filter_map: &mut FnvHashMap<u32, FilterContext>,
+        callback: &Callback,
+    ) {
+        let fctx = match filter_map.get_mut(&id) {
+            Some(fctx) if fctx.generation == generation => fctx,
+            _ => return,
+        };
+        fctx.pending.insert(range.start, (range.end, indices));
+        let scanned = fctx.scanned;
+
+        // Chunks may finish out of order; merge only the contiguous ones.
+        let len = {
+            let mut filtered = filtered.write();
+            let filtered = filtered.entry(id).or_insert_with(Vec::new);
+            let len = filtered.len();
+            while let Some((end, mut indices)) = fctx.pending.remove(&fctx.scanned) {
+                let first = fctx.first;
+                indices.retain(|&index| index as usize >= first);
+                filtered.append(&mut indices);
+                fctx.scanned = end;
+            }
+            if filtered.len() > len {
+                Some(filtered.len())
+            } else {
+                None
+            }
+        };
+        if let Some(len) = len {
+            callback.on_filtered_frames_updated(id, len as u32);
+        }
+        if fctx.scanned > scanned {
+            callback.on_filter_progress(id, fctx.scanned as u32, fctx.total as u32);
+        }
+    }
+
+    /// Schedules filter jobs for frames which have passed the async stage.
     fn process_filters(
         frames: &FrameStore,
+        async_len: usize,
         filtered: &FilteredFrameStore,
         filter_map: &mut FnvHashMap<u32, FilterContext>,
+        pool: &mut filter::Pool,
         callback: &Callback,
     ) {
+        let (first, len) = {
+            let frames = frames.read();
+            (frames.first(), frames.len().min(async_len))
+        };
+        let max_size = pool.concurrency() * 2 * MAX_FILTER_SIZE;
         for (id, fctx) in filter_map.iter_mut() {
-            loop {
-                let (mut indices, end) = {
-                    let frames = frames.read();
-                    let mut indices = frames
-                        .iter()
-                        .skip(fctx.offset)
-                        .take(MAX_FILTER_SIZE)
-                        .filter_map(|frame| {
-                            let ctx = genet_filter::context::Context::new(frame.layers());
-                            if fctx.filter.test(&ctx) {
-                                Some(frame.index())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    fctx.offset = frames.len().min(fctx.offset + MAX_FILTER_SIZE);
-                    (indices, fctx.offset >= frames.len())
-                };
-                if !indices.is_empty() {
-                    let len = {
-                        let mut filtered = filtered.write();
-                        let mut frames = filtered.entry(*id).or_insert_with(Vec::new);
-                        frames.append(&mut indices);
-                        frames.len()
-                    };
-                    callback.on_filtered_frames_updated(*id, len as u32);
-                }
-                if end {
-                    break;
-                }
+            fctx.total = len;
+            fctx.first = first;
+
+            // Skip evicted frames once no chunk is in flight.
+            if fctx.offset == fctx.scanned && fctx.offset < first {
+                fctx.offset = first;
+                fctx.scanned = first;
+            }
+            while fctx.offset < len && fctx.offset - fctx.scanned < max_size {
+                let end = len.min(fctx.offset + MAX_FILTER_SIZE);
+                pool.process(filter::Job {
+                    id: *id,
+                    generation: fctx.generation,
+                    range: fctx.offset..end,
+                    filter: fctx.filter.clone(),
+                    cancelled: fctx.cancelled.clone(),
+                });
+                fctx.offset = end;
+                fctx.complete = false;
+            }
+            if !fctx.complete && fctx.scanned >= len {
+                fctx.complete = true;
+                let frames = filtered.read().get(id).map_or(0, |f| f.len());
+                callback.on_filter_complete(*id, frames as u32);
             }
         }
     }
@@ -438,14 +1061,59 @@ impl fmt::Debug for EventLoop {
 
 #[cfg(test)]
 mod tests {
+    use frame::tests::DISPOSED;
+    use genet_abi::{
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        result::Result,
+        token::Token,
+    };
     use genet_filter::Filter;
+    use io::Input;
     use profile::Profile;
+    use std::{
+        sync::mpsc::{self, Sender},
+        thread,
+        time::Duration,
+    };
     use store::{Callback, Store};
 
     #[derive(Clone)]
     struct TestCallback {}
     impl Callback for TestCallback {}
 
+    #[derive(Clone)]
+    struct EvictionCallback(Sender<u32>);
+    impl Callback for EvictionCallback {
+        fn on_frames_evicted(&self, first: u32) {
+            let _ = self.0.send(first);
+        }
+    }
+
+    #[derive(Clone)]
+    struct FramesCallback(Sender<u32>);
+    impl Callback for FramesCallback {
+        fn on_frames_updated(&self, frames: u32) {
+            let _ = self.0.send(frames);
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestInput(usize, &'static str);
+    impl Input for TestInput {
+        fn read(&mut self) -> Result<Vec<MutFixed<Layer>>> {
+            if self.0 == 0 {
+                return Ok(Vec::new());
+            }
+            let class = Fixed::new(LayerClass::builder(self.1).build());
+            let layers = (0..self.0)
+                .map(|i| MutFixed::new(Layer::new(class.clone(), vec![i as u8; 16])))
+                .collect();
+            self.0 = 0;
+            Ok(layers)
+        }
+    }
+
     #[test]
     fn drop() {
         let profile = Profile::new();
@@ -460,4 +1128,48 @@ mod tests {
         assert_eq!(store.frames(100..0).len(), 0);
         assert_eq!(store.filtered_frames(0, 100..0).len(), 0);
     }
+
+    #[test]
+    fn evict() {
+        let mut profile = Profile::new();
+        profile.set_ring_frames(2);
+        let (send, recv) = mpsc::channel();
+        let mut store = Store::new(profile, EvictionCallback(send));
+        store.set_input(1, TestInput(4, "[link-evicted]"));
+        assert_eq!(recv.recv().unwrap(), 2);
+        let frames = store.frames(0..4);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].with(|f| f.index()), Some(2));
+
+        // The evicted frames are freed without waiting for the client.
+        let id = Token::from("[link-evicted]");
+        let disposed = || DISPOSED.lock().iter().filter(|&&d| d == id).count();
+        for _ in 0..100 {
+            if disposed() >= 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(disposed(), 2);
+    }
+
+    #[test]
+    fn set_profile() {
+        let (send, recv) = mpsc::channel();
+        let mut store = Store::new(Profile::new(), FramesCallback(send));
+        store.set_input(1, TestInput(4, "[link-1]"));
+        while recv.recv().unwrap() < 4 {}
+        let frames = store.frames(0..4);
+
+        // The frames decoded again replace the stored frames and take over their data.
+        store.set_profile(Profile::new());
+        while recv.recv().unwrap() < 4 {}
+        assert!(frames.iter().all(|frame| frame.with(|_| ()).is_none()));
+        let frames = store.frames(0..4);
+        assert_eq!(frames.len(), 4);
+        for (i, frame) in frames.iter().enumerate() {
+            let data = frame.with(|f| f.layers()[0].data().to_vec());
+            assert_eq!(data, Some(vec![i as u8; 16]));
+        }
+    }
 }
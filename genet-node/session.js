@@ -2,29 +2,15 @@ const native = require('./binding')
 const { Token } = native
 const { Disposable } = require('disposables')
 const { EventEmitter } = require('events')
-function consume (len, layerStack, indexStack) {
-  const indices = indexStack.splice(0, len)
-  const layers = layerStack.splice(0, len)
-  for (let index = 0; index < indices.length; index += 1) {
-    const parent = layers[index]
-    parent.children = (parent.children || [])
-      .concat(consume(indices[index], layerStack, indexStack))
+function treefy (frame) {
+  const { layers } = frame
+  layers.forEach((parent, index) => {
+    parent.children = frame.children(index).map((child) => layers[child])
     parent.children.forEach((layer) => {
       layer.parent = parent
     })
-  }
-  return layers
-}
-
-function treefy (layerStack, indexStack) {
-  const layers = [].concat(layerStack)
-  const indices = [].concat(indexStack)
-  const root = consume(1, layers, indices)
-  const len = layerStack.length - layers.length
-  if (indices.length >= len) {
-    consume(len, [].concat(layerStack), indices)
-  }
-  return root
+  })
+  return layers[0]
 }
 
 class Frame {
@@ -39,7 +25,7 @@ class Frame {
 
   get root () {
     if (!this._root) {
-      [this._root] = treefy(this._frame.layers, this._frame.treeIndices)
+      this._root = treefy(this._frame)
     }
     return this._root
   }
@@ -76,8 +62,31 @@ class Session extends EventEmitter {
           this._status.asyncFrames = event.length
           break
         case 'filtered_frames':
-          this._status.filters[Token.string(event.id)] =
-            { frames: event.length }
+          this._updateFilter(event.id, { frames: event.length })
+          break
+        case 'filter_progress':
+          this._updateFilter(event.id, {
+            scanned: event.scanned,
+            total: event.total,
+            complete: false,
+          })
+          break
+        case 'filter_complete':
+          this._updateFilter(event.id, {
+            frames: event.length,
+            complete: true,
+          })
+          break
+        case 'evicted':
+          this._status.firstFrame = event.first
+          break
+        case 'stats':
+          this._status.errors = event.errors
+          this._status.errorFrames = event.errorFrames
+          this._status.experts = event.experts
+          break
+        case 'panic':
+          this.emit('panic', event.decoder, event.frame)
           break
         case 'error':
           this.emit('error', event.error)
@@ -91,7 +100,11 @@ class Session extends EventEmitter {
     this._status = {
       filters: {},
       frames: 0,
+      firstFrame: 0,
       asyncFrames: 0,
+      errors: 0,
+      errorFrames: 0,
+      experts: [],
       stream: false,
     }
   }
@@ -100,6 +113,16 @@ class Session extends EventEmitter {
     this._sess.close()
   }
 
+  _updateFilter (id, status) {
+    const key = Token.string(id)
+    this._status.filters[key] = Object.assign({
+      frames: 0,
+      scanned: 0,
+      total: 0,
+      complete: false,
+    }, this._status.filters[key], status)
+  }
+
   frames (start, end) {
     return this._sess
       .frames(start, end)
@@ -115,10 +138,22 @@ class Session extends EventEmitter {
   }
 
   setFilter (id, filter = '') {
-    this._sess.setFilter(Token.get(id), filter)
+    const warnings = this._sess.setFilter(Token.get(id), filter)
     if (filter === '') {
       Reflect.deleteProperty(this._status.filters, id)
+    } else {
+      this._status.filters[id] = {
+        frames: 0,
+        scanned: 0,
+        total: 0,
+        complete: false,
+      }
     }
+    return warnings
+  }
+
+  setProfile (profile) {
+    this._sess.setProfile(profile)
   }
 
   createReader (id, arg = {}) {
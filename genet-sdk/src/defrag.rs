@@ -0,0 +1,587 @@
+//! Reassembly of fragmented datagrams.
+//!
+//! Type DefragWorker reassembles the fragments of a protocol such as IPv4 or IPv6,
+//! which is described by a Protocol.
+
+use attr::{Attr, AttrClass};
+use context::Context;
+use decoder::{Status, Worker};
+use expert::{Expert, Severity};
+use layer::{LayerStack, Parent, Payload};
+use result::Result;
+use slice::{ByteSlice, TryGet};
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+use token::Token;
+use variant::{Value, Variant};
+
+/// The identifiers of the layers, attributes and options of a fragmented protocol.
+pub struct Protocol {
+    /// The id of the layers which carry fragments.
+    pub layer: &'static str,
+    /// The id of the payloads which contain fragments.
+    pub fragment: &'static str,
+    pub src: &'static str,
+    pub dst: &'static str,
+    /// The attribute which identifies the datagram of a fragment.
+    pub id: &'static str,
+    /// The attribute of the offset of a fragment in units of 8 bytes.
+    pub offset: &'static str,
+    pub more_fragments: &'static str,
+    /// The maximum size of a reassembled datagram in bytes.
+    pub max_size: usize,
+    /// The config key of the reassembly timeout in seconds.
+    pub timeout: &'static str,
+    /// The config key of the reassembly buffer size in MiB.
+    pub memory_limit: &'static str,
+    pub reassembled: &'static AttrClass,
+    pub reassembled_length: &'static AttrClass,
+    pub reassembled_fragments: &'static AttrClass,
+}
+
+#[derive(Debug)]
+struct Datagram {
+    seq: u64,
+    timestamp: u64,
+    len: Option<usize>,
+    size: usize,
+    fragments: u32,
+    slices: BTreeMap<usize, Vec<u8>>,
+}
+
+impl Datagram {
+    fn new(seq: u64, timestamp: u64) -> Datagram {
+        Datagram {
+            seq,
+            timestamp,
+            len: None,
+            size: 0,
+            fragments: 0,
+            slices: BTreeMap::new(),
+        }
+    }
+
+    /// Stores the bytes which are not covered yet and returns true if the fragment overlaps.
+    fn put(&mut self, start: usize, data: ByteSlice) -> Result<bool> {
+        let end = start + data.len();
+        let covered = self
+            .slices
+            .range(..end)
+            .map(|(offset, slice)| (*offset, *offset + slice.len()))
+            .filter(|&(_, slice_end)| slice_end > start)
+            .collect::<Vec<_>>();
+        let mut pos = start;
+        for (offset, slice_end) in covered.iter().cloned() {
+            if offset > pos {
+                self.insert(pos, data.try_get(pos - start..offset - start)?);
+            }
+            pos = pos.max(slice_end);
+        }
+        if pos < end {
+            self.insert(pos, data.try_get(pos - start..)?);
+        }
+        self.fragments += 1;
+        Ok(!covered.is_empty())
+    }
+
+    /// Stores a copy of the data, since the frame it belongs to may be freed first.
+    fn insert(&mut self, start: usize, data: ByteSlice) {
+        self.size += data.len();
+        self.slices.insert(start, data.to_vec());
+    }
+
+    /// Returns the reassembled payload once all fragments have arrived.
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let len = self.len?;
+        if self.size < len {
+            return None;
+        }
+        let mut data = Vec::with_capacity(len);
+        for (offset, slice) in self.slices.iter() {
+            if *offset != data.len() {
+                return None;
+            }
+            data.extend_from_slice(&slice[..slice.len().min(len - offset)]);
+            if data.len() >= len {
+                break;
+            }
+        }
+        if data.len() == len {
+            Some(data)
+        } else {
+            None
+        }
+    }
+}
+
+/// The outcome of adding a fragment.
+#[derive(Debug, Default)]
+struct Reassembly {
+    /// The fragment exceeds the maximum datagram size and is ignored.
+    oversized: bool,
+    /// The fragment overlaps previous data.
+    overlapped: bool,
+    /// The fragment is the last one but disagrees with the length of a previous last fragment.
+    conflicted: bool,
+    /// Incomplete datagrams were dropped to fit in the memory limit.
+    dropped: bool,
+    /// The payload and the number of fragments of a completed datagram.
+    assembled: Option<(Vec<u8>, u32)>,
+}
+
+struct Tokens {
+    layer: Token,
+    fragment: Token,
+    src: Token,
+    dst: Token,
+    id: Token,
+    offset: Token,
+    more_fragments: Token,
+    timestamp: Token,
+}
+
+type Key<I> = (Vec<u8>, Vec<u8>, I, Token);
+
+/// A serial decoder worker which reassembles fragmented datagrams.
+///
+/// Fragments are grouped by the source and destination addresses,
+/// the datagram id of type `I` and the payload type.
+pub struct DefragWorker<I> {
+    protocol: Protocol,
+    tokens: Tokens,
+    map: HashMap<Key<I>, Datagram>,
+    /// The keys of incomplete datagrams ordered by their first timestamp.
+    order: BTreeMap<(u64, u64), Key<I>>,
+    seq: u64,
+    size: usize,
+    timeout: u64,
+    memory_limit: usize,
+}
+
+impl<I> DefragWorker<I>
+where
+    I: Hash + Eq + Clone,
+    Variant: Value<I>,
+{
+    pub fn new(ctx: &Context, protocol: Protocol) -> DefragWorker<I> {
+        let timeout = ctx.get_config(protocol.timeout).parse().unwrap_or(30);
+        let memory_limit: usize = ctx.get_config(protocol.memory_limit).parse().unwrap_or(4);
+        Self::with_limits(protocol, timeout, memory_limit * 1024 * 1024)
+    }
+
+    fn with_limits(protocol: Protocol, timeout: u64, memory_limit: usize) -> DefragWorker<I> {
+        let tokens = Tokens {
+            layer: Token::from(protocol.layer),
+            fragment: Token::from(protocol.fragment),
+            src: Token::from(protocol.src),
+            dst: Token::from(protocol.dst),
+            id: Token::from(protocol.id),
+            offset: Token::from(protocol.offset),
+            more_fragments: Token::from(protocol.more_fragments),
+            timestamp: Token::from("link.timestamp.sec"),
+        };
+        DefragWorker {
+            protocol,
+            tokens,
+            map: HashMap::new(),
+            order: BTreeMap::new(),
+            seq: 0,
+            size: 0,
+            timeout,
+            memory_limit,
+        }
+    }
+
+    /// Adds a fragment which starts at the byte offset `start` to the datagram of `key`.
+    fn reassemble(
+        &mut self,
+        key: Key<I>,
+        start: usize,
+        data: ByteSlice,
+        last: bool,
+        timestamp: u64,
+    ) -> Result<Reassembly> {
+        let mut result = Reassembly::default();
+        if start + data.len() > self.protocol.max_size {
+            result.oversized = true;
+            return Ok(result);
+        }
+
+        self.expire(timestamp);
+
+        let seq = self.seq;
+        self.seq += 1;
+        let (size, assembled) = {
+            let order = &mut self.order;
+            let datagram = self.map.entry(key.clone()).or_insert_with(|| {
+                order.insert((timestamp, seq), key.clone());
+                Datagram::new(seq, timestamp)
+            });
+            let size = datagram.size;
+            if last {
+                let end = start + data.len();
+                match datagram.len {
+                    Some(len) if len != end => result.conflicted = true,
+                    _ => datagram.len = Some(end),
+                }
+            }
+            result.overlapped = datagram.put(start, data)?;
+            let assembled = datagram.assemble().map(|data| (data, datagram.fragments));
+            (datagram.size - size, assembled)
+        };
+        self.size += size;
+
+        if assembled.is_some() {
+            self.remove(&key);
+            result.assembled = assembled;
+        } else {
+            result.dropped = self.shrink();
+        }
+        Ok(result)
+    }
+
+    fn remove(&mut self, key: &Key<I>) {
+        if let Some(datagram) = self.map.remove(key) {
+            self.order.remove(&(datagram.timestamp, datagram.seq));
+            self.size -= datagram.size;
+        }
+    }
+
+    /// Drops incomplete datagrams which have been waiting longer than the timeout.
+    fn expire(&mut self, timestamp: u64) {
+        if self.timeout == 0 {
+            return;
+        }
+        loop {
+            let key = match self.order.iter().next() {
+                Some((&(first, _), key)) if first + self.timeout < timestamp => key.clone(),
+                _ => break,
+            };
+            self.remove(&key);
+        }
+    }
+
+    /// Drops the oldest incomplete datagrams until the buffered bytes fit in the limit.
+    fn shrink(&mut self) -> bool {
+        let mut dropped = false;
+        while self.memory_limit > 0 && self.size > self.memory_limit {
+            let key = match self.order.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&key);
+            dropped = true;
+        }
+        dropped
+    }
+}
+
+fn attr<T>(parent: &Parent, id: Token) -> Result<Option<T>>
+where
+    Variant: Value<T>,
+{
+    if let Some(attr) = parent.attr(id) {
+        let value = attr.try_get(parent)?;
+        Ok(Some(Value::<T>::try_into(value)?))
+    } else {
+        Ok(None)
+    }
+}
+
+impl<I> Worker for DefragWorker<I>
+where
+    I: Hash + Eq + Clone,
+    Variant: Value<I>,
+{
+    fn decode(
+        &mut self,
+        _ctx: &mut Context,
+        stack: &LayerStack,
+        parent: &mut Parent,
+    ) -> Result<Status> {
+        if parent.id() != self.tokens.layer {
+            return Ok(Status::Skip);
+        }
+
+        let fragment = if let Some(payload) = parent
+            .payloads()
+            .iter()
+            .find(|p| p.id() == self.tokens.fragment)
+        {
+            payload.clone()
+        } else {
+            return Ok(Status::Skip);
+        };
+
+        let (src, dst, id, offset, more_fragments) = match (
+            attr::<ByteSlice>(parent, self.tokens.src)?,
+            attr::<ByteSlice>(parent, self.tokens.dst)?,
+            attr::<I>(parent, self.tokens.id)?,
+            attr::<u16>(parent, self.tokens.offset)?,
+            attr::<u8>(parent, self.tokens.more_fragments)?,
+        ) {
+            (Some(src), Some(dst), Some(id), Some(offset), Some(more_fragments)) => {
+                (src, dst, id, offset, more_fragments)
+            }
+            _ => return Ok(Status::Skip),
+        };
+        let timestamp = stack
+            .bottom()
+            .and_then(|root| {
+                root.attr(self.tokens.timestamp)
+                    .and_then(|attr| attr.try_get(root).ok())
+            })
+            .and_then(|value| Value::<u64>::try_into(value).ok())
+            .unwrap_or(0);
+
+        let typ = fragment.typ();
+        let data = fragment.data();
+        let start = usize::from(offset) * 8;
+        let key = (src.to_vec(), dst.to_vec(), id, typ);
+        let result = self.reassemble(key, start, data, more_fragments == 0, timestamp)?;
+        if result.oversized {
+            parent.add_expert(Expert::new(
+                Severity::Error,
+                "malformed",
+                "fragment exceeds the maximum datagram size",
+            ));
+        }
+        if result.overlapped {
+            parent.add_expert(Expert::new(
+                Severity::Warning,
+                "reassembly",
+                "fragment overlaps previous data",
+            ));
+        }
+        if result.conflicted {
+            parent.add_expert(Expert::new(
+                Severity::Warning,
+                "reassembly",
+                "last fragment conflicts with the length of a previous last fragment",
+            ));
+        }
+        if let Some((data, fragments)) = result.assembled {
+            let len = data.len();
+            let data = parent.add_buffer(data);
+            parent.add_attr(
+                Attr::builder(self.protocol.reassembled)
+                    .range(0..parent.data().len())
+                    .build(),
+            );
+            parent.add_attr(
+                Attr::builder(self.protocol.reassembled_length)
+                    .value(len as u64)
+                    .build(),
+            );
+            parent.add_attr(
+                Attr::builder(self.protocol.reassembled_fragments)
+                    .value(u64::from(fragments))
+                    .build(),
+            );
+            if typ != Token::null() {
+                parent.add_payload(Payload::new(data, typ));
+            }
+        } else if result.dropped {
+            parent.add_expert(Expert::new(
+                Severity::Warning,
+                "reassembly",
+                "reassembly buffer is full; incomplete datagrams were dropped",
+            ));
+        }
+
+        Ok(Status::Done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use attr::AttrClass;
+    use defrag::{DefragWorker, Key, Protocol};
+    use slice::ByteSlice;
+    use std::hash::Hash;
+    use token::Token;
+    use variant::{Value, Variant};
+
+    fn defrag<I>(timeout: u64, memory_limit: usize) -> DefragWorker<I>
+    where
+        I: Hash + Eq + Clone,
+        Variant: Value<I>,
+    {
+        let reassembled = Box::leak(Box::new(AttrClass::builder("test.reassembled").build()));
+        let protocol = Protocol {
+            layer: "test",
+            fragment: "@fragment:test",
+            src: "test.src",
+            dst: "test.dst",
+            id: "test.id",
+            offset: "test.offset",
+            more_fragments: "test.moreFragments",
+            max_size: 24,
+            timeout: "test.timeout",
+            memory_limit: "test.memoryLimit",
+            reassembled,
+            reassembled_length: reassembled,
+            reassembled_fragments: reassembled,
+        };
+        DefragWorker::with_limits(protocol, timeout, memory_limit)
+    }
+
+    fn key(id: u16) -> Key<u16> {
+        (vec![1], vec![2], id, Token::null())
+    }
+
+    fn data(data: &'static [u8]) -> ByteSlice {
+        ByteSlice::from(data)
+    }
+
+    #[test]
+    fn in_order() {
+        let mut worker = defrag(30, 0);
+        let result = worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 0)
+            .unwrap();
+        assert!(result.assembled.is_none());
+        let result = worker.reassemble(key(1), 8, data(b"89"), true, 0).unwrap();
+        assert_eq!(result.assembled, Some((b"0123456789".to_vec(), 2)));
+        assert!(!result.overlapped);
+        assert!(worker.map.is_empty());
+        assert!(worker.order.is_empty());
+        assert_eq!(worker.size, 0);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut worker = defrag(30, 0);
+        worker.reassemble(key(1), 16, data(b"gh"), true, 0).unwrap();
+        worker
+            .reassemble(key(2), 0, data(b"ABCDEFGH"), false, 0)
+            .unwrap();
+        worker
+            .reassemble(key(1), 8, data(b"89abcdef"), false, 0)
+            .unwrap();
+        let result = worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 0)
+            .unwrap();
+        assert_eq!(result.assembled, Some((b"0123456789abcdefgh".to_vec(), 3)));
+        assert_eq!(worker.map.len(), 1);
+        assert_eq!(worker.size, 8);
+    }
+
+    #[test]
+    fn overlapping() {
+        let mut worker = defrag(30, 0);
+        worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 0)
+            .unwrap();
+        let result = worker
+            .reassemble(key(1), 0, data(b"ABCDEFGHIJ"), true, 0)
+            .unwrap();
+        assert!(result.overlapped);
+        assert_eq!(result.assembled, Some((b"01234567IJ".to_vec(), 2)));
+    }
+
+    #[test]
+    fn duplicate_last_fragment() {
+        let mut worker = defrag(30, 0);
+        worker.reassemble(key(1), 16, data(b"gh"), true, 0).unwrap();
+        let result = worker
+            .reassemble(key(1), 16, data(b"ghij"), true, 0)
+            .unwrap();
+        assert!(result.conflicted);
+        assert!(result.overlapped);
+
+        // The length of the first last fragment is kept.
+        let result = worker
+            .reassemble(key(1), 0, data(b"0123456789abcdef"), false, 0)
+            .unwrap();
+        assert_eq!(result.assembled, Some((b"0123456789abcdefgh".to_vec(), 3)));
+
+        let mut worker = defrag(30, 0);
+        worker.reassemble(key(1), 8, data(b"89"), true, 0).unwrap();
+        let result = worker.reassemble(key(1), 8, data(b"89"), true, 0).unwrap();
+        assert!(!result.conflicted);
+        assert!(result.overlapped);
+    }
+
+    #[test]
+    fn expired() {
+        let mut worker = defrag(30, 0);
+        worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 100)
+            .unwrap();
+        worker
+            .reassemble(key(2), 0, data(b"ABCDEFGH"), false, 120)
+            .unwrap();
+        let result = worker
+            .reassemble(key(1), 8, data(b"89"), true, 131)
+            .unwrap();
+        assert!(result.assembled.is_none());
+        assert_eq!(worker.map.len(), 2);
+        assert!(worker.map.contains_key(&key(2)));
+        assert_eq!(worker.size, 10);
+
+        let mut worker = defrag(0, 0);
+        worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 100)
+            .unwrap();
+        let result = worker
+            .reassemble(key(1), 8, data(b"89"), true, 1000)
+            .unwrap();
+        assert!(result.assembled.is_some());
+    }
+
+    #[test]
+    fn over_budget() {
+        let mut worker = defrag(30, 20);
+        worker
+            .reassemble(key(1), 0, data(b"01234567"), false, 0)
+            .unwrap();
+        let result = worker
+            .reassemble(key(2), 0, data(b"ABCDEFGH"), false, 1)
+            .unwrap();
+        assert!(!result.dropped);
+        let result = worker
+            .reassemble(key(3), 0, data(b"abcdefgh"), false, 2)
+            .unwrap();
+        assert!(result.dropped);
+        assert_eq!(worker.map.len(), 2);
+        assert!(!worker.map.contains_key(&key(1)));
+        assert_eq!(worker.size, 16);
+
+        // A completed datagram releases its bytes before the limit is checked.
+        let result = worker.reassemble(key(2), 8, data(b"IJ"), true, 3).unwrap();
+        assert!(!result.dropped);
+        assert!(result.assembled.is_some());
+        assert_eq!(worker.size, 8);
+    }
+
+    #[test]
+    fn oversized() {
+        let mut worker = defrag(30, 0);
+        let result = worker
+            .reassemble(key(1), 16, data(b"01234567"), true, 0)
+            .unwrap();
+        assert!(!result.oversized);
+        let result = worker.reassemble(key(1), 24, data(b"8"), true, 0).unwrap();
+        assert!(result.oversized);
+        assert!(!result.conflicted);
+        assert_eq!(worker.size, 8);
+    }
+
+    #[test]
+    fn wide_ids() {
+        let mut worker: DefragWorker<u32> = defrag(30, 0);
+        let key = |id| (vec![1], vec![2], id, Token::null());
+        worker
+            .reassemble(key(0x10000), 0, data(b"01234567"), false, 0)
+            .unwrap();
+        let result = worker.reassemble(key(0), 8, data(b"89"), true, 0).unwrap();
+        assert!(result.assembled.is_none());
+        let result = worker
+            .reassemble(key(0x10000), 8, data(b"89"), true, 0)
+            .unwrap();
+        assert_eq!(result.assembled, Some((b"0123456789".to_vec(), 2)));
+    }
+}
@@ -0,0 +1,5 @@
+//! Expert items.
+//!
+//! Type Expert points out a notable condition in a layer, such as a bad checksum.
+
+pub use genet_abi::expert::{Expert, Severity};
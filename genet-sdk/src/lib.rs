@@ -9,7 +9,9 @@ pub mod attr;
 pub mod cast;
 pub mod context;
 pub mod decoder;
+pub mod defrag;
 pub mod error;
+pub mod expert;
 pub mod file;
 pub mod fixed;
 pub mod helper;
@@ -3,6 +3,7 @@
 pub use attr::{Attr, AttrClass};
 pub use cast::Map;
 pub use context::Context;
+pub use expert::{Expert, Severity};
 pub use file::FileType;
 pub use fixed::Fixed;
 pub use layer::{Layer, LayerClass, LayerStack, Parent, Payload};
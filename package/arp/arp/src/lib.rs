@@ -73,6 +73,7 @@ impl Decoder for ArpDecoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.arp".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
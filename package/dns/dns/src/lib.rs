@@ -0,0 +1,938 @@
+extern crate genet_sdk;
+
+use genet_sdk::{cast, decoder::*, error::Error, prelude::*};
+use std::collections::{BTreeMap, HashMap};
+
+const DNS_PORT: u16 = 53;
+
+/// The maximum number of compression pointers followed in a single name.
+const MAX_POINTERS: usize = 32;
+
+struct DnsWorker {}
+
+impl Worker for DnsWorker {
+    fn decode(
+        &mut self,
+        _ctx: &mut Context,
+        _stack: &LayerStack,
+        parent: &mut Parent,
+    ) -> Result<Status> {
+        if parent.id() != token!("udp") {
+            return Ok(Status::Skip);
+        }
+
+        let data;
+
+        if let Some(payload) = parent.payloads().iter().next() {
+            data = payload.data();
+        } else {
+            return Ok(Status::Skip);
+        }
+
+        let src: u16 = parent
+            .attr(token!("udp.src"))
+            .unwrap()
+            .try_get(parent)?
+            .try_into()?;
+        let dst: u16 = parent
+            .attr(token!("udp.dst"))
+            .unwrap()
+            .try_get(parent)?
+            .try_into()?;
+
+        if src != DNS_PORT && dst != DNS_PORT {
+            return Ok(Status::Skip);
+        }
+
+        let mut layer = Layer::new(&DNS_CLASS, data);
+        decode_message(&mut layer)?;
+        parent.add_child(layer);
+        Ok(Status::Done)
+    }
+}
+
+#[derive(Clone)]
+struct DnsDecoder {}
+
+impl Decoder for DnsDecoder {
+    fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+        Box::new(DnsWorker {})
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.dns".into(),
+            exec_type: ExecType::ParallelSync,
+            ..Metadata::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Flow {
+    serial: u64,
+    timestamp: u64,
+    buffer: Vec<u8>,
+}
+
+type Key = (Vec<u8>, Vec<u8>, u16, u16);
+
+struct DnsTcpWorker {
+    map: HashMap<Key, Flow>,
+    /// The keys of the flows ordered by their last activity.
+    order: BTreeMap<(u64, u64), Key>,
+    serial: u64,
+    size: usize,
+    timeout: u64,
+    memory_limit: usize,
+}
+
+impl DnsTcpWorker {
+    fn new(ctx: &Context) -> DnsTcpWorker {
+        let timeout = ctx
+            .get_config("@genet/dns.tcp.timeout")
+            .parse()
+            .unwrap_or(30);
+        let memory_limit: usize = ctx
+            .get_config("@genet/dns.tcp.memoryLimit")
+            .parse()
+            .unwrap_or(4);
+        Self::with_limits(timeout, memory_limit * 1024 * 1024)
+    }
+
+    fn with_limits(timeout: u64, memory_limit: usize) -> DnsTcpWorker {
+        DnsTcpWorker {
+            map: HashMap::new(),
+            order: BTreeMap::new(),
+            serial: 0,
+            size: 0,
+            timeout,
+            memory_limit,
+        }
+    }
+
+    /// Appends the stream data to the flow of `key` and returns the complete messages.
+    fn put(&mut self, key: Key, slices: &[ByteSlice], timestamp: u64) -> Vec<Vec<u8>> {
+        self.expire(timestamp);
+
+        let serial = self.serial;
+        self.serial += 1;
+        let mut messages = Vec::new();
+        let (before, after) = {
+            let flow = self.map.entry(key.clone()).or_insert_with(|| Flow {
+                serial,
+                timestamp,
+                buffer: Vec::new(),
+            });
+            self.order.remove(&(flow.timestamp, flow.serial));
+            flow.serial = serial;
+            flow.timestamp = timestamp;
+            let before = flow.buffer.len();
+            for slice in slices {
+                flow.buffer.extend_from_slice(slice);
+            }
+
+            // Each message is prefixed with a two byte length field.
+            while flow.buffer.len() >= 2 {
+                let len = (usize::from(flow.buffer[0]) << 8) | usize::from(flow.buffer[1]);
+                if flow.buffer.len() < len + 2 {
+                    break;
+                }
+                messages.push(flow.buffer.drain(..len + 2).skip(2).collect::<Vec<_>>());
+            }
+            (before, flow.buffer.len())
+        };
+        self.size = self.size - before + after;
+
+        if after == 0 {
+            self.map.remove(&key);
+        } else {
+            self.order.insert((timestamp, serial), key);
+        }
+        messages
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(flow) = self.map.remove(key) {
+            self.order.remove(&(flow.timestamp, flow.serial));
+            self.size -= flow.buffer.len();
+        }
+    }
+
+    /// Drops the partial messages of flows which have been idle longer than the timeout.
+    fn expire(&mut self, timestamp: u64) {
+        if self.timeout == 0 {
+            return;
+        }
+        loop {
+            let key = match self.order.iter().next() {
+                Some((&(last, _), key)) if last + self.timeout < timestamp => key.clone(),
+                _ => break,
+            };
+            self.remove(&key);
+        }
+    }
+
+    /// Drops the least recently used flows until the buffered bytes fit in the limit.
+    fn shrink(&mut self) -> bool {
+        let mut dropped = false;
+        while self.memory_limit > 0 && self.size > self.memory_limit {
+            let key = match self.order.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&key);
+            dropped = true;
+        }
+        dropped
+    }
+}
+
+impl Worker for DnsTcpWorker {
+    fn decode(
+        &mut self,
+        _ctx: &mut Context,
+        stack: &LayerStack,
+        parent: &mut Parent,
+    ) -> Result<Status> {
+        if parent.id() != token!("tcp") {
+            return Ok(Status::Skip);
+        }
+
+        let slices = parent
+            .payloads()
+            .iter()
+            .filter(|p| p.id() == token!("@stream:tcp"))
+            .map(|p| p.data())
+            .collect::<Vec<_>>();
+
+        if slices.is_empty() {
+            return Ok(Status::Skip);
+        }
+
+        let src: u16 = parent
+            .attr(token!("tcp.src"))
+            .unwrap()
+            .try_get(parent)?
+            .try_into()?;
+        let dst: u16 = parent
+            .attr(token!("tcp.dst"))
+            .unwrap()
+            .try_get(parent)?
+            .try_into()?;
+
+        if src != DNS_PORT && dst != DNS_PORT {
+            return Ok(Status::Skip);
+        }
+
+        let parent_src: ByteSlice = if let Some((attr, layer)) = stack.attr_layer(token!("_.src")) {
+            attr.try_get(layer)?.try_into()?
+        } else {
+            return Ok(Status::Skip);
+        };
+        let parent_dst: ByteSlice = if let Some((attr, layer)) = stack.attr_layer(token!("_.dst")) {
+            attr.try_get(layer)?.try_into()?
+        } else {
+            return Ok(Status::Skip);
+        };
+        let timestamp = stack
+            .bottom()
+            .and_then(|root| {
+                root.attr(token!("link.timestamp.sec"))
+                    .and_then(|attr| attr.try_get(root).ok())
+            })
+            .and_then(|value| value.try_into().ok())
+            .unwrap_or(0);
+
+        let key = (parent_src.to_vec(), parent_dst.to_vec(), src, dst);
+        for message in self.put(key, &slices, timestamp) {
+            parent.add_child(message_layer(message));
+        }
+        if self.shrink() {
+            parent.add_expert(Expert::new(
+                Severity::Warning,
+                "reassembly",
+                "reassembly buffer is full; partial messages were dropped",
+            ));
+        }
+
+        Ok(Status::Done)
+    }
+}
+
+/// Creates a layer which owns the message.
+///
+/// A malformed message is reported by an expert item, so that the following messages are
+/// still decoded.
+fn message_layer(message: Vec<u8>) -> Layer {
+    let mut layer = Layer::new(&DNS_CLASS, ByteSlice::new());
+    let data = layer.add_buffer(message);
+    layer.set_data(data);
+    if let Err(err) = decode_message(&mut layer) {
+        layer.add_expert(Expert::new(Severity::Error, "malformed", &err.to_string()));
+    }
+    layer
+}
+
+#[derive(Clone)]
+struct DnsTcpDecoder {}
+
+impl Decoder for DnsTcpDecoder {
+    fn new_worker(&self, ctx: &Context) -> Box<Worker> {
+        Box::new(DnsTcpWorker::new(ctx))
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.dns-tcp".into(),
+            exec_type: ExecType::SerialSync,
+            runs_after: vec!["app.genet.decoder.tcp-stream".into()],
+            ..Metadata::default()
+        }
+    }
+}
+
+fn decode_message(layer: &mut Layer) -> Result<()> {
+    let opcode = OPCODE_ATTR_HEADER.try_get(layer)?.try_into()?;
+    if let Some(attr) = get_opcode(opcode) {
+        layer.add_attr(attr!(attr, range: 2..3));
+    }
+
+    let rcode = RCODE_ATTR_HEADER.try_get(layer)?.try_into()?;
+    if let Some(attr) = get_rcode(rcode) {
+        layer.add_attr(attr!(attr, range: 3..4));
+    }
+
+    let qdcount: u16 = QDCOUNT_ATTR_HEADER.try_get(layer)?.try_into()?;
+    let ancount: u16 = ANCOUNT_ATTR_HEADER.try_get(layer)?.try_into()?;
+    let nscount: u16 = NSCOUNT_ATTR_HEADER.try_get(layer)?.try_into()?;
+    let arcount: u16 = ARCOUNT_ATTR_HEADER.try_get(layer)?.try_into()?;
+
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        offset = decode_question(layer, offset)?;
+    }
+
+    let sections = [
+        (ancount, get_section(0)),
+        (nscount, get_section(1)),
+        (arcount, get_section(2)),
+    ];
+    for (count, classes) in sections.iter() {
+        for _ in 0..*count {
+            offset = decode_record(layer, offset, classes)?;
+        }
+    }
+
+    if offset < layer.data().len() {
+        layer.add_expert(
+            Expert::new(
+                Severity::Warning,
+                "malformed",
+                "trailing data after the message",
+            )
+            .with_range(offset..layer.data().len()),
+        );
+    }
+    Ok(())
+}
+
+fn decode_question(layer: &mut Layer, offset: usize) -> Result<usize> {
+    let (name, next) = read_name(&layer.data(), offset)?;
+    let end = next + 4;
+    let typ = read_u16(&layer.data(), next)?;
+    layer.data().try_get(next..end)?;
+    layer.add_attr(attr!(&QRY_ATTR, range: offset..end));
+    layer.add_attr(attr!(&QRY_NAME_ATTR, range: offset..next, value: name.into_boxed_str()));
+    layer.add_attr(attr!(&QRY_TYPE_ATTR, range: next..next + 2));
+    if let Some(attr) = get_qry_type(u64::from(typ)) {
+        layer.add_attr(attr!(attr, range: next..next + 2));
+    }
+    layer.add_attr(attr!(&QRY_CLASS_ATTR, range: next + 2..end));
+    Ok(end)
+}
+
+fn decode_record(layer: &mut Layer, offset: usize, classes: &RecordClasses) -> Result<usize> {
+    let (name, next) = read_name(&layer.data(), offset)?;
+    let typ = read_u16(&layer.data(), next)?;
+    let rdlength = usize::from(read_u16(&layer.data(), next + 8)?);
+    let rdata = next + 10;
+    let end = rdata + rdlength;
+    layer.data().try_get(rdata..end)?;
+
+    layer.add_attr(attr!(classes.record, range: offset..end));
+    layer.add_attr(attr!(classes.name, range: offset..next, value: name.into_boxed_str()));
+    layer.add_attr(attr!(classes.typ, range: next..next + 2));
+    if let Some(attr) = (classes.types)(u64::from(typ)) {
+        layer.add_attr(attr!(attr, range: next..next + 2));
+    }
+
+    if typ == 41 {
+        // OPT pseudo-records reuse the class and ttl fields.
+        layer.add_attr(attr!(classes.opt_udp_payload_size, range: next + 2..next + 4));
+        layer.add_attr(attr!(classes.opt_extended_rcode, range: next + 4..next + 5));
+        layer.add_attr(attr!(classes.opt_version, range: next + 5..next + 6));
+        layer.add_attr(attr!(classes.opt_do, range: next + 6..next + 7));
+    } else {
+        layer.add_attr(attr!(classes.class, range: next + 2..next + 4));
+        layer.add_attr(attr!(classes.ttl, range: next + 4..next + 8));
+    }
+    layer.add_attr(attr!(classes.rdlength, range: next + 8..rdata));
+
+    match typ {
+        1 if rdlength == 4 => {
+            layer.add_attr(attr!(classes.a, range: rdata..end));
+        }
+        28 if rdlength == 16 => {
+            layer.add_attr(attr!(classes.aaaa, range: rdata..end));
+        }
+        2 | 5 | 12 => {
+            let class = match typ {
+                2 => classes.ns,
+                5 => classes.cname,
+                _ => classes.ptr,
+            };
+            decode_name(layer, rdata, end, class)?;
+        }
+        15 => {
+            layer.data().try_get(rdata..rdata + 2)?;
+            layer.add_attr(attr!(classes.mx_preference, range: rdata..rdata + 2));
+            decode_name(layer, rdata + 2, end, classes.mx_exchange)?;
+        }
+        16 => {
+            let mut pos = rdata;
+            while pos < end {
+                let len = usize::from(layer.data().try_get(pos)?);
+                if pos + 1 + len > end {
+                    return Err(Box::new(Error::new("invalid character string length")));
+                }
+                let text = layer.data().try_get(pos + 1..pos + 1 + len)?;
+                let text = String::from_utf8_lossy(&text).into_owned();
+                layer.add_attr(
+                    attr!(classes.txt, range: pos..pos + 1 + len, value: text.into_boxed_str()),
+                );
+                pos += 1 + len;
+            }
+        }
+        33 => {
+            layer.data().try_get(rdata..rdata + 6)?;
+            layer.add_attr(attr!(classes.srv_priority, range: rdata..rdata + 2));
+            layer.add_attr(attr!(classes.srv_weight, range: rdata + 2..rdata + 4));
+            layer.add_attr(attr!(classes.srv_port, range: rdata + 4..rdata + 6));
+            decode_name(layer, rdata + 6, end, classes.srv_target)?;
+        }
+        6 => {
+            let pos = decode_name(layer, rdata, end, classes.soa_mname)?;
+            let pos = decode_name(layer, pos, end, classes.soa_rname)?;
+            if pos + 20 > end {
+                return Err(Box::new(Error::new("invalid SOA record length")));
+            }
+            layer.add_attr(attr!(classes.soa_serial, range: pos..pos + 4));
+            layer.add_attr(attr!(classes.soa_refresh, range: pos + 4..pos + 8));
+            layer.add_attr(attr!(classes.soa_retry, range: pos + 8..pos + 12));
+            layer.add_attr(attr!(classes.soa_expire, range: pos + 12..pos + 16));
+            layer.add_attr(attr!(classes.soa_minimum, range: pos + 16..pos + 20));
+        }
+        _ => {}
+    }
+
+    Ok(end)
+}
+
+/// Adds a domain name attribute and returns the offset following the name.
+fn decode_name(
+    layer: &mut Layer,
+    offset: usize,
+    end: usize,
+    class: &'static AttrClass,
+) -> Result<usize> {
+    let (name, next) = read_name(&layer.data(), offset)?;
+    if next > end {
+        return Err(Box::new(Error::new("domain name exceeds the record")));
+    }
+    layer.add_attr(attr!(class, range: offset..next, value: name.into_boxed_str()));
+    Ok(next)
+}
+
+/// Reads a possibly compressed domain name and returns it with the offset following it.
+fn read_name(data: &ByteSlice, offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = offset;
+    let mut next = None;
+    let mut pointers = 0;
+    loop {
+        let len = usize::from(data.try_get(pos)?);
+        match len & 0xc0 {
+            0x00 => {
+                if len == 0 {
+                    break;
+                }
+                let label = data.try_get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(&label).into_owned());
+                pos += 1 + len;
+            }
+            0xc0 => {
+                let ptr = ((len & 0x3f) << 8) | usize::from(data.try_get(pos + 1)?);
+                if next.is_none() {
+                    next = Some(pos + 2);
+                }
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return Err(Box::new(Error::new("too many compression pointers")));
+                }
+                pos = ptr;
+            }
+            _ => return Err(Box::new(Error::new("unsupported label type"))),
+        }
+    }
+    let name = if labels.is_empty() {
+        "<Root>".to_string()
+    } else {
+        labels.join(".")
+    };
+    Ok((name, next.unwrap_or(pos + 1)))
+}
+
+fn read_u16(data: &ByteSlice, offset: usize) -> Result<u16> {
+    let bytes = data.try_get(offset..offset + 2)?;
+    Ok((u16::from(bytes[0]) << 8) | u16::from(bytes[1]))
+}
+
+def_layer_class!(DNS_CLASS, "dns",
+    header: attr!(&ID_ATTR, range: 0..2),
+    header: attr!(&FLAGS_ATTR, range: 2..4),
+    header: attr!(&FLAGS_QR_ATTR, bit_range: 2 0..1),
+    header: &OPCODE_ATTR_HEADER,
+    header: attr!(&FLAGS_AA_ATTR, bit_range: 2 5..6),
+    header: attr!(&FLAGS_TC_ATTR, bit_range: 2 6..7),
+    header: attr!(&FLAGS_RD_ATTR, bit_range: 2 7..8),
+    header: attr!(&FLAGS_RA_ATTR, bit_range: 3 0..1),
+    header: attr!(&FLAGS_Z_ATTR, bit_range: 3 1..2),
+    header: attr!(&FLAGS_AD_ATTR, bit_range: 3 2..3),
+    header: attr!(&FLAGS_CD_ATTR, bit_range: 3 3..4),
+    header: &RCODE_ATTR_HEADER,
+    header: &QDCOUNT_ATTR_HEADER,
+    header: &ANCOUNT_ATTR_HEADER,
+    header: &NSCOUNT_ATTR_HEADER,
+    header: &ARCOUNT_ATTR_HEADER
+);
+
+def_attr!(OPCODE_ATTR_HEADER, &OPCODE_ATTR, bit_range: 2 1..5);
+
+def_attr!(RCODE_ATTR_HEADER, &RCODE_ATTR, bit_range: 3 4..8);
+
+def_attr!(QDCOUNT_ATTR_HEADER, &QDCOUNT_ATTR, range: 4..6);
+
+def_attr!(ANCOUNT_ATTR_HEADER, &ANCOUNT_ATTR, range: 6..8);
+
+def_attr!(NSCOUNT_ATTR_HEADER, &NSCOUNT_ATTR, range: 8..10);
+
+def_attr!(ARCOUNT_ATTR_HEADER, &ARCOUNT_ATTR, range: 10..12);
+
+def_attr_class!(ID_ATTR, "dns.id", cast: cast::UInt16BE());
+
+def_attr_class!(FLAGS_ATTR, "dns.flags",
+    cast: cast::UInt16BE(),
+    typ: "@flags"
+);
+
+def_attr_class!(FLAGS_QR_ATTR, "dns.flags.response",
+    cast: cast::UInt8().map(|v| v & 0b10000000 != 0)
+);
+
+def_attr_class!(OPCODE_ATTR, "dns.opcode",
+    cast: cast::UInt8().map(|v| (v >> 3) & 0b00001111),
+    typ: "@enum"
+);
+
+def_attr_class!(FLAGS_AA_ATTR, "dns.flags.authoritative",
+    cast: cast::UInt8().map(|v| v & 0b00000100 != 0)
+);
+
+def_attr_class!(FLAGS_TC_ATTR, "dns.flags.truncated",
+    cast: cast::UInt8().map(|v| v & 0b00000010 != 0)
+);
+
+def_attr_class!(FLAGS_RD_ATTR, "dns.flags.recursionDesired",
+    cast: cast::UInt8().map(|v| v & 0b00000001 != 0)
+);
+
+def_attr_class!(FLAGS_RA_ATTR, "dns.flags.recursionAvailable",
+    cast: cast::UInt8().map(|v| v & 0b10000000 != 0)
+);
+
+def_attr_class!(FLAGS_Z_ATTR, "dns.flags.reserved",
+    cast: cast::UInt8().map(|v| v & 0b01000000 != 0)
+);
+
+def_attr_class!(FLAGS_AD_ATTR, "dns.flags.authenticData",
+    cast: cast::UInt8().map(|v| v & 0b00100000 != 0)
+);
+
+def_attr_class!(FLAGS_CD_ATTR, "dns.flags.checkingDisabled",
+    cast: cast::UInt8().map(|v| v & 0b00010000 != 0)
+);
+
+def_attr_class!(RCODE_ATTR, "dns.rcode",
+    cast: cast::UInt8().map(|v| v & 0b00001111),
+    typ: "@enum"
+);
+
+def_attr_class!(QDCOUNT_ATTR, "dns.qdcount", cast: cast::UInt16BE());
+
+def_attr_class!(ANCOUNT_ATTR, "dns.ancount", cast: cast::UInt16BE());
+
+def_attr_class!(NSCOUNT_ATTR, "dns.nscount", cast: cast::UInt16BE());
+
+def_attr_class!(ARCOUNT_ATTR, "dns.arcount", cast: cast::UInt16BE());
+
+def_attr_class!(QRY_ATTR, "dns.qry",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(QRY_NAME_ATTR, "dns.qry.name");
+
+def_attr_class!(QRY_TYPE_ATTR, "dns.qry.type",
+    cast: cast::UInt16BE(),
+    typ: "@enum"
+);
+
+def_attr_class!(QRY_CLASS_ATTR, "dns.qry.class", cast: cast::UInt16BE());
+
+struct RecordClasses {
+    record: &'static AttrClass,
+    name: &'static AttrClass,
+    typ: &'static AttrClass,
+    types: fn(u64) -> Option<&'static AttrClass>,
+    class: &'static AttrClass,
+    ttl: &'static AttrClass,
+    rdlength: &'static AttrClass,
+    a: &'static AttrClass,
+    aaaa: &'static AttrClass,
+    ns: &'static AttrClass,
+    cname: &'static AttrClass,
+    ptr: &'static AttrClass,
+    mx_preference: &'static AttrClass,
+    mx_exchange: &'static AttrClass,
+    txt: &'static AttrClass,
+    srv_priority: &'static AttrClass,
+    srv_weight: &'static AttrClass,
+    srv_port: &'static AttrClass,
+    srv_target: &'static AttrClass,
+    soa_mname: &'static AttrClass,
+    soa_rname: &'static AttrClass,
+    soa_serial: &'static AttrClass,
+    soa_refresh: &'static AttrClass,
+    soa_retry: &'static AttrClass,
+    soa_expire: &'static AttrClass,
+    soa_minimum: &'static AttrClass,
+    opt_udp_payload_size: &'static AttrClass,
+    opt_extended_rcode: &'static AttrClass,
+    opt_version: &'static AttrClass,
+    opt_do: &'static AttrClass,
+}
+
+macro_rules! record_classes {
+    ($id:tt, $types:expr) => {
+        RecordClasses {
+            record: attr_class_lazy!($id, typ: "@nested", value: true),
+            name: attr_class_lazy!(concat!($id, ".name")),
+            typ: attr_class_lazy!(concat!($id, ".type"), cast: cast::UInt16BE(), typ: "@enum"),
+            types: $types,
+            class: attr_class_lazy!(concat!($id, ".class"), cast: cast::UInt16BE()),
+            ttl: attr_class_lazy!(concat!($id, ".ttl"), cast: cast::UInt32BE()),
+            rdlength: attr_class_lazy!(concat!($id, ".rdlength"), cast: cast::UInt16BE()),
+            a: attr_class_lazy!(concat!($id, ".a"), typ: "@ipv4:addr", cast: cast::ByteSlice()),
+            aaaa: attr_class_lazy!(concat!($id, ".aaaa"), typ: "@ipv6:addr", cast: cast::ByteSlice()),
+            ns: attr_class_lazy!(concat!($id, ".ns")),
+            cname: attr_class_lazy!(concat!($id, ".cname")),
+            ptr: attr_class_lazy!(concat!($id, ".ptr")),
+            mx_preference: attr_class_lazy!(concat!($id, ".mx.preference"), cast: cast::UInt16BE()),
+            mx_exchange: attr_class_lazy!(concat!($id, ".mx.exchange")),
+            txt: attr_class_lazy!(concat!($id, ".txt")),
+            srv_priority: attr_class_lazy!(concat!($id, ".srv.priority"), cast: cast::UInt16BE()),
+            srv_weight: attr_class_lazy!(concat!($id, ".srv.weight"), cast: cast::UInt16BE()),
+            srv_port: attr_class_lazy!(concat!($id, ".srv.port"), cast: cast::UInt16BE()),
+            srv_target: attr_class_lazy!(concat!($id, ".srv.target")),
+            soa_mname: attr_class_lazy!(concat!($id, ".soa.mname")),
+            soa_rname: attr_class_lazy!(concat!($id, ".soa.rname")),
+            soa_serial: attr_class_lazy!(concat!($id, ".soa.serial"), cast: cast::UInt32BE()),
+            soa_refresh: attr_class_lazy!(concat!($id, ".soa.refresh"), cast: cast::UInt32BE()),
+            soa_retry: attr_class_lazy!(concat!($id, ".soa.retry"), cast: cast::UInt32BE()),
+            soa_expire: attr_class_lazy!(concat!($id, ".soa.expire"), cast: cast::UInt32BE()),
+            soa_minimum: attr_class_lazy!(concat!($id, ".soa.minimum"), cast: cast::UInt32BE()),
+            opt_udp_payload_size: attr_class_lazy!(concat!($id, ".opt.udpPayloadSize"), cast: cast::UInt16BE()),
+            opt_extended_rcode: attr_class_lazy!(concat!($id, ".opt.extendedRcode"), cast: cast::UInt8()),
+            opt_version: attr_class_lazy!(concat!($id, ".opt.version"), cast: cast::UInt8()),
+            opt_do: attr_class_lazy!(concat!($id, ".opt.dnssecOk"), cast: cast::UInt8().map(|v| v & 0b10000000 != 0)),
+        }
+    };
+}
+
+macro_rules! get_types {
+    ($name:ident, $id:tt) => {
+        fn $name(val: u64) -> Option<&'static AttrClass> {
+            match val {
+                1 => Some(attr_class_lazy!(concat!($id, ".a"), typ: "@novalue", value: true)),
+                2 => Some(attr_class_lazy!(concat!($id, ".ns"), typ: "@novalue", value: true)),
+                5 => Some(attr_class_lazy!(concat!($id, ".cname"), typ: "@novalue", value: true)),
+                6 => Some(attr_class_lazy!(concat!($id, ".soa"), typ: "@novalue", value: true)),
+                12 => Some(attr_class_lazy!(concat!($id, ".ptr"), typ: "@novalue", value: true)),
+                15 => Some(attr_class_lazy!(concat!($id, ".mx"), typ: "@novalue", value: true)),
+                16 => Some(attr_class_lazy!(concat!($id, ".txt"), typ: "@novalue", value: true)),
+                28 => Some(attr_class_lazy!(concat!($id, ".aaaa"), typ: "@novalue", value: true)),
+                33 => Some(attr_class_lazy!(concat!($id, ".srv"), typ: "@novalue", value: true)),
+                41 => Some(attr_class_lazy!(concat!($id, ".opt"), typ: "@novalue", value: true)),
+                255 => Some(attr_class_lazy!(concat!($id, ".any"), typ: "@novalue", value: true)),
+                _ => None,
+            }
+        }
+    };
+}
+
+get_types!(get_qry_type, "dns.qry.type");
+get_types!(get_answer_type, "dns.answer.type");
+get_types!(get_authority_type, "dns.authority.type");
+get_types!(get_additional_type, "dns.additional.type");
+
+fn get_section(index: usize) -> RecordClasses {
+    match index {
+        0 => record_classes!("dns.answer", get_answer_type),
+        1 => record_classes!("dns.authority", get_authority_type),
+        _ => record_classes!("dns.additional", get_additional_type),
+    }
+}
+
+fn get_opcode(val: u64) -> Option<&'static AttrClass> {
+    match val {
+        0 => Some(attr_class_lazy!("dns.opcode.query", typ: "@novalue", value: true)),
+        1 => Some(attr_class_lazy!("dns.opcode.iquery", typ: "@novalue", value: true)),
+        2 => Some(attr_class_lazy!("dns.opcode.status", typ: "@novalue", value: true)),
+        4 => Some(attr_class_lazy!("dns.opcode.notify", typ: "@novalue", value: true)),
+        5 => Some(attr_class_lazy!("dns.opcode.update", typ: "@novalue", value: true)),
+        _ => None,
+    }
+}
+
+fn get_rcode(val: u64) -> Option<&'static AttrClass> {
+    match val {
+        0 => Some(attr_class_lazy!("dns.rcode.noError", typ: "@novalue", value: true)),
+        1 => Some(attr_class_lazy!("dns.rcode.formErr", typ: "@novalue", value: true)),
+        2 => Some(attr_class_lazy!("dns.rcode.servFail", typ: "@novalue", value: true)),
+        3 => Some(attr_class_lazy!("dns.rcode.nxDomain", typ: "@novalue", value: true)),
+        4 => Some(attr_class_lazy!("dns.rcode.notImp", typ: "@novalue", value: true)),
+        5 => Some(attr_class_lazy!("dns.rcode.refused", typ: "@novalue", value: true)),
+        _ => None,
+    }
+}
+
+genet_decoders!(DnsDecoder {}, DnsTcpDecoder {});
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_message, message_layer, read_name, DnsTcpWorker, DNS_CLASS};
+    use genet_sdk::{prelude::*, variant::Variant};
+
+    fn name(data: &'static [u8], offset: usize) -> Result<(String, usize)> {
+        read_name(&ByteSlice::from(data), offset)
+    }
+
+    fn values<T>(layer: &Layer, id: &str) -> Vec<T>
+    where
+        Variant: Value<T>,
+    {
+        let id = Token::from(id);
+        layer
+            .attrs()
+            .iter()
+            .filter(|attr| attr.id() == id)
+            .map(|attr| attr.try_get(layer).unwrap().try_into().unwrap())
+            .collect()
+    }
+
+    fn message(data: Vec<u8>) -> Layer {
+        let mut layer = Layer::new(&DNS_CLASS, ByteSlice::new());
+        let data = layer.add_buffer(data);
+        layer.set_data(data);
+        layer
+    }
+
+    fn record(buf: &mut Vec<u8>, typ: u16, rdata: &[u8]) {
+        // The name points to the question.
+        buf.extend_from_slice(&[0xc0, 0x0c]);
+        buf.extend_from_slice(&[(typ >> 8) as u8, typ as u8, 0x00, 0x01]);
+        buf.extend_from_slice(&[0x00, 0x00, 0x0e, 0x10]);
+        buf.extend_from_slice(&[0x00, rdata.len() as u8]);
+        buf.extend_from_slice(rdata);
+    }
+
+    fn response() -> Vec<u8> {
+        let mut buf = vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x03, 0x00, 0x01, 0x00, 0x00,
+        ];
+        buf.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        record(&mut buf, 1, &[93, 184, 216, 34]);
+        record(&mut buf, 15, b"\x00\x0a\x04mail\xc0\x0c");
+        record(&mut buf, 16, b"\x02hi\x02yo");
+        let mut soa = b"\x02ns\xc0\x0c\x04host\xc0\x0c".to_vec();
+        soa.extend_from_slice(&[
+            0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5,
+        ]);
+        record(&mut buf, 6, &soa);
+        buf
+    }
+
+    #[test]
+    fn plain_name() {
+        assert_eq!(
+            name(b"\x03www\x07example\x03com\x00", 0).unwrap(),
+            ("www.example.com".to_string(), 17)
+        );
+        assert_eq!(name(b"\x00", 0).unwrap(), ("<Root>".to_string(), 1));
+    }
+
+    #[test]
+    fn compressed_name() {
+        let data = b"\x07example\x03com\x00\x03www\xc0\x00";
+        assert_eq!(
+            name(data, 13).unwrap(),
+            ("www.example.com".to_string(), 19)
+        );
+    }
+
+    #[test]
+    fn forward_pointer() {
+        let data = b"\xc0\x02\x03www\x00";
+        assert_eq!(name(data, 0).unwrap(), ("www".to_string(), 2));
+    }
+
+    #[test]
+    fn pointer_loop() {
+        assert!(name(b"\xc0\x00", 0).is_err());
+        assert!(name(b"\x03www\xc0\x06\xc0\x04", 0).is_err());
+    }
+
+    #[test]
+    fn malformed_name() {
+        assert!(name(b"\x05ab", 0).is_err());
+        assert!(name(b"\x03www", 0).is_err());
+        assert!(name(b"\xc0", 0).is_err());
+        assert!(name(b"\x40\x00", 0).is_err());
+    }
+
+    #[test]
+    fn records() {
+        let mut layer = message(response());
+        decode_message(&mut layer).unwrap();
+        assert!(layer.experts().is_empty());
+
+        assert_eq!(
+            values::<String>(&layer, "dns.qry.name"),
+            vec!["example.com"]
+        );
+        assert_eq!(
+            values::<Vec<u8>>(&layer, "dns.answer.a"),
+            vec![vec![93, 184, 216, 34]]
+        );
+        assert_eq!(values::<u16>(&layer, "dns.answer.mx.preference"), vec![10]);
+        assert_eq!(
+            values::<String>(&layer, "dns.answer.mx.exchange"),
+            vec!["mail.example.com"]
+        );
+        assert_eq!(values::<String>(&layer, "dns.answer.txt"), vec!["hi", "yo"]);
+        assert_eq!(
+            values::<String>(&layer, "dns.authority.soa.mname"),
+            vec!["ns.example.com"]
+        );
+        assert_eq!(
+            values::<String>(&layer, "dns.authority.soa.rname"),
+            vec!["host.example.com"]
+        );
+        assert_eq!(values::<u32>(&layer, "dns.authority.soa.serial"), vec![1]);
+        assert_eq!(values::<u32>(&layer, "dns.authority.soa.minimum"), vec![5]);
+        assert_eq!(values::<u32>(&layer, "dns.answer.ttl"), vec![3600; 3]);
+    }
+
+    #[test]
+    fn truncated_records() {
+        let mut data = response();
+        data.pop();
+        let mut layer = message(data);
+        assert!(decode_message(&mut layer).is_err());
+
+        // The character string claims more bytes than the record has.
+        let mut data = response()[..29].to_vec();
+        data[7] = 1;
+        data[9] = 0;
+        record(&mut data, 16, b"\x03hi");
+        let mut layer = message(data);
+        assert!(decode_message(&mut layer).is_err());
+    }
+
+    #[test]
+    fn malformed_message() {
+        let layer = message_layer(vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(layer.experts().len(), 1);
+        assert_eq!(layer.experts()[0].severity(), Severity::Error);
+        assert_eq!(layer.data().len(), 12);
+        assert_eq!(layer.buffer_size(), 12);
+
+        let layer = message_layer(response());
+        assert!(layer.experts().is_empty());
+    }
+
+    fn key(port: u16) -> (Vec<u8>, Vec<u8>, u16, u16) {
+        (vec![1], vec![2], port, 53)
+    }
+
+    #[test]
+    fn tcp_messages() {
+        let mut worker = DnsTcpWorker::with_limits(30, 1024);
+        let messages = worker.put(
+            key(1000),
+            &[ByteSlice::from(&b"\x00\x02ab\x00\x03c"[..])],
+            0,
+        );
+        assert_eq!(messages, vec![b"ab".to_vec()]);
+        assert_eq!(worker.size, 3);
+
+        let messages = worker.put(
+            key(1000),
+            &[
+                ByteSlice::from(&b"de"[..]),
+                ByteSlice::from(&b"\x00\x00"[..]),
+            ],
+            1,
+        );
+        assert_eq!(messages, vec![b"cde".to_vec(), Vec::new()]);
+        assert_eq!(worker.size, 0);
+        assert!(worker.map.is_empty());
+        assert!(worker.order.is_empty());
+    }
+
+    #[test]
+    fn tcp_expired() {
+        let mut worker = DnsTcpWorker::with_limits(30, 1024);
+        worker.put(key(1000), &[ByteSlice::from(&b"\x00\x05ab"[..])], 0);
+        worker.put(key(1001), &[ByteSlice::from(&b"\x00\x05ab"[..])], 20);
+        let messages = worker.put(key(1000), &[ByteSlice::from(&b"cde"[..])], 40);
+        assert!(messages.is_empty());
+        assert_eq!(worker.size, 7);
+        assert!(worker.map.contains_key(&key(1000)));
+        assert!(worker.map.contains_key(&key(1001)));
+        assert_eq!(worker.map[&key(1000)].buffer, b"cde");
+    }
+
+    #[test]
+    fn tcp_over_budget() {
+        let mut worker = DnsTcpWorker::with_limits(0, 8);
+        worker.put(key(1000), &[ByteSlice::from(&b"\x00\x10abcd"[..])], 0);
+        worker.put(key(1001), &[ByteSlice::from(&b"\x00\x10abcd"[..])], 1);
+        assert!(worker.shrink());
+        assert_eq!(worker.size, 6);
+        assert!(!worker.map.contains_key(&key(1000)));
+        assert!(worker.map.contains_key(&key(1001)));
+        assert!(!worker.shrink());
+    }
+}
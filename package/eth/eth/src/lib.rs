@@ -43,6 +43,7 @@ impl Decoder for EthDecoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.eth".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
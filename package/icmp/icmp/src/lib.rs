@@ -0,0 +1,267 @@
+extern crate genet_sdk;
+
+use genet_sdk::{cast, decoder::*, prelude::*};
+
+struct IcmpWorker {}
+
+impl Worker for IcmpWorker {
+    fn decode(
+        &mut self,
+        _ctx: &mut Context,
+        _stack: &LayerStack,
+        parent: &mut Parent,
+    ) -> Result<Status> {
+        let data;
+
+        if let Some(payload) = parent
+            .payloads()
+            .iter()
+            .find(|p| p.id() == token!("@data:icmp"))
+        {
+            data = payload.data();
+        } else {
+            return Ok(Status::Skip);
+        }
+
+        parent.add_child(Self::decode_message(data)?);
+        Ok(Status::Done)
+    }
+}
+
+impl IcmpWorker {
+    fn decode_message(data: ByteSlice) -> Result<Layer> {
+        let mut layer = Layer::new(&ICMP_CLASS, data);
+
+        let typ = TYPE_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if let Some(attr) = get_type(typ) {
+            layer.add_attr(attr!(attr, range: 0..1));
+        }
+
+        let code = CODE_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if let Some(attr) = get_code(typ, code) {
+            layer.add_attr(attr!(attr, range: 1..2));
+        }
+
+        match typ {
+            // Echo Reply, Echo Request
+            0 | 8 => {
+                layer.add_attr(attr!(&ID_ATTR, range: 4..6));
+                layer.add_attr(attr!(&SEQ_ATTR, range: 6..8));
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, ""));
+            }
+            // Destination Unreachable, Source Quench, Time Exceeded
+            3 | 4 | 11 => {
+                if typ == 3 && code == 4 {
+                    layer.add_attr(attr!(&MTU_ATTR, range: 6..8));
+                }
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, "@data:ipv4"));
+            }
+            // Redirect
+            5 => {
+                layer.add_attr(attr!(&GATEWAY_ATTR, range: 4..8));
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, "@data:ipv4"));
+            }
+            // Parameter Problem
+            12 => {
+                layer.add_attr(attr!(&POINTER_ATTR, range: 4..5));
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, "@data:ipv4"));
+            }
+            // Timestamp, Timestamp Reply
+            13 | 14 => {
+                layer.data().try_get(..20)?;
+                layer.add_attr(attr!(&ID_ATTR, range: 4..6));
+                layer.add_attr(attr!(&SEQ_ATTR, range: 6..8));
+                layer.add_attr(attr!(&ORIGINATE_ATTR, range: 8..12));
+                layer.add_attr(attr!(&RECEIVE_ATTR, range: 12..16));
+                layer.add_attr(attr!(&TRANSMIT_ATTR, range: 16..20));
+            }
+            _ => {}
+        }
+
+        Ok(layer)
+    }
+}
+
+#[derive(Clone)]
+struct IcmpDecoder {}
+
+impl Decoder for IcmpDecoder {
+    fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+        Box::new(IcmpWorker {})
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.icmp".into(),
+            exec_type: ExecType::ParallelSync,
+            ..Metadata::default()
+        }
+    }
+}
+
+def_layer_class!(ICMP_CLASS, "icmp",
+    header: &TYPE_ATTR_HEADER,
+    header: &CODE_ATTR_HEADER,
+    header: attr!(&CHECKSUM_ATTR, range: 2..4)
+);
+
+def_attr!(TYPE_ATTR_HEADER, &TYPE_ATTR, range: 0..1);
+
+def_attr!(CODE_ATTR_HEADER, &CODE_ATTR, range: 1..2);
+
+def_attr_class!(TYPE_ATTR, "icmp.type",
+    cast: cast::UInt8(),
+    typ: "@enum"
+);
+
+def_attr_class!(CODE_ATTR, "icmp.code",
+    cast: cast::UInt8(),
+    typ: "@enum"
+);
+
+def_attr_class!(CHECKSUM_ATTR, "icmp.checksum", cast: cast::UInt16BE());
+
+def_attr_class!(ID_ATTR, "icmp.id", cast: cast::UInt16BE());
+
+def_attr_class!(SEQ_ATTR, "icmp.seq", cast: cast::UInt16BE());
+
+def_attr_class!(MTU_ATTR, "icmp.mtu", cast: cast::UInt16BE());
+
+def_attr_class!(GATEWAY_ATTR, "icmp.gateway",
+    typ: "@ipv4:addr",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(POINTER_ATTR, "icmp.pointer", cast: cast::UInt8());
+
+def_attr_class!(ORIGINATE_ATTR, "icmp.originateTimestamp", cast: cast::UInt32BE());
+
+def_attr_class!(RECEIVE_ATTR, "icmp.receiveTimestamp", cast: cast::UInt32BE());
+
+def_attr_class!(TRANSMIT_ATTR, "icmp.transmitTimestamp", cast: cast::UInt32BE());
+
+fn get_type(val: u64) -> Option<&'static AttrClass> {
+    match val {
+        0 => Some(attr_class_lazy!("icmp.type.echoReply", typ: "@novalue", value: true)),
+        3 => Some(attr_class_lazy!("icmp.type.unreachable", typ: "@novalue", value: true)),
+        4 => Some(attr_class_lazy!("icmp.type.sourceQuench", typ: "@novalue", value: true)),
+        5 => Some(attr_class_lazy!("icmp.type.redirect", typ: "@novalue", value: true)),
+        8 => Some(attr_class_lazy!("icmp.type.echoRequest", typ: "@novalue", value: true)),
+        9 => Some(attr_class_lazy!("icmp.type.routerAdvertisement", typ: "@novalue", value: true)),
+        10 => Some(attr_class_lazy!("icmp.type.routerSolicitation", typ: "@novalue", value: true)),
+        11 => Some(attr_class_lazy!("icmp.type.timeExceeded", typ: "@novalue", value: true)),
+        12 => Some(attr_class_lazy!("icmp.type.parameterProblem", typ: "@novalue", value: true)),
+        13 => Some(attr_class_lazy!("icmp.type.timestamp", typ: "@novalue", value: true)),
+        14 => Some(attr_class_lazy!("icmp.type.timestampReply", typ: "@novalue", value: true)),
+        _ => None,
+    }
+}
+
+fn get_code(typ: u64, val: u64) -> Option<&'static AttrClass> {
+    match (typ, val) {
+        (3, 0) => Some(attr_class_lazy!("icmp.code.netUnreachable", typ: "@novalue", value: true)),
+        (3, 1) => Some(attr_class_lazy!("icmp.code.hostUnreachable", typ: "@novalue", value: true)),
+        (3, 2) => {
+            Some(attr_class_lazy!("icmp.code.protocolUnreachable", typ: "@novalue", value: true))
+        }
+        (3, 3) => Some(attr_class_lazy!("icmp.code.portUnreachable", typ: "@novalue", value: true)),
+        (3, 4) => {
+            Some(attr_class_lazy!("icmp.code.fragmentationNeeded", typ: "@novalue", value: true))
+        }
+        (3, 5) => {
+            Some(attr_class_lazy!("icmp.code.sourceRouteFailed", typ: "@novalue", value: true))
+        }
+        (3, 9) | (3, 10) | (3, 13) => {
+            Some(attr_class_lazy!("icmp.code.prohibited", typ: "@novalue", value: true))
+        }
+        (5, 0) => Some(attr_class_lazy!("icmp.code.redirectNet", typ: "@novalue", value: true)),
+        (5, 1) => Some(attr_class_lazy!("icmp.code.redirectHost", typ: "@novalue", value: true)),
+        (11, 0) => Some(attr_class_lazy!("icmp.code.ttlExceeded", typ: "@novalue", value: true)),
+        (11, 1) => {
+            Some(attr_class_lazy!("icmp.code.reassemblyTimeExceeded", typ: "@novalue", value: true))
+        }
+        _ => None,
+    }
+}
+
+genet_decoders!(IcmpDecoder {});
+
+#[cfg(test)]
+mod tests {
+    use super::IcmpWorker;
+    use genet_sdk::prelude::*;
+
+    fn ids(layer: &Layer) -> Vec<String> {
+        layer.attrs().iter().map(|a| a.id().to_string()).collect()
+    }
+
+    #[test]
+    fn echo() {
+        let data = &b"\x08\x00\xf7\xfe\x00\x01\x00\x02ping"[..];
+        let layer = IcmpWorker::decode_message(ByteSlice::from(data)).unwrap();
+        assert_eq!(
+            ids(&layer),
+            vec!["icmp.type.echoRequest", "icmp.id", "icmp.seq"]
+        );
+        assert_eq!(layer.payloads().len(), 1);
+        assert_eq!(layer.payloads()[0].id(), Token::null());
+        assert_eq!(&layer.payloads()[0].data()[..], b"ping");
+    }
+
+    #[test]
+    fn error_payload() {
+        // The payload is the IP header and the first bytes of the datagram in error.
+        let datagram = [
+            0x45, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1,
+            10, 0, 0, 2, 0x30, 0x39, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00,
+        ];
+        let mut data = vec![0x03, 0x04, 0x00, 0x00, 0x00, 0x00, 0x05, 0xdc];
+        data.extend_from_slice(&datagram);
+        let layer = IcmpWorker::decode_message(ByteSlice::from(data)).unwrap();
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "icmp.type.unreachable",
+                "icmp.code.fragmentationNeeded",
+                "icmp.mtu",
+            ]
+        );
+        assert_eq!(layer.payloads().len(), 1);
+        assert_eq!(layer.payloads()[0].id(), Token::from("@data:ipv4"));
+        assert_eq!(&layer.payloads()[0].data()[..], &datagram[..]);
+
+        let mut data = vec![0x05, 0x01, 0x00, 0x00, 10, 0, 0, 254];
+        data.extend_from_slice(&datagram);
+        let layer = IcmpWorker::decode_message(ByteSlice::from(data)).unwrap();
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "icmp.type.redirect",
+                "icmp.code.redirectHost",
+                "icmp.gateway"
+            ]
+        );
+        assert_eq!(layer.attr("icmp.gateway").unwrap().range(), 4..8);
+        assert_eq!(&layer.payloads()[0].data()[..], &datagram[..]);
+
+        let mut data = vec![0x0c, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&datagram);
+        let layer = IcmpWorker::decode_message(ByteSlice::from(data)).unwrap();
+        assert_eq!(
+            ids(&layer),
+            vec!["icmp.type.parameterProblem", "icmp.pointer"]
+        );
+        assert_eq!(layer.payloads()[0].id(), Token::from("@data:ipv4"));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = &b"\x0d\x00\x00\x00\x00\x01\x00\x02\x00\x00\x00\x00"[..];
+        assert!(IcmpWorker::decode_message(ByteSlice::from(data)).is_err());
+        assert!(IcmpWorker::decode_message(ByteSlice::from(&b"\x03"[..])).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+extern crate genet_sdk;
+
+use genet_sdk::{cast, decoder::*, prelude::*};
+
+struct IcmpV6Worker {}
+
+impl Worker for IcmpV6Worker {
+    fn decode(
+        &mut self,
+        _ctx: &mut Context,
+        _stack: &LayerStack,
+        parent: &mut Parent,
+    ) -> Result<Status> {
+        let data;
+
+        if let Some(payload) = parent
+            .payloads()
+            .iter()
+            .find(|p| p.id() == token!("@data:icmpv6"))
+        {
+            data = payload.data();
+        } else {
+            return Ok(Status::Skip);
+        }
+
+        parent.add_child(Self::decode_message(data)?);
+        Ok(Status::Done)
+    }
+}
+
+impl IcmpV6Worker {
+    fn decode_message(data: ByteSlice) -> Result<Layer> {
+        let mut layer = Layer::new(&ICMPV6_CLASS, data);
+
+        let typ = TYPE_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if let Some(attr) = get_type(typ) {
+            layer.add_attr(attr!(attr, range: 0..1));
+        }
+
+        let code = CODE_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if let Some(attr) = get_code(typ, code) {
+            layer.add_attr(attr!(attr, range: 1..2));
+        }
+
+        match typ {
+            // Destination Unreachable, Packet Too Big, Time Exceeded, Parameter Problem
+            1 | 2 | 3 | 4 => {
+                if typ == 2 {
+                    layer.add_attr(attr!(&MTU_ATTR, range: 4..8));
+                } else if typ == 4 {
+                    layer.add_attr(attr!(&POINTER_ATTR, range: 4..8));
+                }
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, "@data:ipv6"));
+            }
+            // Echo Request, Echo Reply
+            128 | 129 => {
+                layer.add_attr(attr!(&ID_ATTR, range: 4..6));
+                layer.add_attr(attr!(&SEQ_ATTR, range: 6..8));
+                let payload = layer.data().try_get(8..)?;
+                layer.add_payload(Payload::new(payload, ""));
+            }
+            // Router Solicitation
+            133 => {
+                Self::decode_options(&mut layer, 8)?;
+            }
+            // Router Advertisement
+            134 => {
+                layer.data().try_get(..16)?;
+                layer.add_attr(attr!(&RA_HLIM_ATTR, range: 4..5));
+                layer.add_attr(attr!(&FLAGS_ATTR, range: 5..6));
+                layer.add_attr(attr!(&RA_FLAGS_M_ATTR, range: 5..6));
+                layer.add_attr(attr!(&RA_FLAGS_O_ATTR, range: 5..6));
+                layer.add_attr(attr!(&RA_LIFETIME_ATTR, range: 6..8));
+                layer.add_attr(attr!(&RA_REACHABLE_ATTR, range: 8..12));
+                layer.add_attr(attr!(&RA_RETRANS_ATTR, range: 12..16));
+                Self::decode_options(&mut layer, 16)?;
+            }
+            // Neighbor Solicitation
+            135 => {
+                layer.data().try_get(..24)?;
+                layer.add_attr(attr!(&TARGET_ATTR, range: 8..24));
+                Self::decode_options(&mut layer, 24)?;
+            }
+            // Neighbor Advertisement
+            136 => {
+                layer.data().try_get(..24)?;
+                layer.add_attr(attr!(&FLAGS_ATTR, range: 4..5));
+                layer.add_attr(attr!(&NA_FLAGS_R_ATTR, range: 4..5));
+                layer.add_attr(attr!(&NA_FLAGS_S_ATTR, range: 4..5));
+                layer.add_attr(attr!(&NA_FLAGS_O_ATTR, range: 4..5));
+                layer.add_attr(attr!(&TARGET_ATTR, range: 8..24));
+                Self::decode_options(&mut layer, 24)?;
+            }
+            // Redirect
+            137 => {
+                layer.data().try_get(..40)?;
+                layer.add_attr(attr!(&TARGET_ATTR, range: 8..24));
+                layer.add_attr(attr!(&DESTINATION_ATTR, range: 24..40));
+                Self::decode_options(&mut layer, 40)?;
+            }
+            _ => {}
+        }
+
+        Ok(layer)
+    }
+
+    fn decode_options(layer: &mut Layer, start: usize) -> Result<()> {
+        let end = layer.data().len();
+        if start >= end {
+            return Ok(());
+        }
+        layer.add_attr(attr!(&OPTIONS_ATTR, range: start..end));
+        let mut offset = start;
+        while offset + 2 <= end {
+            let typ = layer.data().try_get(offset)?;
+            let len = layer.data().try_get(offset + 1)? as usize * 8;
+            if len == 0 || offset + len > end {
+                layer.add_expert(
+                    Expert::new(Severity::Error, "malformed", "invalid option length")
+                        .with_range(offset..end),
+                );
+                break;
+            }
+            match typ {
+                1 if len == 8 => {
+                    layer.add_attr(attr!(&OPTIONS_SLLA_ATTR, range: offset + 2..offset + 8));
+                }
+                2 if len == 8 => {
+                    layer.add_attr(attr!(&OPTIONS_TLLA_ATTR, range: offset + 2..offset + 8));
+                }
+                3 if len == 32 => {
+                    layer.add_attr(attr!(&OPTIONS_PREFIX_ATTR, range: offset..offset + len));
+                    layer.add_attr(
+                        attr!(&OPTIONS_PREFIX_LENGTH_ATTR, range: offset + 2..offset + 3),
+                    );
+                    layer.add_attr(
+                        attr!(&OPTIONS_PREFIX_FLAGS_L_ATTR, range: offset + 3..offset + 4),
+                    );
+                    layer.add_attr(
+                        attr!(&OPTIONS_PREFIX_FLAGS_A_ATTR, range: offset + 3..offset + 4),
+                    );
+                    layer
+                        .add_attr(attr!(&OPTIONS_PREFIX_VALID_ATTR, range: offset + 4..offset + 8));
+                    layer.add_attr(
+                        attr!(&OPTIONS_PREFIX_PREFERRED_ATTR, range: offset + 8..offset + 12),
+                    );
+                    layer.add_attr(
+                        attr!(&OPTIONS_PREFIX_PREFIX_ATTR, range: offset + 16..offset + 32),
+                    );
+                }
+                5 if len == 8 => {
+                    layer.add_attr(attr!(&OPTIONS_MTU_ATTR, range: offset + 4..offset + 8));
+                }
+                _ => {}
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct IcmpV6Decoder {}
+
+impl Decoder for IcmpV6Decoder {
+    fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+        Box::new(IcmpV6Worker {})
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.icmpv6".into(),
+            exec_type: ExecType::ParallelSync,
+            ..Metadata::default()
+        }
+    }
+}
+
+def_layer_class!(ICMPV6_CLASS, "icmpv6",
+    header: &TYPE_ATTR_HEADER,
+    header: &CODE_ATTR_HEADER,
+    header: attr!(&CHECKSUM_ATTR, range: 2..4)
+);
+
+def_attr!(TYPE_ATTR_HEADER, &TYPE_ATTR, range: 0..1);
+
+def_attr!(CODE_ATTR_HEADER, &CODE_ATTR, range: 1..2);
+
+def_attr_class!(TYPE_ATTR, "icmpv6.type",
+    cast: cast::UInt8(),
+    typ: "@enum"
+);
+
+def_attr_class!(CODE_ATTR, "icmpv6.code",
+    cast: cast::UInt8(),
+    typ: "@enum"
+);
+
+def_attr_class!(CHECKSUM_ATTR, "icmpv6.checksum", cast: cast::UInt16BE());
+
+def_attr_class!(ID_ATTR, "icmpv6.id", cast: cast::UInt16BE());
+
+def_attr_class!(SEQ_ATTR, "icmpv6.seq", cast: cast::UInt16BE());
+
+def_attr_class!(MTU_ATTR, "icmpv6.mtu", cast: cast::UInt32BE());
+
+def_attr_class!(POINTER_ATTR, "icmpv6.pointer", cast: cast::UInt32BE());
+
+def_attr_class!(RA_HLIM_ATTR, "icmpv6.curHopLimit", cast: cast::UInt8());
+
+def_attr_class!(FLAGS_ATTR, "icmpv6.flags",
+    cast: cast::UInt8(),
+    typ: "@flags"
+);
+
+def_attr_class!(RA_FLAGS_M_ATTR, "icmpv6.flags.managed",
+    cast: cast::UInt8().map(|v| v & 0b10000000 != 0)
+);
+
+def_attr_class!(RA_FLAGS_O_ATTR, "icmpv6.flags.other",
+    cast: cast::UInt8().map(|v| v & 0b01000000 != 0)
+);
+
+def_attr_class!(RA_LIFETIME_ATTR, "icmpv6.routerLifetime", cast: cast::UInt16BE());
+
+def_attr_class!(RA_REACHABLE_ATTR, "icmpv6.reachableTime", cast: cast::UInt32BE());
+
+def_attr_class!(RA_RETRANS_ATTR, "icmpv6.retransTimer", cast: cast::UInt32BE());
+
+def_attr_class!(NA_FLAGS_R_ATTR, "icmpv6.flags.router",
+    cast: cast::UInt8().map(|v| v & 0b10000000 != 0)
+);
+
+def_attr_class!(NA_FLAGS_S_ATTR, "icmpv6.flags.solicited",
+    cast: cast::UInt8().map(|v| v & 0b01000000 != 0)
+);
+
+def_attr_class!(NA_FLAGS_O_ATTR, "icmpv6.flags.override",
+    cast: cast::UInt8().map(|v| v & 0b00100000 != 0)
+);
+
+def_attr_class!(TARGET_ATTR, "icmpv6.target",
+    typ: "@ipv6:addr",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(DESTINATION_ATTR, "icmpv6.destination",
+    typ: "@ipv6:addr",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(OPTIONS_ATTR, "icmpv6.options",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(OPTIONS_SLLA_ATTR, "icmpv6.options.sourceLinkLayerAddress",
+    typ: "@eth:mac",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(OPTIONS_TLLA_ATTR, "icmpv6.options.targetLinkLayerAddress",
+    typ: "@eth:mac",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(OPTIONS_PREFIX_ATTR, "icmpv6.options.prefixInformation",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(OPTIONS_PREFIX_LENGTH_ATTR, "icmpv6.options.prefixInformation.prefixLength",
+    cast: cast::UInt8()
+);
+
+def_attr_class!(OPTIONS_PREFIX_FLAGS_L_ATTR, "icmpv6.options.prefixInformation.onLink",
+    cast: cast::UInt8().map(|v| v & 0b10000000 != 0)
+);
+
+def_attr_class!(OPTIONS_PREFIX_FLAGS_A_ATTR, "icmpv6.options.prefixInformation.autonomous",
+    cast: cast::UInt8().map(|v| v & 0b01000000 != 0)
+);
+
+def_attr_class!(OPTIONS_PREFIX_VALID_ATTR, "icmpv6.options.prefixInformation.validLifetime",
+    cast: cast::UInt32BE()
+);
+
+def_attr_class!(OPTIONS_PREFIX_PREFERRED_ATTR, "icmpv6.options.prefixInformation.preferredLifetime",
+    cast: cast::UInt32BE()
+);
+
+def_attr_class!(OPTIONS_PREFIX_PREFIX_ATTR, "icmpv6.options.prefixInformation.prefix",
+    typ: "@ipv6:addr",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(OPTIONS_MTU_ATTR, "icmpv6.options.mtu", cast: cast::UInt32BE());
+
+fn get_type(val: u64) -> Option<&'static AttrClass> {
+    match val {
+        1 => Some(attr_class_lazy!("icmpv6.type.unreachable", typ: "@novalue", value: true)),
+        2 => Some(attr_class_lazy!("icmpv6.type.packetTooBig", typ: "@novalue", value: true)),
+        3 => Some(attr_class_lazy!("icmpv6.type.timeExceeded", typ: "@novalue", value: true)),
+        4 => Some(attr_class_lazy!("icmpv6.type.parameterProblem", typ: "@novalue", value: true)),
+        128 => Some(attr_class_lazy!("icmpv6.type.echoRequest", typ: "@novalue", value: true)),
+        129 => Some(attr_class_lazy!("icmpv6.type.echoReply", typ: "@novalue", value: true)),
+        133 => {
+            Some(attr_class_lazy!("icmpv6.type.routerSolicitation", typ: "@novalue", value: true))
+        }
+        134 => {
+            Some(attr_class_lazy!("icmpv6.type.routerAdvertisement", typ: "@novalue", value: true))
+        }
+        135 => {
+            Some(attr_class_lazy!("icmpv6.type.neighborSolicitation", typ: "@novalue", value: true))
+        }
+        136 => Some(
+            attr_class_lazy!("icmpv6.type.neighborAdvertisement", typ: "@novalue", value: true),
+        ),
+        137 => Some(attr_class_lazy!("icmpv6.type.redirect", typ: "@novalue", value: true)),
+        _ => None,
+    }
+}
+
+fn get_code(typ: u64, val: u64) -> Option<&'static AttrClass> {
+    match (typ, val) {
+        (1, 0) => Some(attr_class_lazy!("icmpv6.code.noRoute", typ: "@novalue", value: true)),
+        (1, 1) => Some(attr_class_lazy!("icmpv6.code.prohibited", typ: "@novalue", value: true)),
+        (1, 2) => Some(attr_class_lazy!("icmpv6.code.beyondScope", typ: "@novalue", value: true)),
+        (1, 3) => {
+            Some(attr_class_lazy!("icmpv6.code.addressUnreachable", typ: "@novalue", value: true))
+        }
+        (1, 4) => {
+            Some(attr_class_lazy!("icmpv6.code.portUnreachable", typ: "@novalue", value: true))
+        }
+        (3, 0) => {
+            Some(attr_class_lazy!("icmpv6.code.hopLimitExceeded", typ: "@novalue", value: true))
+        }
+        (3, 1) => Some(
+            attr_class_lazy!("icmpv6.code.reassemblyTimeExceeded", typ: "@novalue", value: true),
+        ),
+        (4, 0) => {
+            Some(attr_class_lazy!("icmpv6.code.erroneousHeader", typ: "@novalue", value: true))
+        }
+        (4, 1) => Some(
+            attr_class_lazy!("icmpv6.code.unrecognizedNextHeader", typ: "@novalue", value: true),
+        ),
+        (4, 2) => {
+            Some(attr_class_lazy!("icmpv6.code.unrecognizedOption", typ: "@novalue", value: true))
+        }
+        _ => None,
+    }
+}
+
+genet_decoders!(IcmpV6Decoder {});
+
+#[cfg(test)]
+mod tests {
+    use super::IcmpV6Worker;
+    use genet_sdk::prelude::*;
+
+    fn ids(layer: &Layer) -> Vec<String> {
+        layer.attrs().iter().map(|a| a.id().to_string()).collect()
+    }
+
+    fn message(header: &[u8], body: &[u8]) -> Layer {
+        let mut data = header.to_vec();
+        data.extend_from_slice(body);
+        IcmpV6Worker::decode_message(ByteSlice::from(data)).unwrap()
+    }
+
+    #[test]
+    fn error_payload() {
+        let datagram = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x08, 0x11, 0x40, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 1, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0x30, 0x39, 0x00,
+            0x35, 0x00, 0x08, 0x00, 0x00,
+        ];
+        let layer = message(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00], &datagram);
+        assert_eq!(ids(&layer), vec!["icmpv6.type.packetTooBig", "icmpv6.mtu"]);
+        assert_eq!(layer.payloads().len(), 1);
+        assert_eq!(layer.payloads()[0].id(), Token::from("@data:ipv6"));
+        assert_eq!(&layer.payloads()[0].data()[..], &datagram[..]);
+
+        let layer = message(&[0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], &datagram);
+        assert_eq!(
+            ids(&layer),
+            vec!["icmpv6.type.unreachable", "icmpv6.code.portUnreachable"]
+        );
+        assert_eq!(&layer.payloads()[0].data()[..], &datagram[..]);
+
+        let layer = message(&[0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06], &datagram);
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "icmpv6.type.parameterProblem",
+                "icmpv6.code.unrecognizedNextHeader",
+                "icmpv6.pointer",
+            ]
+        );
+    }
+
+    #[test]
+    fn neighbor_discovery() {
+        let target = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let mut body = target.to_vec();
+        body.extend_from_slice(&[0x01, 0x01, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let layer = message(&[0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], &body);
+        assert!(layer.experts().is_empty());
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "icmpv6.type.neighborSolicitation",
+                "icmpv6.target",
+                "icmpv6.options",
+                "icmpv6.options.sourceLinkLayerAddress",
+            ]
+        );
+        assert_eq!(layer.attr("icmpv6.options").unwrap().range(), 24..32);
+        assert_eq!(
+            layer
+                .attr("icmpv6.options.sourceLinkLayerAddress")
+                .unwrap()
+                .range(),
+            26..32
+        );
+
+        let mut body = vec![0x40, 0xc0, 0x07, 0x08, 0, 0, 0, 0, 0, 0, 0, 0];
+        body.extend_from_slice(&[0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0x05, 0xdc]);
+        body.extend_from_slice(&[0x03, 0x04, 0x40, 0xc0, 0, 0, 0x0e, 0x10, 0, 0, 0x0e, 0x10]);
+        body.extend_from_slice(&[0, 0, 0, 0, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0]);
+        body.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        let layer = message(&[0x86, 0x00, 0x00, 0x00], &body);
+        assert!(layer.experts().is_empty());
+        let ids = ids(&layer);
+        assert!(ids.contains(&"icmpv6.options.mtu".to_string()));
+        assert!(ids.contains(&"icmpv6.options.prefixInformation.prefix".to_string()));
+        assert_eq!(layer.attr("icmpv6.options").unwrap().range(), 16..56);
+        assert_eq!(
+            layer
+                .attr("icmpv6.options.prefixInformation.prefix")
+                .unwrap()
+                .range(),
+            40..56
+        );
+    }
+
+    #[test]
+    fn invalid_options() {
+        let mut body = [0u8; 16].to_vec();
+        body.extend_from_slice(&[0x02, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let layer = message(&[0x88, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00], &body);
+        assert_eq!(layer.experts().len(), 1);
+        assert_eq!(layer.experts()[0].severity(), Severity::Error);
+        assert_eq!(layer.experts()[0].range(), 24..32);
+
+        let data = ByteSlice::from(&b"\x87\x00\x00\x00\x00\x00\x00\x00"[..]);
+        assert!(IcmpV6Worker::decode_message(data).is_err());
+    }
+}
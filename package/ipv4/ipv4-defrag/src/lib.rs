@@ -0,0 +1,52 @@
+extern crate genet_sdk;
+
+use genet_sdk::{
+    decoder::*,
+    defrag::{DefragWorker, Protocol},
+    prelude::*,
+};
+
+#[derive(Clone)]
+struct IPv4DefragDecoder {}
+
+impl Decoder for IPv4DefragDecoder {
+    fn new_worker(&self, ctx: &Context) -> Box<Worker> {
+        Box::new(DefragWorker::<u16>::new(
+            ctx,
+            Protocol {
+                layer: "ipv4",
+                fragment: "@fragment:ipv4",
+                src: "ipv4.src",
+                dst: "ipv4.dst",
+                id: "ipv4.id",
+                offset: "ipv4.fragmentOffset",
+                more_fragments: "ipv4.flags.moreFragments",
+                max_size: 65535,
+                timeout: "@genet/ipv4.reassembly.timeout",
+                memory_limit: "@genet/ipv4.reassembly.memoryLimit",
+                reassembled: &REASSEMBLED_ATTR,
+                reassembled_length: &REASSEMBLED_LENGTH_ATTR,
+                reassembled_fragments: &REASSEMBLED_FRAGMENTS_ATTR,
+            },
+        ))
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.ipv4-defrag".into(),
+            exec_type: ExecType::SerialSync,
+            ..Metadata::default()
+        }
+    }
+}
+
+def_attr_class!(REASSEMBLED_ATTR, "ipv4.reassembled",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(REASSEMBLED_LENGTH_ATTR, "ipv4.reassembled.length");
+
+def_attr_class!(REASSEMBLED_FRAGMENTS_ATTR, "ipv4.reassembled.fragments");
+
+genet_decoders!(IPv4DefragDecoder {});
@@ -1,6 +1,6 @@
 extern crate genet_sdk;
 
-use genet_sdk::{cast, decoder::*, prelude::*};
+use genet_sdk::{cast, decoder::*, error::Error, prelude::*};
 
 struct IPv4Worker {}
 
@@ -23,19 +23,141 @@ impl Worker for IPv4Worker {
             return Ok(Status::Skip);
         }
 
+        parent.add_child(Self::decode_packet(data)?);
+        Ok(Status::Done)
+    }
+}
+
+impl IPv4Worker {
+    fn decode_packet(data: ByteSlice) -> Result<Layer> {
+        let header_len = usize::from(data.try_get(0)? & 0x0f) * 4;
+        let total_len = usize::from(data.try_get(2)?) << 8 | usize::from(data.try_get(3)?);
+        if header_len < 20 || total_len < header_len {
+            return Err(Box::new(Error::new("invalid header length")));
+        }
+
+        // Trailing bytes such as Ethernet padding are not part of the packet.
+        let truncated = total_len > data.len();
+        let data = if truncated {
+            data
+        } else {
+            data.try_get(..total_len)?
+        };
+
         let mut layer = Layer::new(&IPV4_CLASS, data);
-        let proto = PROTO_ATTR_HEADER.try_get(&layer)?.try_into()?;
-        if let Some((typ, attr)) = get_proto(proto) {
+        let header = layer.data().try_get(..header_len)?;
+        if truncated {
+            layer.add_expert(
+                Expert::new(Severity::Warning, "malformed", "packet is truncated").with_range(2..4),
+            );
+        }
+
+        layer.add_attr(attr!(&CHECKSUM_VALID_ATTR, range: 0..header_len));
+        if checksum(&header) != 0 {
+            layer.add_expert(
+                Expert::new(Severity::Warning, "checksum", "bad header checksum")
+                    .with_range(10..12),
+            );
+        }
+
+        if header_len > 20 {
+            Self::decode_options(&mut layer, header_len)?;
+        }
+
+        let proto = get_proto(PROTO_ATTR_HEADER.try_get(&layer)?.try_into()?);
+        if let Some((_, attr)) = proto {
             layer.add_attr(attr!(attr, range: 9..10));
-            let payload = layer.data().try_get(20..)?;
+        }
+
+        let payload = layer.data().try_get(header_len..)?;
+        let more_fragments: u8 = FLAGS_MF_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        let fragment_offset: u16 = OFFSET_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if more_fragments != 0 || fragment_offset > 0 {
+            // Fragments are decoded further once reassembled.
+            let typ = proto.map_or_else(Token::null, |(typ, _)| typ);
+            layer.add_payload(Payload::with_typ(payload, "@fragment:ipv4", typ));
+        } else if let Some((typ, _)) = proto {
             layer.add_payload(Payload::new(payload, typ));
         }
 
-        parent.add_child(layer);
-        Ok(Status::Done)
+        Ok(layer)
+    }
+
+    fn decode_options(layer: &mut Layer, header_len: usize) -> Result<()> {
+        let mut offset = 20;
+        while offset < header_len {
+            let typ = layer.data().try_get(offset)?;
+            if typ <= 1 {
+                if typ == 0 {
+                    layer.add_attr(attr!(&OPTIONS_EOL_ATTR, range: offset..offset + 1));
+                    break;
+                }
+                layer.add_attr(attr!(&OPTIONS_NOP_ATTR, range: offset..offset + 1));
+                offset += 1;
+                continue;
+            }
+            let len = layer.data().try_get(offset + 1)? as usize;
+            if len < 2 || offset + len > header_len {
+                layer.add_expert(
+                    Expert::new(Severity::Error, "malformed", "invalid option length")
+                        .with_range(offset..header_len),
+                );
+                break;
+            }
+            match typ {
+                0x44 if len >= 4 => {
+                    layer.add_attr(attr!(&OPTIONS_TS_ATTR, range: offset..offset + len));
+                    layer.add_attr(attr!(&OPTIONS_TS_POINTER_ATTR, range: offset + 2..offset + 3));
+                    layer.add_attr(attr!(&OPTIONS_TS_OVERFLOW_ATTR, range: offset + 3..offset + 4));
+                    layer.add_attr(attr!(&OPTIONS_TS_FLAG_ATTR, range: offset + 3..offset + 4));
+                    layer.add_attr(attr!(&OPTIONS_TS_DATA_ATTR, range: offset + 4..offset + len));
+                }
+                0x82 => {
+                    layer.add_attr(attr!(&OPTIONS_SECURITY_ATTR, range: offset + 2..offset + len));
+                }
+                0x88 if len == 4 => {
+                    layer.add_attr(attr!(&OPTIONS_STREAM_ATTR, range: offset + 2..offset + 4));
+                }
+                0x94 if len == 4 => {
+                    layer.add_attr(attr!(&OPTIONS_RA_ATTR, range: offset + 2..offset + 4));
+                }
+                _ => {
+                    if let Some(classes) = get_route(typ) {
+                        Self::decode_route(layer, offset, len, classes);
+                    }
+                }
+            }
+            offset += len;
+        }
+        layer.add_attr(attr!(&OPTIONS_ATTR, range: 20..header_len));
+        Ok(())
+    }
+
+    fn decode_route(layer: &mut Layer, offset: usize, len: usize, classes: RouteClasses) {
+        layer.add_attr(attr!(classes.0, range: offset..offset + len));
+        if len >= 3 {
+            layer.add_attr(attr!(classes.1, range: offset + 2..offset + 3));
+        }
+        let mut addr = offset + 3;
+        while addr + 4 <= offset + len {
+            layer.add_attr(attr!(classes.2, range: addr..addr + 4));
+            addr += 4;
+        }
     }
 }
 
+/// Computes the internet checksum, which is zero for a valid header.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|w| u32::from(w[0]) << 8 | u32::from(*w.get(1).unwrap_or(&0)))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
 #[derive(Clone)]
 struct IPv4Decoder {}
 
@@ -46,6 +168,7 @@ impl Decoder for IPv4Decoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.ipv4".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
@@ -63,8 +186,8 @@ def_layer_class!(IPV4_CLASS, "ipv4",
     header: attr!(&FLAGS_ATTR, bit_range: 6 0..1),
     header: attr!(&FLAGS_RV_ATTR, bit_range: 6 1..2),
     header: attr!(&FLAGS_DF_ATTR, bit_range: 6 2..3),
-    header: attr!(&FLAGS_MF_ATTR, bit_range: 6 3..4),
-    header: attr!(&OFFSET_ATTR, bit_range: 6 4..16),
+    header: &FLAGS_MF_ATTR_HEADER,
+    header: &OFFSET_ATTR_HEADER,
     header: attr!(&TTL_ATTR, range: 8..9),
     header: &PROTO_ATTR_HEADER,
     header: attr!(&CHECKSUM_ATTR, range: 10..12),
@@ -73,6 +196,8 @@ def_layer_class!(IPV4_CLASS, "ipv4",
 );
 
 def_attr!(PROTO_ATTR_HEADER,  &PROTO_ATTR, range: 9..10);
+def_attr!(FLAGS_MF_ATTR_HEADER, &FLAGS_MF_ATTR, bit_range: 6 3..4);
+def_attr!(OFFSET_ATTR_HEADER, &OFFSET_ATTR, bit_range: 6 4..16);
 
 def_attr_class!(VERSION_ATTR, "ipv4.version",
     cast: cast::UInt8().map(|v| v >> 4)
@@ -118,6 +243,10 @@ def_attr_class!(PROTO_ATTR, "ipv4.protocol",
 
 def_attr_class!(CHECKSUM_ATTR, "ipv4.checksum", cast: cast::UInt16BE());
 
+def_attr_class!(CHECKSUM_VALID_ATTR, "ipv4.checksum.valid",
+    cast: cast::ByteSlice().map(|data| checksum(&data) == 0)
+);
+
 def_attr_class!(SRC_ATTR, "ipv4.src",
     typ: "@ipv4:addr",
     cast: cast::ByteSlice()
@@ -128,6 +257,46 @@ def_attr_class!(DST_ATTR, "ipv4.dst",
     cast: cast::ByteSlice()
 );
 
+def_attr_class!(OPTIONS_ATTR, "ipv4.options",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(OPTIONS_EOL_ATTR, "ipv4.options.eol",
+    typ: "@novalue",
+    value: true
+);
+
+def_attr_class!(OPTIONS_NOP_ATTR, "ipv4.options.nop",
+    typ: "@novalue",
+    value: true
+);
+
+type RouteClasses = (&'static AttrClass, &'static AttrClass, &'static AttrClass);
+
+def_attr_class!(OPTIONS_TS_ATTR, "ipv4.options.ts",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(OPTIONS_TS_POINTER_ATTR, "ipv4.options.ts.pointer", cast: cast::UInt8());
+
+def_attr_class!(OPTIONS_TS_OVERFLOW_ATTR, "ipv4.options.ts.overflow",
+    cast: cast::UInt8().map(|v| v >> 4)
+);
+
+def_attr_class!(OPTIONS_TS_FLAG_ATTR, "ipv4.options.ts.flag",
+    cast: cast::UInt8().map(|v| v & 0b00001111)
+);
+
+def_attr_class!(OPTIONS_TS_DATA_ATTR, "ipv4.options.ts.data", cast: cast::ByteSlice());
+
+def_attr_class!(OPTIONS_SECURITY_ATTR, "ipv4.options.security", cast: cast::ByteSlice());
+
+def_attr_class!(OPTIONS_STREAM_ATTR, "ipv4.options.streamId", cast: cast::UInt16BE());
+
+def_attr_class!(OPTIONS_RA_ATTR, "ipv4.options.routerAlert", cast: cast::UInt16BE());
+
 fn get_proto(val: u64) -> Option<(Token, &'static AttrClass)> {
     match val {
         0x01 => Some((
@@ -150,4 +319,167 @@ fn get_proto(val: u64) -> Option<(Token, &'static AttrClass)> {
     }
 }
 
+fn get_route(val: u8) -> Option<RouteClasses> {
+    match val {
+        0x07 => Some((
+            attr_class_lazy!("ipv4.options.rr", typ: "@nested", value: true),
+            attr_class_lazy!("ipv4.options.rr.pointer", cast: cast::UInt8()),
+            attr_class_lazy!("ipv4.options.rr.route",
+                typ: "@ipv4:addr",
+                cast: cast::ByteSlice()
+            ),
+        )),
+        0x83 => Some((
+            attr_class_lazy!("ipv4.options.lsrr", typ: "@nested", value: true),
+            attr_class_lazy!("ipv4.options.lsrr.pointer", cast: cast::UInt8()),
+            attr_class_lazy!("ipv4.options.lsrr.route",
+                typ: "@ipv4:addr",
+                cast: cast::ByteSlice()
+            ),
+        )),
+        0x89 => Some((
+            attr_class_lazy!("ipv4.options.ssrr", typ: "@nested", value: true),
+            attr_class_lazy!("ipv4.options.ssrr.pointer", cast: cast::UInt8()),
+            attr_class_lazy!("ipv4.options.ssrr.route",
+                typ: "@ipv4:addr",
+                cast: cast::ByteSlice()
+            ),
+        )),
+        _ => None,
+    }
+}
+
 genet_decoders!(IPv4Decoder {});
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, IPv4Worker};
+    use genet_sdk::prelude::*;
+
+    fn ids(layer: &Layer) -> Vec<String> {
+        layer.attrs().iter().map(|a| a.id().to_string()).collect()
+    }
+
+    fn packet(options: &[u8], total_len: usize, payload: &[u8]) -> ByteSlice {
+        let header_len = 20 + options.len();
+        let mut data = vec![
+            0x40 | (header_len / 4) as u8,
+            0x00,
+            (total_len >> 8) as u8,
+            total_len as u8,
+            0x12,
+            0x34,
+            0x00,
+            0x00,
+            0x40,
+            0x11,
+            0x00,
+            0x00,
+            192,
+            168,
+            0,
+            1,
+            192,
+            168,
+            0,
+            2,
+        ];
+        data.extend_from_slice(options);
+        let sum = checksum(&data);
+        data[10] = (sum >> 8) as u8;
+        data[11] = sum as u8;
+        data.extend_from_slice(payload);
+        ByteSlice::from(data)
+    }
+
+    #[test]
+    fn header_length() {
+        let layer = IPv4Worker::decode_packet(packet(&[], 24, b"abcd")).unwrap();
+        assert!(layer.experts().is_empty());
+        assert_eq!(
+            ids(&layer),
+            vec!["ipv4.checksum.valid", "ipv4.protocol.udp"]
+        );
+        assert_eq!(layer.payloads().len(), 1);
+        assert_eq!(layer.payloads()[0].id(), Token::from("@data:udp"));
+        assert_eq!(&layer.payloads()[0].data()[..], b"abcd");
+
+        let mut data = packet(&[], 24, b"abcd").to_vec();
+        data[0] = 0x44;
+        assert!(IPv4Worker::decode_packet(ByteSlice::from(data)).is_err());
+        assert!(IPv4Worker::decode_packet(packet(&[], 16, b"")).is_err());
+        assert!(IPv4Worker::decode_packet(packet(&[], 20, b"")).is_ok());
+    }
+
+    #[test]
+    fn total_length() {
+        // Ethernet pads short frames, and the padding is not part of the packet.
+        let layer = IPv4Worker::decode_packet(packet(&[], 22, b"ab\0\0\0\0")).unwrap();
+        assert_eq!(layer.data().len(), 22);
+        assert_eq!(&layer.payloads()[0].data()[..], b"ab");
+        assert!(layer.experts().is_empty());
+
+        let layer = IPv4Worker::decode_packet(packet(&[], 30, b"ab")).unwrap();
+        assert_eq!(layer.data().len(), 22);
+        assert_eq!(&layer.payloads()[0].data()[..], b"ab");
+        assert_eq!(layer.experts().len(), 1);
+        assert_eq!(layer.experts()[0].severity(), Severity::Warning);
+        assert_eq!(layer.experts()[0].range(), 2..4);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = packet(&[], 20, b"").to_vec();
+        data[11] ^= 0xff;
+        let layer = IPv4Worker::decode_packet(ByteSlice::from(data)).unwrap();
+        assert_eq!(layer.experts().len(), 1);
+        assert_eq!(layer.experts()[0].group(), Token::from("checksum"));
+        assert_eq!(layer.experts()[0].range(), 10..12);
+    }
+
+    #[test]
+    fn options() {
+        let options = [
+            0x01, 0x94, 0x04, 0x00, 0x00, 0x07, 0x07, 0x04, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let layer = IPv4Worker::decode_packet(packet(&options, 40, b"abcd")).unwrap();
+        assert!(layer.experts().is_empty());
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "ipv4.checksum.valid",
+                "ipv4.options.nop",
+                "ipv4.options.routerAlert",
+                "ipv4.options.rr",
+                "ipv4.options.rr.pointer",
+                "ipv4.options.rr.route",
+                "ipv4.options.eol",
+                "ipv4.options",
+                "ipv4.protocol.udp",
+            ]
+        );
+        let route = layer.attr("ipv4.options.rr.route").unwrap();
+        assert_eq!(route.range(), 28..32);
+        assert_eq!(layer.attr("ipv4.options").unwrap().range(), 20..36);
+        assert_eq!(&layer.payloads()[0].data()[..], b"abcd");
+    }
+
+    #[test]
+    fn invalid_options() {
+        let options = [0x01, 0x94, 0x08, 0x00];
+        let layer = IPv4Worker::decode_packet(packet(&options, 24, b"")).unwrap();
+        assert_eq!(
+            ids(&layer),
+            vec![
+                "ipv4.checksum.valid",
+                "ipv4.options.nop",
+                "ipv4.options",
+                "ipv4.protocol.udp"
+            ]
+        );
+        assert_eq!(layer.experts().len(), 1);
+        assert_eq!(layer.experts()[0].severity(), Severity::Error);
+        assert_eq!(layer.experts()[0].range(), 21..24);
+    }
+}
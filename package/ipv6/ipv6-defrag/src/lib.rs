@@ -0,0 +1,53 @@
+extern crate genet_sdk;
+
+use genet_sdk::{
+    decoder::*,
+    defrag::{DefragWorker, Protocol},
+    prelude::*,
+};
+
+#[derive(Clone)]
+struct IPv6DefragDecoder {}
+
+impl Decoder for IPv6DefragDecoder {
+    fn new_worker(&self, ctx: &Context) -> Box<Worker> {
+        Box::new(DefragWorker::<u32>::new(
+            ctx,
+            Protocol {
+                layer: "ipv6",
+                fragment: "@fragment:ipv6",
+                src: "ipv6.src",
+                dst: "ipv6.dst",
+                id: "ipv6.fragment.id",
+                offset: "ipv6.fragment.offset",
+                more_fragments: "ipv6.fragment.moreFragments",
+                // The largest fragment offset plus the largest fragment.
+                max_size: 0x1fff * 8 + 65535,
+                timeout: "@genet/ipv6.reassembly.timeout",
+                memory_limit: "@genet/ipv6.reassembly.memoryLimit",
+                reassembled: &REASSEMBLED_ATTR,
+                reassembled_length: &REASSEMBLED_LENGTH_ATTR,
+                reassembled_fragments: &REASSEMBLED_FRAGMENTS_ATTR,
+            },
+        ))
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.decoder.ipv6-defrag".into(),
+            exec_type: ExecType::SerialSync,
+            ..Metadata::default()
+        }
+    }
+}
+
+def_attr_class!(REASSEMBLED_ATTR, "ipv6.reassembled",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(REASSEMBLED_LENGTH_ATTR, "ipv6.reassembled.length");
+
+def_attr_class!(REASSEMBLED_FRAGMENTS_ATTR, "ipv6.reassembled.fragments");
+
+genet_decoders!(IPv6DefragDecoder {});
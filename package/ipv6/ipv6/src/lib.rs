@@ -1,6 +1,7 @@
 extern crate genet_sdk;
 
-use genet_sdk::{cast, decoder::*, prelude::*};
+use genet_sdk::{cast, decoder::*, error::Error, prelude::*};
+use std::ops::Range;
 
 struct IPv6Worker {}
 
@@ -23,31 +24,122 @@ impl Worker for IPv6Worker {
             return Ok(Status::Skip);
         }
 
+        // A zero payload length is used by jumbograms.
+        let payload_len = usize::from(data.try_get(4)?) << 8 | usize::from(data.try_get(5)?);
+        let truncated = 40 + payload_len > data.len();
+        let data = if truncated || payload_len == 0 {
+            data
+        } else {
+            data.try_get(..40 + payload_len)?
+        };
+
         let mut layer = Layer::new(&IPV6_CLASS, data);
-        let nheader = NHEADER_ATTR_HEADER.try_get(&layer)?.try_into()?;
+        if truncated {
+            layer.add_expert(
+                Expert::new(Severity::Warning, "malformed", "packet is truncated").with_range(4..6),
+            );
+        }
 
+        let mut range = NHEADER_ATTR_HEADER.range();
+        let mut offset = 40;
+        let mut fragment = None;
         loop {
+            let nheader = layer.data().try_get(range.start)?;
             match nheader {
-                // TODO:
-                // case 0 | 60 # Hop-by-Hop Options, Destination Options
-                // case 43  # Routing
-                // case 44  # Fragment
-                // case 51  # Authentication Header
-                // case 50  # Encapsulating Security Payload
-                // case 135 # Mobility
-                // No Next Header
-                59 => break,
+                // Hop-by-Hop Options, Destination Options
+                0 | 60 => {
+                    let len = (usize::from(layer.data().try_get(offset + 1)?) + 1) * 8;
+                    if let Some(classes) = get_options(nheader) {
+                        Self::decode_options(&mut layer, offset..offset + len, classes)?;
+                    }
+                    range = offset..offset + 1;
+                    offset += len;
+                }
+                // Routing
+                43 => {
+                    let len = (usize::from(layer.data().try_get(offset + 1)?) + 1) * 8;
+                    layer.data().try_get(offset..offset + len)?;
+                    layer.add_attr(attr!(&ROUTING_ATTR, range: offset..offset + len));
+                    layer.add_attr(attr!(&ROUTING_TYPE_ATTR, range: offset + 2..offset + 3));
+                    layer.add_attr(attr!(&ROUTING_LEFT_ATTR, range: offset + 3..offset + 4));
+                    if layer.data().try_get(offset + 2)? == 0 {
+                        let mut addr = offset + 8;
+                        while addr + 16 <= offset + len {
+                            layer.add_attr(attr!(&ROUTING_ADDR_ATTR, range: addr..addr + 16));
+                            addr += 16;
+                        }
+                    }
+                    range = offset..offset + 1;
+                    offset += len;
+                }
+                // Fragment
+                44 => {
+                    layer.data().try_get(offset..offset + 8)?;
+                    layer.add_attr(attr!(&FRAGMENT_ATTR, range: offset..offset + 8));
+                    layer.add_attr(attr!(&FRAGMENT_OFFSET_ATTR, range: offset + 2..offset + 4));
+                    layer.add_attr(attr!(&FRAGMENT_MF_ATTR, range: offset + 3..offset + 4));
+                    layer.add_attr(attr!(&FRAGMENT_ID_ATTR, range: offset + 4..offset + 8));
+                    fragment = Some(offset);
+                    range = offset..offset + 1;
+                    offset += 8;
+
+                    // The rest of the packet is decoded once reassembled.
+                    break;
+                }
+                // Authentication Header
+                51 => {
+                    let len = (usize::from(layer.data().try_get(offset + 1)?) + 2) * 4;
+                    layer.data().try_get(offset..offset + len)?;
+                    layer.add_attr(attr!(&AH_ATTR, range: offset..offset + len));
+                    layer.add_attr(attr!(&AH_SPI_ATTR, range: offset + 4..offset + 8));
+                    layer.add_attr(attr!(&AH_SEQ_ATTR, range: offset + 8..offset + 12));
+                    layer.add_attr(attr!(&AH_ICV_ATTR, range: offset + 12..offset + len));
+                    range = offset..offset + 1;
+                    offset += len;
+                }
+                // Encapsulating Security Payload
+                50 => {
+                    layer.data().try_get(offset..offset + 8)?;
+                    let len = layer.data().len() - offset;
+                    layer.add_attr(attr!(&ESP_ATTR, range: offset..offset + len));
+                    layer.add_attr(attr!(&ESP_SPI_ATTR, range: offset..offset + 4));
+                    layer.add_attr(attr!(&ESP_SEQ_ATTR, range: offset + 4..offset + 8));
+                    break;
+                }
+                // Mobility
+                135 => {
+                    let len = (usize::from(layer.data().try_get(offset + 1)?) + 1) * 8;
+                    layer.data().try_get(offset..offset + len)?;
+                    layer.add_attr(attr!(&MOBILITY_ATTR, range: offset..offset + len));
+                    layer.add_attr(attr!(&MOBILITY_TYPE_ATTR, range: offset + 2..offset + 3));
+                    range = offset..offset + 1;
+                    offset += len;
+                }
+                // No Next Header and upper-layer protocols
                 _ => break,
             }
         }
 
-        let range = NHEADER_ATTR_HEADER.range();
         let proto_attr = attr!(&PROTOCOL_ATTR, range: range.clone());
         let proto = proto_attr.try_get(&layer)?.try_into()?;
         layer.add_attr(proto_attr);
-        if let Some((typ, attr)) = get_proto(proto) {
+        let proto = get_proto(proto);
+        if let Some((_, attr)) = proto {
             layer.add_attr(attr!(attr, range: range.clone()));
-            let payload = layer.data().try_get(40..)?;
+        }
+
+        let payload = layer.data().try_get(offset..)?;
+        if let Some(fragment) = fragment {
+            let header = layer.data().try_get(fragment + 2..fragment + 4)?;
+            if header[0] != 0 || header[1] != 0 {
+                // Fragments are decoded further once reassembled.
+                let typ = proto.map_or_else(Token::null, |(typ, _)| typ);
+                layer.add_payload(Payload::with_typ(payload, "@fragment:ipv6", typ));
+                parent.add_child(layer);
+                return Ok(Status::Done);
+            }
+        }
+        if let Some((typ, _)) = proto {
             layer.add_payload(Payload::new(payload, typ));
         }
 
@@ -56,6 +148,40 @@ impl Worker for IPv6Worker {
     }
 }
 
+impl IPv6Worker {
+    fn decode_options(
+        layer: &mut Layer,
+        range: Range<usize>,
+        classes: OptionClasses,
+    ) -> Result<()> {
+        layer.data().try_get(range.clone())?;
+        layer.add_attr(attr!(classes.0, range: range.clone()));
+        let mut offset = range.start + 2;
+        while offset < range.end {
+            let typ = layer.data().try_get(offset)?;
+            if typ == 0 {
+                offset += 1;
+                continue;
+            }
+            let len = usize::from(layer.data().try_get(offset + 1)?) + 2;
+            if offset + len > range.end {
+                return Err(Box::new(Error::new("invalid option length")));
+            }
+            match typ {
+                0x05 if len == 4 => {
+                    layer.add_attr(attr!(classes.1, range: offset + 2..offset + 4));
+                }
+                0xc2 if len == 6 => {
+                    layer.add_attr(attr!(classes.2, range: offset + 2..offset + 6));
+                }
+                _ => {}
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 struct IPv6Decoder {}
 
@@ -66,6 +192,7 @@ impl Decoder for IPv6Decoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.ipv6".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
@@ -95,13 +222,13 @@ def_attr_class!(TRAFFIC_ATTR, "ipv6.trafficClass",
     cast: cast::UInt16BE().map(|v| (v >> 4) & 0xff)
 );
 
-def_attr_class!(FLOW_ATTR, "ipv6.flowLabel", 
+def_attr_class!(FLOW_ATTR, "ipv6.flowLabel",
     cast:
         cast::ByteSlice()
             .map(|v| (((v[2] as u32) & 0xf) << 16) | ((v[1] as u32) << 8) | v[2] as u32)
 );
 
-def_attr_class!(LENGTH_ATTR, "ipv6.payloadLength", cast: cast::UInt8());
+def_attr_class!(LENGTH_ATTR, "ipv6.payloadLength", cast: cast::UInt16BE());
 
 def_attr_class!(NHEADER_ATTR, "ipv6.nextHeader", cast: cast::UInt8());
 
@@ -122,6 +249,64 @@ def_attr_class!(PROTOCOL_ATTR, "ipv6.protocol",
     cast: cast::UInt8()
 );
 
+type OptionClasses = (&'static AttrClass, &'static AttrClass, &'static AttrClass);
+
+def_attr_class!(ROUTING_ATTR, "ipv6.routing",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(ROUTING_TYPE_ATTR, "ipv6.routing.type", cast: cast::UInt8());
+
+def_attr_class!(ROUTING_LEFT_ATTR, "ipv6.routing.segmentsLeft", cast: cast::UInt8());
+
+def_attr_class!(ROUTING_ADDR_ATTR, "ipv6.routing.address",
+    typ: "@ipv6:addr",
+    cast: cast::ByteSlice()
+);
+
+def_attr_class!(FRAGMENT_ATTR, "ipv6.fragment",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(FRAGMENT_OFFSET_ATTR, "ipv6.fragment.offset",
+    cast: cast::UInt16BE().map(|v| v >> 3)
+);
+
+def_attr_class!(FRAGMENT_MF_ATTR, "ipv6.fragment.moreFragments",
+    cast: cast::UInt8().map(|v| v & 0b00000001 != 0)
+);
+
+def_attr_class!(FRAGMENT_ID_ATTR, "ipv6.fragment.id", cast: cast::UInt32BE());
+
+def_attr_class!(AH_ATTR, "ipv6.ah",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(AH_SPI_ATTR, "ipv6.ah.spi", cast: cast::UInt32BE());
+
+def_attr_class!(AH_SEQ_ATTR, "ipv6.ah.seq", cast: cast::UInt32BE());
+
+def_attr_class!(AH_ICV_ATTR, "ipv6.ah.icv", cast: cast::ByteSlice());
+
+def_attr_class!(ESP_ATTR, "ipv6.esp",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(ESP_SPI_ATTR, "ipv6.esp.spi", cast: cast::UInt32BE());
+
+def_attr_class!(ESP_SEQ_ATTR, "ipv6.esp.seq", cast: cast::UInt32BE());
+
+def_attr_class!(MOBILITY_ATTR, "ipv6.mobility",
+    typ: "@nested",
+    value: true
+);
+
+def_attr_class!(MOBILITY_TYPE_ATTR, "ipv6.mobility.type", cast: cast::UInt8());
+
 fn get_proto(val: u64) -> Option<(Token, &'static AttrClass)> {
     match val {
         0x02 => Some((
@@ -137,8 +322,24 @@ fn get_proto(val: u64) -> Option<(Token, &'static AttrClass)> {
             attr_class_lazy!("ipv6.protocol.udp", typ: "@novalue", value: true),
         )),
         0x3a => Some((
-            token!("@data:icmp"),
-            attr_class_lazy!("ipv6.protocol.icmp", typ: "@novalue", value: true),
+            token!("@data:icmpv6"),
+            attr_class_lazy!("ipv6.protocol.icmpv6", typ: "@novalue", value: true),
+        )),
+        _ => None,
+    }
+}
+
+fn get_options(val: u8) -> Option<OptionClasses> {
+    match val {
+        0 => Some((
+            attr_class_lazy!("ipv6.hopByHop", typ: "@nested", value: true),
+            attr_class_lazy!("ipv6.hopByHop.routerAlert", cast: cast::UInt16BE()),
+            attr_class_lazy!("ipv6.hopByHop.jumboPayloadLength", cast: cast::UInt32BE()),
+        )),
+        60 => Some((
+            attr_class_lazy!("ipv6.dstOpts", typ: "@nested", value: true),
+            attr_class_lazy!("ipv6.dstOpts.routerAlert", cast: cast::UInt16BE()),
+            attr_class_lazy!("ipv6.dstOpts.jumboPayloadLength", cast: cast::UInt32BE()),
         )),
         _ => None,
     }
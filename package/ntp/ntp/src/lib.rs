@@ -76,6 +76,7 @@ impl Decoder for NtpDecoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.ntp".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
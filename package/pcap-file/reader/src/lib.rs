@@ -10,7 +10,7 @@ use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use genet_sdk::{prelude::*, reader::*};
 use std::{
     fs::File,
-    io::{self, BufReader, Error, ErrorKind, Read},
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read},
 };
 
 #[derive(Deserialize)]
@@ -87,7 +87,11 @@ struct PcapFileWorker {
 }
 
 impl PcapFileWorker {
-    fn read_one(&mut self) -> io::Result<Layer> {
+    fn read_one(&mut self) -> io::Result<Option<Layer>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
         let (ts_sec, mut ts_usec, inc_len, orig_len) = if self.le {
             (
                 self.reader.read_u32::<LittleEndian>()?,
@@ -125,7 +129,7 @@ impl PcapFileWorker {
         layer.add_attr(attr!(&TS_SEC_CLASS, value: u64::from(ts_sec)));
         layer.add_attr(attr!(&TS_USEC_CLASS, value: u64::from(ts_usec)));
 
-        Ok(layer)
+        Ok(Some(layer))
     }
 }
 
@@ -136,7 +140,8 @@ impl Worker for PcapFileWorker {
         let mut layers = Vec::with_capacity(BLOCK_SIZE);
         for _ in 0..BLOCK_SIZE {
             match self.read_one() {
-                Ok(layer) => layers.push(layer),
+                Ok(Some(layer)) => layers.push(layer),
+                Ok(None) => break,
                 Err(err) => {
                     if layers.is_empty() {
                         return Err(err.into());
@@ -0,0 +1,6 @@
+module.exports = (sess, arg) => {
+  if (arg.file.endsWith('.pcapng')) {
+    sess.createReader('app.genet.reader.pcapng-file', arg)
+    return true
+  }
+}
@@ -0,0 +1,606 @@
+extern crate byteorder;
+extern crate genet_sdk;
+extern crate serde;
+extern crate serde_json;
+
+#[macro_use]
+extern crate serde_derive;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use genet_sdk::{prelude::*, reader::*};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read},
+};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const SIMPLE_PACKET_BLOCK: u32 = 0x0000_0003;
+const NAME_RESOLUTION_BLOCK: u32 = 0x0000_0004;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+/// The largest block accepted, to reject corrupt lengths early.
+const MAX_BLOCK_LEN: u32 = 16 * 1024 * 1024;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+const IF_TSOFFSET: u16 = 14;
+
+const NRB_RECORD_IPV4: u16 = 1;
+const NRB_RECORD_IPV6: u16 = 2;
+
+#[derive(Deserialize)]
+struct Arg {
+    file: String,
+}
+
+#[derive(Clone)]
+struct PcapngFileReader {}
+
+impl Reader for PcapngFileReader {
+    fn new_worker(&self, _ctx: &Context, arg: &str) -> Result<Box<Worker>> {
+        let arg: Arg = serde_json::from_str(arg)?;
+        let file = File::open(&arg.file)?;
+        let worker = PcapngFileWorker::new(BufReader::new(file))?;
+        Ok(Box::new(worker))
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.reader.pcapng-file".into(),
+            filters: vec![FileType::new("Pcapng File", &["pcapng"])],
+            ..Metadata::default()
+        }
+    }
+}
+
+struct Interface {
+    link_class: Fixed<LayerClass>,
+    snaplen: u32,
+    units_per_sec: u64,
+    tsoffset: i64,
+}
+
+impl Interface {
+    fn timestamp(&self, ts: u64) -> (u64, u64) {
+        let sec = ts / self.units_per_sec;
+        let frac = ts % self.units_per_sec;
+        let nsec = u128::from(frac) * 1_000_000_000 / u128::from(self.units_per_sec);
+        ((sec as i64 + self.tsoffset) as u64, nsec as u64)
+    }
+}
+
+struct NameRecord {
+    class: &'static AttrClass,
+    address: Vec<u8>,
+    names: Vec<String>,
+}
+
+struct PcapngFileWorker<R> {
+    le: bool,
+    reader: R,
+    interfaces: Vec<Interface>,
+    names: Vec<NameRecord>,
+}
+
+impl<R: BufRead> PcapngFileWorker<R> {
+    fn new(mut reader: R) -> io::Result<PcapngFileWorker<R>> {
+        let block_type = reader.read_u32::<BigEndian>()?;
+        if block_type != SECTION_HEADER_BLOCK {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong magic number"));
+        }
+
+        let mut worker = PcapngFileWorker {
+            le: false,
+            reader,
+            interfaces: Vec::new(),
+            names: Vec::new(),
+        };
+        worker.read_section_header()?;
+        Ok(worker)
+    }
+
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        if self.le {
+            LittleEndian::read_u16(&buf[offset..])
+        } else {
+            BigEndian::read_u16(&buf[offset..])
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        if self.le {
+            LittleEndian::read_u32(&buf[offset..])
+        } else {
+            BigEndian::read_u32(&buf[offset..])
+        }
+    }
+
+    fn u64_at(&self, buf: &[u8], offset: usize) -> u64 {
+        if self.le {
+            LittleEndian::read_u64(&buf[offset..])
+        } else {
+            BigEndian::read_u64(&buf[offset..])
+        }
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        if self.le {
+            self.reader.read_u32::<LittleEndian>()
+        } else {
+            self.reader.read_u32::<BigEndian>()
+        }
+    }
+
+    /// Reads the rest of a block whose type has already been consumed.
+    ///
+    /// Returns the block body without the leading and trailing lengths.
+    fn read_body(&mut self, total_len: u32) -> io::Result<Vec<u8>> {
+        if total_len < 12 || total_len & 3 != 0 || total_len > MAX_BLOCK_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong block length"));
+        }
+        let mut body = vec![0; total_len as usize - 12];
+        self.reader.read_exact(&mut body)?;
+        if self.read_u32()? != total_len {
+            return Err(Error::new(ErrorKind::InvalidData, "block length mismatch"));
+        }
+        Ok(body)
+    }
+
+    /// Reads a Section Header Block and starts a new section.
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        self.le = match self.reader.read_u32::<BigEndian>()? {
+            BYTE_ORDER_MAGIC => false,
+            0x4d3c_2b1a => true,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "wrong byte-order magic")),
+        };
+
+        let total_len = self.u32_at(&len, 0);
+        if total_len < 28 || total_len > MAX_BLOCK_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong block length"));
+        }
+        let mut body = vec![0; total_len as usize - 16];
+        self.reader.read_exact(&mut body)?;
+        if self.read_u32()? != total_len {
+            return Err(Error::new(ErrorKind::InvalidData, "block length mismatch"));
+        }
+
+        let ver_major = self.u16_at(&body, 0);
+        if ver_major != 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported version"));
+        }
+
+        self.interfaces.clear();
+        self.names.clear();
+        Ok(())
+    }
+
+    fn read_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong block length"));
+        }
+        let link = self.u16_at(body, 0);
+        let snaplen = self.u32_at(body, 4);
+        let id = self.interfaces.len() as u64;
+
+        let mut builder = LayerClass::builder(format!("[link-{}]", link))
+            .header(attr!(&TYPE_CLASS, value: i64::from(link)))
+            .header(attr!(&INTERFACE_CLASS, value: id));
+        let mut units_per_sec = 1_000_000;
+        let mut tsoffset = 0;
+
+        for (code, value) in self.options(&body[8..]) {
+            match code {
+                IF_NAME => {
+                    let name = String::from_utf8_lossy(value).into_owned();
+                    builder = builder.header(attr!(&IF_NAME_CLASS, value: name.into_boxed_str()));
+                }
+                IF_DESCRIPTION => {
+                    let desc = String::from_utf8_lossy(value).into_owned();
+                    builder =
+                        builder.header(attr!(&IF_DESCRIPTION_CLASS, value: desc.into_boxed_str()));
+                }
+                IF_TSRESOL if !value.is_empty() => {
+                    let exp = u32::from(value[0] & 0x7f);
+                    units_per_sec = if value[0] & 0x80 == 0 {
+                        10u64.checked_pow(exp)
+                    } else {
+                        2u64.checked_pow(exp)
+                    }
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "wrong if_tsresol"))?;
+                }
+                IF_TSOFFSET if value.len() >= 8 => {
+                    tsoffset = self.u64_at(value, 0) as i64;
+                }
+                _ => {}
+            }
+        }
+
+        self.interfaces.push(Interface {
+            link_class: Fixed::new(builder.build()),
+            snaplen,
+            units_per_sec,
+            tsoffset,
+        });
+        Ok(())
+    }
+
+    /// Reads the records of a Name Resolution Block.
+    ///
+    /// The records are attached to the next packet of the section.
+    fn read_name_resolution(&mut self, body: &[u8]) {
+        // Records share the layout of options and end with nrb_record_end.
+        for (typ, value) in self.options(body) {
+            let (class, len) = match typ {
+                NRB_RECORD_IPV4 => (&*NRB_IPV4_CLASS, 4),
+                NRB_RECORD_IPV6 => (&*NRB_IPV6_CLASS, 16),
+                _ => continue,
+            };
+            if value.len() <= len {
+                continue;
+            }
+            let names = value[len..]
+                .split(|&b| b == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .collect();
+            self.names.push(NameRecord {
+                class,
+                address: value[..len].to_vec(),
+                names,
+            });
+        }
+    }
+
+    fn add_names(&mut self, layer: &mut Layer) {
+        for record in self.names.drain(..) {
+            layer.add_attr(attr!(record.class, value: record.address.into_boxed_slice()));
+            for name in record.names {
+                layer.add_attr(attr!(&NRB_NAME_CLASS, value: name.into_boxed_str()));
+            }
+        }
+    }
+
+    fn options<'a>(&self, mut buf: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+        let mut options = Vec::new();
+        while buf.len() >= 4 {
+            let code = self.u16_at(buf, 0);
+            let len = self.u16_at(buf, 2) as usize;
+            if code == OPT_ENDOFOPT || buf.len() < 4 + len {
+                break;
+            }
+            options.push((code, &buf[4..4 + len]));
+            let padded = (len + 3) & !3;
+            buf = &buf[(4 + padded).min(buf.len())..];
+        }
+        options
+    }
+
+    fn interface(&self, id: u32) -> io::Result<&Interface> {
+        self.interfaces
+            .get(id as usize)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "unknown interface"))
+    }
+
+    fn read_enhanced_packet(&self, body: &[u8]) -> io::Result<Layer> {
+        if body.len() < 20 {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong block length"));
+        }
+        let iface = self.interface(self.u32_at(body, 0))?;
+        let ts = u64::from(self.u32_at(body, 4)) << 32 | u64::from(self.u32_at(body, 8));
+        let inc_len = self.u32_at(body, 12) as usize;
+        let orig_len = self.u32_at(body, 16);
+        if body.len() < 20 + inc_len {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong captured length"));
+        }
+
+        let data = body[20..20 + inc_len].to_vec();
+        let mut layer = Layer::new(iface.link_class.clone(), ByteSlice::from(data));
+        let (ts_sec, ts_nsec) = iface.timestamp(ts);
+        add_link_attrs(&mut layer, orig_len, ts_sec, ts_nsec);
+
+        let padded = (inc_len + 3) & !3;
+        let options = body.get(20 + padded..).unwrap_or(&[]);
+        for (code, value) in self.options(options) {
+            if code == OPT_COMMENT {
+                let comment = String::from_utf8_lossy(value).into_owned();
+                layer.add_attr(attr!(&COMMENT_CLASS, value: comment.into_boxed_str()));
+            }
+        }
+        Ok(layer)
+    }
+
+    fn read_simple_packet(&self, body: &[u8]) -> io::Result<Layer> {
+        if body.len() < 4 {
+            return Err(Error::new(ErrorKind::InvalidData, "wrong block length"));
+        }
+        let iface = self.interface(0)?;
+        let orig_len = self.u32_at(body, 0);
+        let mut inc_len = (body.len() - 4).min(orig_len as usize);
+        if iface.snaplen > 0 {
+            inc_len = inc_len.min(iface.snaplen as usize);
+        }
+
+        let data = body[4..4 + inc_len].to_vec();
+        let mut layer = Layer::new(iface.link_class.clone(), ByteSlice::from(data));
+        add_link_attrs(&mut layer, orig_len, 0, 0);
+        Ok(layer)
+    }
+
+    fn read_one(&mut self) -> io::Result<Option<Layer>> {
+        loop {
+            if self.reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            let block_type = self.read_u32()?;
+            if block_type == SECTION_HEADER_BLOCK {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let total_len = self.read_u32()?;
+            let body = self.read_body(total_len)?;
+            let mut layer = match block_type {
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    self.read_interface(&body)?;
+                    continue;
+                }
+                NAME_RESOLUTION_BLOCK => {
+                    self.read_name_resolution(&body);
+                    continue;
+                }
+                ENHANCED_PACKET_BLOCK => self.read_enhanced_packet(&body)?,
+                SIMPLE_PACKET_BLOCK => self.read_simple_packet(&body)?,
+                _ => continue,
+            };
+            self.add_names(&mut layer);
+            return Ok(Some(layer));
+        }
+    }
+}
+
+fn add_link_attrs(layer: &mut Layer, orig_len: u32, ts_sec: u64, ts_nsec: u64) {
+    layer.add_attr(attr!(&LENGTH_CLASS, value: u64::from(orig_len)));
+    layer.add_attr(attr!(
+        &TS_CLASS,
+        value: ts_sec as f64 + ts_nsec as f64 / 1_000_000_000f64
+    ));
+    layer.add_attr(attr!(&TS_SEC_CLASS, value: ts_sec));
+    layer.add_attr(attr!(&TS_USEC_CLASS, value: ts_nsec));
+}
+
+const BLOCK_SIZE: usize = 65535;
+
+impl<R: 'static + BufRead + Send> Worker for PcapngFileWorker<R> {
+    fn read(&mut self) -> Result<Vec<Layer>> {
+        let mut layers = Vec::with_capacity(BLOCK_SIZE);
+        for _ in 0..BLOCK_SIZE {
+            match self.read_one() {
+                Ok(Some(layer)) => layers.push(layer),
+                Ok(None) => break,
+                Err(err) => {
+                    if layers.is_empty() {
+                        return Err(err.into());
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(layers)
+    }
+}
+
+def_attr_class!(TYPE_CLASS, "link.type");
+def_attr_class!(INTERFACE_CLASS, "link.interface");
+def_attr_class!(IF_NAME_CLASS, "link.interface.name");
+def_attr_class!(IF_DESCRIPTION_CLASS, "link.interface.description");
+def_attr_class!(LENGTH_CLASS, "link.length");
+def_attr_class!(COMMENT_CLASS, "link.comment");
+def_attr_class!(TS_CLASS, "link.timestamp",
+    typ: "@datetime:unix"
+);
+def_attr_class!(TS_SEC_CLASS, "link.timestamp.sec");
+def_attr_class!(TS_USEC_CLASS, "link.timestamp.usec");
+def_attr_class!(NRB_IPV4_CLASS, "link.nameResolution.ipv4",
+    typ: "@ipv4:addr"
+);
+def_attr_class!(NRB_IPV6_CLASS, "link.nameResolution.ipv6",
+    typ: "@ipv6:addr"
+);
+def_attr_class!(NRB_NAME_CLASS, "link.nameResolution.name");
+
+genet_readers!(PcapngFileReader {});
+
+#[cfg(test)]
+mod tests {
+    use super::PcapngFileWorker;
+    use genet_sdk::{prelude::*, reader::Worker, variant::Variant};
+    use std::io::Cursor;
+
+    fn block(typ: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        while body.len() % 4 != 0 {
+            body.push(0);
+        }
+        let len = body.len() as u32 + 12;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&typ.to_le_bytes());
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&body);
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf
+    }
+
+    fn option(code: u16, value: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&code.to_le_bytes());
+        buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        buf.extend_from_slice(value);
+        while buf.len() % 4 != 0 {
+            buf.push(0);
+        }
+        buf
+    }
+
+    fn section() -> Vec<u8> {
+        let mut body = vec![0x4d, 0x3c, 0x2b, 0x1a, 0x01, 0x00, 0x00, 0x00];
+        body.extend_from_slice(&[0xff; 8]);
+        block(0x0a0d_0d0a, &body)
+    }
+
+    fn interface(options: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        body.extend_from_slice(options);
+        block(1, &body)
+    }
+
+    fn packet(ts: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        block(6, &body)
+    }
+
+    fn read(blocks: &[Vec<u8>]) -> Result<Vec<Layer>> {
+        let data = blocks.concat();
+        let mut worker = PcapngFileWorker::new(Cursor::new(data))?;
+        worker.read()
+    }
+
+    fn values(layer: &Layer, id: &str) -> Vec<Variant> {
+        let id = Token::from(id);
+        layer
+            .attrs()
+            .iter()
+            .filter(|attr| attr.id() == id)
+            .map(|attr| attr.try_get(layer).unwrap())
+            .collect()
+    }
+
+    fn timestamp(layer: &Layer) -> (Variant, Variant) {
+        (
+            values(layer, "link.timestamp.sec").remove(0),
+            values(layer, "link.timestamp.usec").remove(0),
+        )
+    }
+
+    #[test]
+    fn timestamp_resolution() {
+        let layers = read(&[section(), interface(&[]), packet(1_500_000_123_456, b"a")]).unwrap();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(&layers[0].data()[..], b"a");
+        assert_eq!(
+            timestamp(&layers[0]),
+            (Variant::UInt64(1_500_000), Variant::UInt64(123_456_000))
+        );
+
+        let nsec = interface(&option(9, &[9]));
+        let layers = read(&[section(), nsec, packet(1_500_000_000_123_456_789, b"a")]).unwrap();
+        assert_eq!(
+            timestamp(&layers[0]),
+            (Variant::UInt64(1_500_000_000), Variant::UInt64(123_456_789))
+        );
+
+        let binary = interface(&option(9, &[0x8a]));
+        let layers = read(&[section(), binary, packet(3 * 1024 + 512, b"a")]).unwrap();
+        assert_eq!(
+            timestamp(&layers[0]),
+            (Variant::UInt64(3), Variant::UInt64(500_000_000))
+        );
+
+        let mut options = option(9, &[0]);
+        options.extend_from_slice(&option(14, &100u64.to_le_bytes()));
+        let layers = read(&[section(), interface(&options), packet(5, b"a")]).unwrap();
+        assert_eq!(
+            timestamp(&layers[0]),
+            (Variant::UInt64(105), Variant::UInt64(0))
+        );
+
+        let overflow = interface(&option(9, &[0x7f]));
+        assert!(read(&[section(), overflow, packet(0, b"a")]).is_err());
+    }
+
+    #[test]
+    fn name_resolution() {
+        let mut records = option(1, b"\x0a\x00\x00\x01host.example\x00alias\x00");
+        records.extend_from_slice(&option(
+            2,
+            b"\xfe\x80\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01host6\x00",
+        ));
+        records.extend_from_slice(&option(0, &[]));
+        let layers = read(&[
+            section(),
+            interface(&[]),
+            block(4, &records),
+            packet(0, b"a"),
+            packet(0, b"b"),
+        ])
+        .unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            values(&layers[0], "link.nameResolution.ipv4"),
+            vec![Variant::Buffer(vec![10, 0, 0, 1].into_boxed_slice())]
+        );
+        assert_eq!(values(&layers[0], "link.nameResolution.ipv6").len(), 1);
+        assert_eq!(
+            values(&layers[0], "link.nameResolution.name"),
+            vec![
+                Variant::String("host.example".to_string().into_boxed_str()),
+                Variant::String("alias".to_string().into_boxed_str()),
+                Variant::String("host6".to_string().into_boxed_str()),
+            ]
+        );
+        assert!(values(&layers[1], "link.nameResolution.name").is_empty());
+    }
+
+    #[test]
+    fn block_length() {
+        let valid = packet(0, b"abcd");
+        assert_eq!(
+            read(&[section(), interface(&[]), valid.clone()])
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // The total length must be a multiple of 4.
+        let mut unaligned = valid.clone();
+        unaligned[4] += 1;
+        assert!(read(&[section(), interface(&[]), unaligned]).is_err());
+
+        // The total length must cover the type and both lengths.
+        let short = [6, 0, 0, 0, 8, 0, 0, 0, 8, 0, 0, 0].to_vec();
+        assert!(read(&[section(), interface(&[]), short]).is_err());
+
+        // The trailing length must match the leading one.
+        let mut mismatch = valid.clone();
+        let len = mismatch.len();
+        mismatch[len - 4] += 4;
+        assert!(read(&[section(), interface(&[]), mismatch]).is_err());
+
+        // Corrupt lengths are rejected before allocating the block.
+        let huge = [6, 0, 0, 0, 0, 0, 0, 0x40].to_vec();
+        assert!(read(&[section(), interface(&[]), huge]).is_err());
+    }
+
+    #[test]
+    fn end_of_file() {
+        assert!(read(&[section()]).unwrap().is_empty());
+        assert!(read(&[section(), interface(&[])]).unwrap().is_empty());
+
+        let mut truncated = packet(0, b"abcd");
+        truncated.truncate(10);
+        assert!(read(&[section(), interface(&[]), truncated]).is_err());
+    }
+}
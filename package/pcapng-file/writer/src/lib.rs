@@ -0,0 +1,168 @@
+extern crate byteorder;
+extern crate genet_sdk;
+extern crate serde;
+extern crate serde_json;
+
+#[macro_use]
+extern crate serde_derive;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use genet_sdk::{prelude::*, writer::*};
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_TSRESOL: u16 = 9;
+
+#[derive(Deserialize)]
+struct Arg {
+    file: String,
+}
+
+#[derive(Clone)]
+struct PcapngFileWriter {}
+
+impl Writer for PcapngFileWriter {
+    fn new_worker(&self, _ctx: &Context, arg: &str) -> Result<Box<Worker>> {
+        let arg: Arg = serde_json::from_str(arg)?;
+        let file = File::create(&arg.file)?;
+        let mut worker = PcapngFileWorker {
+            writer: BufWriter::new(file),
+            interfaces: HashMap::new(),
+        };
+        worker.write_section_header()?;
+        Ok(Box::new(worker))
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            id: "app.genet.writer.pcapng-file".into(),
+            filters: vec![FileType::new("Pcapng File", &["pcapng"])],
+            ..Metadata::default()
+        }
+    }
+}
+
+struct PcapngFileWorker {
+    writer: BufWriter<File>,
+    interfaces: HashMap<u16, u32>,
+}
+
+impl PcapngFileWorker {
+    fn write_block(&mut self, typ: u32, body: &[u8]) -> Result<()> {
+        let padding = ((body.len() + 3) & !3) - body.len();
+        let total_len = (body.len() + padding + 12) as u32;
+        self.writer.write_u32::<LittleEndian>(typ)?;
+        self.writer.write_u32::<LittleEndian>(total_len)?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&[0; 3][..padding])?;
+        self.writer.write_u32::<LittleEndian>(total_len)?;
+        Ok(())
+    }
+
+    fn write_section_header(&mut self) -> Result<()> {
+        let mut body = Vec::new();
+        body.write_u32::<LittleEndian>(BYTE_ORDER_MAGIC)?;
+        body.write_u16::<LittleEndian>(1)?;
+        body.write_u16::<LittleEndian>(0)?;
+        body.write_i64::<LittleEndian>(-1)?;
+        self.write_block(SECTION_HEADER_BLOCK, &body)
+    }
+
+    /// Returns the interface ID for the link type,
+    /// emitting a new Interface Description Block on its first use.
+    fn interface(&mut self, link: u16) -> Result<u32> {
+        if let Some(id) = self.interfaces.get(&link) {
+            return Ok(*id);
+        }
+
+        let mut body = Vec::new();
+        body.write_u16::<LittleEndian>(link)?;
+        body.write_u16::<LittleEndian>(0)?;
+        body.write_u32::<LittleEndian>(0)?;
+        write_option(&mut body, IF_TSRESOL, &[9])?;
+        write_option(&mut body, OPT_ENDOFOPT, &[])?;
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, &body)?;
+
+        let id = self.interfaces.len() as u32;
+        self.interfaces.insert(link, id);
+        Ok(id)
+    }
+}
+
+fn write_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) -> Result<()> {
+    let padding = ((value.len() + 3) & !3) - value.len();
+    buf.write_u16::<LittleEndian>(code)?;
+    buf.write_u16::<LittleEndian>(value.len() as u16)?;
+    buf.write_all(value)?;
+    buf.write_all(&[0; 3][..padding])?;
+    Ok(())
+}
+
+impl Worker for PcapngFileWorker {
+    fn write(&mut self, _index: u32, stack: &LayerStack) -> Result<()> {
+        if let Some(layer) = stack.bottom() {
+            let data = layer.data();
+            let mut orig_len = data.len() as u64;
+            let mut ts_sec: u64 = 0;
+            let mut ts_nsec: u64 = 0;
+            let mut link = 0;
+            let mut comment: Option<String> = None;
+
+            if let Some(attr) = layer.attr(token!("link.length")) {
+                orig_len = attr.try_get(layer)?.try_into()?;
+            }
+            if let Some(attr) = layer.attr(token!("link.type")) {
+                link = attr.try_get(layer)?.try_into()?;
+            }
+            if let Some(attr) = layer.attr(token!("link.timestamp.sec")) {
+                ts_sec = attr.try_get(layer)?.try_into()?;
+            }
+            if let Some(attr) = layer.attr(token!("link.timestamp.usec")) {
+                ts_nsec = attr.try_get(layer)?.try_into()?;
+            }
+            if let Some(attr) = layer.attr(token!("link.comment")) {
+                comment = Some(attr.try_get(layer)?.try_into()?);
+            }
+
+            let id = self.interface(link)?;
+            let ts = ts_sec * 1_000_000_000 + ts_nsec;
+
+            let mut body = Vec::with_capacity(data.len() + 32);
+            body.write_u32::<LittleEndian>(id)?;
+            body.write_u32::<LittleEndian>((ts >> 32) as u32)?;
+            body.write_u32::<LittleEndian>(ts as u32)?;
+            body.write_u32::<LittleEndian>(data.len() as u32)?;
+            body.write_u32::<LittleEndian>(orig_len as u32)?;
+            body.write_all(&data)?;
+            if let Some(comment) = comment {
+                let padding = ((data.len() + 3) & !3) - data.len();
+                body.write_all(&[0; 3][..padding])?;
+                let comment = comment.as_bytes();
+                let len = comment.len().min(u16::max_value() as usize);
+                write_option(&mut body, OPT_COMMENT, &comment[..len])?;
+                write_option(&mut body, OPT_ENDOFOPT, &[])?;
+            }
+            self.write_block(ENHANCED_PACKET_BLOCK, &body)?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+genet_writers!(PcapngFileWriter {});
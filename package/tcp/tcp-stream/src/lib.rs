@@ -3,67 +3,251 @@ extern crate genet_sdk;
 use genet_sdk::{cast, decoder::*, prelude::*};
 use std::collections::{BTreeMap, HashMap};
 
+/// The number of bytes buffered after a gap until the gap is skipped.
+///
+/// A missing segment is unlikely to be retransmitted once this much later data has arrived.
+const MAX_WINDOW: usize = 1024 * 1024;
+
+/// The data which follows the fetched bytes of a stream.
+#[derive(Debug, PartialEq)]
+enum Chunk {
+    /// Data of the current segment.
+    Slice(ByteSlice),
+    /// Data copied from a previous segment.
+    Buffer(Vec<u8>),
+    /// The number of bytes which were never captured.
+    Gap(usize),
+}
+
 #[derive(Debug)]
 struct Stream {
-    pub id: u64,
     pub seq: i64,
-    pub len: usize,
     offset: usize,
-    slices: BTreeMap<usize, ByteSlice>,
+    size: usize,
+    serial: u64,
+    timestamp: u64,
+    slices: BTreeMap<usize, Vec<u8>>,
 }
 
 impl Stream {
-    fn new(id: u64) -> Stream {
-        return Stream {
-            id: id,
+    fn new(serial: u64, timestamp: u64) -> Stream {
+        Stream {
             seq: -1,
-            len: 0,
             offset: 0,
+            size: 0,
+            serial,
+            timestamp,
             slices: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the segment at `start` and returns the data which can be fetched.
+    fn put(&mut self, start: usize, data: ByteSlice) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        let end = start + data.len();
+        if end <= self.offset {
+            return chunks;
+        }
+        let (start, data) = if start < self.offset {
+            (
+                self.offset,
+                data.try_get(self.offset - start..).unwrap_or_default(),
+            )
+        } else {
+            (start, data)
         };
+        if start == self.offset {
+            self.offset = end;
+            chunks.push(Chunk::Slice(data));
+            self.fetch(&mut chunks);
+            return chunks;
+        }
+
+        let longer = self
+            .slices
+            .get(&start)
+            .map(|slice| slice.len() < data.len())
+            .unwrap_or(true);
+        if longer {
+            // Data which is not fetched right away is copied,
+            // since the frame it belongs to may be freed first.
+            let old = self
+                .slices
+                .insert(start, data.to_vec())
+                .map(|slice| slice.len())
+                .unwrap_or(0);
+            self.size = self.size + data.len() - old;
+        }
+        if self.size > MAX_WINDOW {
+            self.skip(&mut chunks);
+        }
+        chunks
     }
 
-    fn put(&mut self, start: usize, data: ByteSlice) {
-        if data.len() > 0 {
-            self.slices.insert(start, data);
-            let mut end = self.offset;
-            for (start, slice) in self.slices.iter_mut() {
-                if *start < end {}
-                end = *start + slice.len();
-            }
+    /// Returns all the buffered data, skipping the gaps between them.
+    fn flush(&mut self) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        while !self.slices.is_empty() {
+            self.skip(&mut chunks);
         }
+        chunks
     }
 
-    fn fetch(&mut self) -> impl Iterator<Item = ByteSlice> {
-        let mut slices = Vec::new();
-        loop {
-            let pos;
-            if let Some((key, value)) = self.slices.iter().next() {
-                pos = *key;
-                if *key == self.offset {
-                    self.offset += value.len();
-                    slices.push(value.clone());
-                } else {
-                    break;
-                }
-            } else {
+    /// Moves the buffered data which no longer follows a gap to `chunks`.
+    fn fetch(&mut self, chunks: &mut Vec<Chunk>) {
+        while let Some(start) = self.slices.keys().next().cloned() {
+            if start > self.offset {
                 break;
             }
-            self.slices.remove(&pos);
+            if let Some(mut slice) = self.slices.remove(&start) {
+                self.size -= slice.len();
+                // Skip the bytes which have already been fetched.
+                if start + slice.len() > self.offset {
+                    slice.drain(..self.offset - start);
+                    self.offset += slice.len();
+                    chunks.push(Chunk::Buffer(slice));
+                }
+            }
+        }
+    }
+
+    /// Skips the gap before the first buffered data.
+    fn skip(&mut self, chunks: &mut Vec<Chunk>) {
+        if let Some(start) = self.slices.keys().next().cloned() {
+            if start > self.offset {
+                chunks.push(Chunk::Gap(start - self.offset));
+                self.offset = start;
+            }
+            self.fetch(chunks);
         }
-        slices.into_iter()
     }
 }
 
+type Key = (Vec<u8>, Vec<u8>, u32, u32);
+
 struct TcpStreamWorker {
-    map: HashMap<(ByteSlice, ByteSlice, u32, u32), Stream>,
+    map: HashMap<Key, Stream>,
+    /// The keys of the streams ordered by their last activity.
+    order: BTreeMap<(u64, u64), Key>,
+    serial: u64,
+    size: usize,
+    timeout: u64,
+    memory_limit: usize,
 }
 
 impl TcpStreamWorker {
-    fn new() -> TcpStreamWorker {
+    fn new(ctx: &Context) -> TcpStreamWorker {
+        let timeout = ctx
+            .get_config("@genet/tcp.stream.timeout")
+            .parse()
+            .unwrap_or(60);
+        let memory_limit: usize = ctx
+            .get_config("@genet/tcp.stream.memoryLimit")
+            .parse()
+            .unwrap_or(16);
+        Self::with_limits(timeout, memory_limit * 1024 * 1024)
+    }
+
+    fn with_limits(timeout: u64, memory_limit: usize) -> TcpStreamWorker {
         TcpStreamWorker {
             map: HashMap::new(),
+            order: BTreeMap::new(),
+            serial: 0,
+            size: 0,
+            timeout,
+            memory_limit,
+        }
+    }
+
+    /// Adds a segment to the stream of `key` and returns the data which can be fetched.
+    fn put(
+        &mut self,
+        key: Key,
+        seq: u32,
+        flags: u8,
+        data: ByteSlice,
+        timestamp: u64,
+    ) -> Vec<Chunk> {
+        self.expire(timestamp);
+
+        let serial = self.serial;
+        self.serial += 1;
+        let (before, after, mut chunks) = {
+            let stream = self
+                .map
+                .entry(key.clone())
+                .or_insert_with(|| Stream::new(serial, timestamp));
+            self.order.remove(&(stream.timestamp, stream.serial));
+            self.order.insert((timestamp, serial), key.clone());
+            stream.serial = serial;
+            stream.timestamp = timestamp;
+
+            let before = stream.size;
+            let syn = (flags & (0x1 << 1)) != 0;
+            let mut chunks = Vec::new();
+            if syn {
+                if stream.seq < 0 {
+                    // The first byte of the stream follows the SYN.
+                    stream.seq = i64::from(seq.wrapping_add(1));
+                }
+            } else if !data.is_empty() {
+                if stream.seq < 0 {
+                    // The SYN was not captured, so the stream starts with this segment.
+                    stream.seq = i64::from(seq);
+                }
+                // Segments before the start of the stream have a wrapped offset.
+                let offset = seq.wrapping_sub(stream.seq as u32);
+                if offset <= std::i32::MAX as u32 {
+                    chunks = stream.put(offset as usize, data);
+                }
+            }
+            (before, stream.size, chunks)
+        };
+        self.size = self.size - before + after;
+
+        let fin = (flags & 0x1) != 0;
+        let rst = (flags & (0x1 << 2)) != 0;
+        if fin || rst {
+            if let Some(mut stream) = self.remove(&key) {
+                chunks.extend(stream.flush());
+            }
+        }
+        chunks
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Stream> {
+        let stream = self.map.remove(key)?;
+        self.order.remove(&(stream.timestamp, stream.serial));
+        self.size -= stream.size;
+        Some(stream)
+    }
+
+    /// Drops the streams which have been idle longer than the timeout.
+    fn expire(&mut self, timestamp: u64) {
+        if self.timeout == 0 {
+            return;
+        }
+        loop {
+            let key = match self.order.iter().next() {
+                Some((&(last, _), key)) if last + self.timeout < timestamp => key.clone(),
+                _ => break,
+            };
+            self.remove(&key);
+        }
+    }
+
+    /// Drops the least recently used streams until the buffered bytes fit in the limit.
+    fn shrink(&mut self) -> bool {
+        let mut dropped = false;
+        while self.memory_limit > 0 && self.size > self.memory_limit {
+            let key = match self.order.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&key);
+            dropped = true;
         }
+        dropped
     }
 }
 
@@ -83,16 +267,18 @@ impl Worker for TcpStreamWorker {
                 .data();
 
             let stream_id = {
-                let parent_src: ByteSlice = stack
-                    .attr(token!("_.src"))
-                    .unwrap()
-                    .try_get(parent)?
-                    .try_into()?;
-                let parent_dst: ByteSlice = stack
-                    .attr(token!("_.dst"))
-                    .unwrap()
-                    .try_get(parent)?
-                    .try_into()?;
+                let parent_src: ByteSlice =
+                    if let Some((attr, layer)) = stack.attr_layer(token!("_.src")) {
+                        attr.try_get(layer)?.try_into()?
+                    } else {
+                        return Ok(Status::Skip);
+                    };
+                let parent_dst: ByteSlice =
+                    if let Some((attr, layer)) = stack.attr_layer(token!("_.dst")) {
+                        attr.try_get(layer)?.try_into()?
+                    } else {
+                        return Ok(Status::Skip);
+                    };
                 let src: u32 = parent
                     .attr(token!("tcp.src"))
                     .unwrap()
@@ -103,61 +289,49 @@ impl Worker for TcpStreamWorker {
                     .unwrap()
                     .try_get(parent)?
                     .try_into()?;
-                (parent_src, parent_dst, src, dst)
+                (parent_src.to_vec(), parent_dst.to_vec(), src, dst)
             };
 
-            let id = self.map.len();
-            let stream = self
-                .map
-                .entry(stream_id)
-                .or_insert_with(|| Stream::new(id as u64));
-
             let seq: u32 = parent
                 .attr(token!("tcp.seq"))
                 .unwrap()
                 .try_get(parent)?
                 .try_into()?;
-            let window: u16 = parent
-                .attr(token!("tcp.window"))
-                .unwrap()
-                .try_get(parent)?
-                .try_into()?;
             let flags: u8 = parent
                 .attr(token!("tcp.flags"))
                 .unwrap()
                 .try_get(parent)?
                 .try_into()?;
+            let timestamp = stack
+                .bottom()
+                .and_then(|root| {
+                    root.attr(token!("link.timestamp.sec"))
+                        .and_then(|attr| attr.try_get(root).ok())
+                })
+                .and_then(|value| value.try_into().ok())
+                .unwrap_or(0);
 
-            let syn = (flags & (0x1 << 1)) != 0;
-            if syn {
-                if stream.seq < 0 {
-                    let offset = stream.len;
-                    stream.seq = seq as i64;
-                    stream.len += slice.len();
-                    stream.put(offset, slice);
-                }
-            } else if stream.seq >= 0 {
-                if slice.len() > 0 {
-                    if seq >= stream.seq as u32 {
-                        let offset = stream.len + (seq - stream.seq as u32) as usize;
-                        stream.seq = seq as i64;
-                        stream.len += slice.len();
-                        stream.put(offset, slice);
-                    } else if stream.seq - seq as i64 > window as i64 {
-                        let offset = stream.len
-                            + ((std::u32::MAX as u32 - stream.seq as u32) + seq) as usize;
-                        stream.seq = seq as i64;
-                        stream.len += slice.len();
-                        stream.put(offset, slice);
+            for chunk in self.put(stream_id, seq, flags, slice, timestamp) {
+                let data = match chunk {
+                    Chunk::Slice(data) => data,
+                    Chunk::Buffer(data) => parent.add_buffer(data),
+                    Chunk::Gap(_) => {
+                        parent.add_expert(Expert::new(
+                            Severity::Warning,
+                            "reassembly",
+                            "missing segments were skipped",
+                        ));
+                        continue;
                     }
-                } else if (stream.seq + 1) % std::u32::MAX as i64 == seq as i64 {
-                    stream.seq = seq as i64;
-                }
+                };
+                parent.add_payload(Payload::new(data, "@stream:tcp"));
             }
-
-            let payloads = stream.fetch();
-            for payload in payloads {
-                parent.add_payload(Payload::new(payload, "@stream:tcp"));
+            if self.shrink() {
+                parent.add_expert(Expert::new(
+                    Severity::Warning,
+                    "reassembly",
+                    "reassembly buffer is full; buffered segments were dropped",
+                ));
             }
 
             parent.add_attr(attr!(&STREAM_ATTR));
@@ -172,12 +346,13 @@ impl Worker for TcpStreamWorker {
 struct TcpStreamDecoder {}
 
 impl Decoder for TcpStreamDecoder {
-    fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
-        Box::new(TcpStreamWorker::new())
+    fn new_worker(&self, ctx: &Context) -> Box<Worker> {
+        Box::new(TcpStreamWorker::new(ctx))
     }
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.tcp-stream".into(),
             exec_type: ExecType::SerialSync,
             ..Metadata::default()
         }
@@ -190,3 +365,176 @@ def_attr_class!(STREAM_ATTR, "tcp.stream",
 );
 
 genet_decoders!(TcpStreamDecoder {});
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, Stream, TcpStreamWorker, MAX_WINDOW};
+    use genet_sdk::slice::ByteSlice;
+
+    const SYN: u8 = 0x02;
+    const ACK: u8 = 0x10;
+    const FIN: u8 = 0x01;
+    const RST: u8 = 0x04;
+
+    fn data(data: &'static [u8]) -> ByteSlice {
+        ByteSlice::from(data)
+    }
+
+    fn bytes(chunks: &[Chunk]) -> Vec<u8> {
+        chunks
+            .iter()
+            .flat_map(|chunk| match chunk {
+                Chunk::Slice(data) => data.to_vec(),
+                Chunk::Buffer(data) => data.clone(),
+                Chunk::Gap(_) => Vec::new(),
+            })
+            .collect()
+    }
+
+    fn key() -> (Vec<u8>, Vec<u8>, u32, u32) {
+        (vec![1], vec![2], 1000, 80)
+    }
+
+    #[test]
+    fn in_order() {
+        let mut stream = Stream::new(0, 0);
+        assert_eq!(
+            stream.put(0, data(b"abc")),
+            vec![Chunk::Slice(data(b"abc"))]
+        );
+        assert_eq!(
+            stream.put(3, data(b"def")),
+            vec![Chunk::Slice(data(b"def"))]
+        );
+        assert_eq!(stream.offset, 6);
+        assert_eq!(stream.size, 0);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut stream = Stream::new(0, 0);
+        assert!(stream.put(6, data(b"ghi")).is_empty());
+        assert!(stream.put(3, data(b"def")).is_empty());
+        assert_eq!(stream.size, 6);
+        let chunks = stream.put(0, data(b"abc"));
+        assert_eq!(chunks[0], Chunk::Slice(data(b"abc")));
+        assert_eq!(bytes(&chunks), b"abcdefghi");
+        assert_eq!(stream.size, 0);
+    }
+
+    #[test]
+    fn retransmission() {
+        let mut stream = Stream::new(0, 0);
+        stream.put(0, data(b"abc"));
+        assert!(stream.put(0, data(b"abc")).is_empty());
+        assert_eq!(
+            stream.put(1, data(b"bcde")),
+            vec![Chunk::Slice(data(b"de"))]
+        );
+        assert!(stream.put(8, data(b"i")).is_empty());
+        assert!(stream.put(8, data(b"ij")).is_empty());
+        assert_eq!(stream.size, 2);
+        assert_eq!(bytes(&stream.put(5, data(b"fgh"))), b"fghij");
+    }
+
+    #[test]
+    fn gap() {
+        let mut stream = Stream::new(0, 0);
+        stream.put(0, data(b"abc"));
+        let large = vec![0u8; MAX_WINDOW];
+        let large = unsafe { ByteSlice::from_raw_parts(large.as_ptr(), large.len()) };
+        assert!(stream.put(10, data(b"x")).is_empty());
+        let chunks = stream.put(11, large);
+        assert_eq!(chunks[0], Chunk::Gap(7));
+        assert_eq!(bytes(&chunks).len(), MAX_WINDOW + 1);
+        assert_eq!(stream.offset, MAX_WINDOW + 11);
+        assert_eq!(stream.size, 0);
+
+        let mut stream = Stream::new(0, 0);
+        stream.put(4, data(b"e"));
+        stream.put(8, data(b"i"));
+        let chunks = stream.flush();
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk::Gap(4),
+                Chunk::Buffer(b"e".to_vec()),
+                Chunk::Gap(3),
+                Chunk::Buffer(b"i".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn handshake() {
+        let mut worker = TcpStreamWorker::with_limits(60, 0);
+        assert!(worker.put(key(), 99, SYN, data(b""), 0).is_empty());
+        assert!(worker.put(key(), 103, ACK, data(b"def"), 0).is_empty());
+        assert_eq!(
+            bytes(&worker.put(key(), 100, ACK, data(b"abc"), 0)),
+            b"abcdef"
+        );
+
+        // Segments before the start of the stream are ignored.
+        assert!(worker.put(key(), 90, ACK, data(b"abc"), 0).is_empty());
+    }
+
+    #[test]
+    fn missing_syn() {
+        let mut worker = TcpStreamWorker::with_limits(60, 0);
+        assert_eq!(bytes(&worker.put(key(), 500, ACK, data(b"abc"), 0)), b"abc");
+        assert_eq!(bytes(&worker.put(key(), 503, ACK, data(b"def"), 0)), b"def");
+    }
+
+    #[test]
+    fn close() {
+        let mut worker = TcpStreamWorker::with_limits(60, 0);
+        worker.put(key(), 99, SYN, data(b""), 0);
+        worker.put(key(), 103, ACK, data(b"def"), 0);
+        let chunks = worker.put(key(), 106, FIN | ACK, data(b""), 0);
+        assert_eq!(chunks, vec![Chunk::Gap(3), Chunk::Buffer(b"def".to_vec())]);
+        assert!(worker.map.is_empty());
+        assert!(worker.order.is_empty());
+        assert_eq!(worker.size, 0);
+
+        worker.put(key(), 99, SYN, data(b""), 0);
+        worker.put(key(), 100, RST, data(b""), 0);
+        assert!(worker.map.is_empty());
+    }
+
+    #[test]
+    fn expired() {
+        let mut worker = TcpStreamWorker::with_limits(60, 0);
+        worker.put(key(), 99, SYN, data(b""), 100);
+        worker.put(key(), 103, ACK, data(b"def"), 100);
+        let other = (vec![2], vec![1], 80, 1000);
+        worker.put(other.clone(), 10, SYN, data(b""), 150);
+        assert_eq!(worker.size, 3);
+
+        worker.put(other, 11, ACK, data(b"abc"), 161);
+        assert_eq!(worker.map.len(), 1);
+        assert_eq!(worker.size, 0);
+
+        // The expired stream starts again without a SYN.
+        assert_eq!(
+            bytes(&worker.put(key(), 106, ACK, data(b"ghi"), 162)),
+            b"ghi"
+        );
+    }
+
+    #[test]
+    fn over_budget() {
+        let mut worker = TcpStreamWorker::with_limits(60, 4);
+        worker.put(key(), 99, SYN, data(b""), 0);
+        worker.put(key(), 103, ACK, data(b"def"), 0);
+        assert!(!worker.shrink());
+
+        let other = (vec![2], vec![1], 80, 1000);
+        worker.put(other.clone(), 10, SYN, data(b""), 1);
+        worker.put(other.clone(), 14, ACK, data(b"efg"), 1);
+        assert!(worker.shrink());
+        assert_eq!(worker.map.len(), 1);
+        assert!(worker.map.contains_key(&other));
+        assert_eq!(worker.size, 3);
+    }
+}
@@ -81,6 +81,7 @@ impl Decoder for TcpDecoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.tcp".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
@@ -42,6 +42,7 @@ impl Decoder for UdpDecoder {
 
     fn metadata(&self) -> Metadata {
         Metadata {
+            id: "app.genet.decoder.udp".into(),
             exec_type: ExecType::ParallelSync,
             ..Metadata::default()
         }
@@ -35,6 +35,32 @@ export default {
       maximum: 8,
       default: 0,
     },
+    '_.decoder.memoryLimit': {
+      description: 'Memory budget for decoded layers in MiB (0 for unlimited)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
+    '_.decoder.disabled': {
+      description: 'Ids of decoders to disable',
+      type: 'array',
+      items: {
+        type: 'string',
+      },
+      default: [],
+    },
+    '_.decoder.ringFrames': {
+      description: 'Maximum number of frames to keep (0 for unlimited)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
+    '_.decoder.ringSeconds': {
+      description: 'Maximum time span of frames to keep in seconds (0 for unlimited)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
     '_.dev.tabReloading': {
       description: 'Touch $HOME/.genet/.reload to reload all tabs',
       type: 'boolean',
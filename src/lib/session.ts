@@ -93,12 +93,22 @@ export default class Session extends EventEmitter {
     return null
   }
 
-  async create() {
+  async createProfile() {
     const profile = new native.Session.Profile()
     profile.concurrency = genet.config.get('_.decoder.concurrency')
+    profile.memoryLimit =
+      genet.config.get('_.decoder.memoryLimit', 0) * 1024 * 1024
+    profile.ringFrames = genet.config.get('_.decoder.ringFrames', 0)
+    profile.ringSeconds = genet.config.get('_.decoder.ringSeconds', 0)
+    for (const id of genet.config.get('_.decoder.disabled', [])) {
+      profile.setDecoderEnabled(id, false)
+    }
     for (const [key, value] of Object.entries(this._config.toJSON())) {
       profile.setConfig(key, JSON.stringify(value))
     }
+    for (const id of this._tokens.keys()) {
+      profile.registerToken(id)
+    }
     for (const file of this._libs) {
       try {
         profile.loadLibrary(file)
@@ -106,6 +116,14 @@ export default class Session extends EventEmitter {
         this.emit('error', new Error(`Filed to load ${file}: ${err.message}`))
       }
     }
-    return new native.Session(profile, {})
+    return profile
+  }
+
+  async create() {
+    return new native.Session(await this.createProfile(), {})
+  }
+
+  async redecode(sess) {
+    sess.setProfile(await this.createProfile())
   }
 }
@@ -11,6 +11,12 @@ function selectRange(range: any = null) {
   genet.action.emit('core:frame:range-selected', range)
 }
 
+const expertIcons = {
+  error: 'fa fa-times-circle',
+  note: 'fa fa-info-circle',
+  warning: 'fa fa-exclamation-triangle',
+}
+
 function mergeOrphanedItems(item) {
   const newChildren: any[] = []
   for (const child of item.children) {
@@ -161,6 +167,26 @@ class LayerItem {
                       ])
                     ])
                   ])
+                ])),
+            layer.experts.map(
+              (expert) => m('li', {
+                class: `expert ${expert.severity}`,
+                onmouseover: () => selectRange(
+                  expert.range[0] < expert.range[1]
+                    ? {
+                      base: addr + expert.range[0],
+                      length: expert.range[1] - expert.range[0],
+                    }
+                    : {
+                      base: addr,
+                      length: layer.data.length,
+                    }),
+                onmouseout: () => selectRange(),
+              }, [
+                  m('i', { class: expertIcons[expert.severity] }),
+                  ' ', expert.message, ' ',
+                  m('span', { class: 'expert-group' },
+                    [genet.session.tokenName(expert.group)])
                 ]))
           ])
         ])
@@ -110,14 +110,19 @@ export default class FrameListView {
     if (sess && status.frames > 0 && this.dummyItem) {
       const frames = (status.filters.main
         ? status.filters.main.frames
-        : status.frames)
+        : status.frames - status.firstFrame)
       if (frames > 0) {
         for (let line = 0; line < this.mapHeight; line += 1) {
           let index = Math.floor(frames / this.mapHeight * (line + 0.5))
           if (status.filters.main) {
             [index] = sess.filteredFrames('main', index, index + 1)
+          } else {
+            index += status.firstFrame
           }
           const [frame] = sess.frames(index, index + 1)
+          if (!frame) {
+            continue
+          }
           this.dummyItem.setAttribute('data-layer', frame.primary.id)
           const [red, green, blue] =
             parseColor(getComputedStyle(this.dummyItem)
@@ -141,7 +146,7 @@ export default class FrameListView {
     const { status } = vnode.attrs.sess
     const frames = status.filters.main
       ? status.filters.main.frames
-      : status.frames
+      : status.frames - status.firstFrame
     const startIndex = Math.floor(this.scrollTop / this.itemHeight)
     const visibleItems = Math.min(
       Math.floor(this.height / this.itemHeight) + 2, frames - startIndex)
@@ -152,7 +157,7 @@ export default class FrameListView {
     const indices = status.filters.main
       ? filteredFrames
       : new Array(visibleItems).fill(0)
-        .map((_val, index) => startIndex + index)
+        .map((_val, index) => status.firstFrame + startIndex + index)
     const items = indices.map((seq, index) => {
       const itemStyle = {
         height: `${this.itemHeight}px`,
@@ -188,7 +193,12 @@ export default class FrameListView {
     const { status } = sess
     const frames = status.filters.main
       ? status.filters.main.frames
-      : status.frames
+      : status.frames - status.firstFrame
+    if (viewState.selectedFrame >= 0 &&
+      viewState.selectedFrame < status.firstFrame) {
+      viewState.selectedFrame = -1
+      genet.action.emit('core:frame:selected', null)
+    }
     if (this.prevFrames !== frames) {
       this.updateMapThrottle(vnode)
       this.prevFrames = frames
@@ -65,9 +65,17 @@ export default class TopView {
     this.viewState.counter = '0'
     if (this.sess) {
       const { status } = this.sess
-      this.viewState.counter = status.filters.main
-        ? `${status.filters.main.frames} / ${status.frames}`
+      const filter = status.filters.main
+      this.viewState.counter = filter
+        ? `${filter.frames} / ${status.frames}`
         : `${status.frames}`
+      if (filter && !filter.complete && filter.total > 0) {
+        const percent = Math.floor(filter.scanned * 100 / filter.total)
+        this.viewState.counter += ` (${percent}%)`
+      }
+      if (status.errorFrames > 0) {
+        this.viewState.counter += ` · ${status.errorFrames} malformed`
+      }
     }
     return [
       m('header', [
@@ -87,6 +95,14 @@ export default class TopView {
             }, [
                 'Reload'
               ]),
+            m('a', {
+              onclick: () => {
+                genet.session.redecode(this.sess)
+                this.showReloadBalloon = false
+              },
+            }, [
+                'Re-decode'
+              ]),
             m('a', {
               onclick: () => {
                 this.showReloadBalloon = false
@@ -152,6 +168,12 @@ export default class TopView {
             title: 'Session Error',
           })
         })
+        sess.on('panic', (decoder, frame) => {
+          genet.notify.show(`${decoder || 'A decoder'} panicked on frame ${frame}`, {
+            type: 'error',
+            title: 'Decoder Crashed',
+          })
+        })
         this.viewState.capture = sess.status.stream
         this.sess = sess
         if (genet.resumer.has('core:filter')) {